@@ -53,6 +53,7 @@ object GameClient {
         socket.close()
         return Result.Err(s"failed to connect to server: $message")
     }
+    socket.sendPacket(NetworkPacket.Subscribe(maxChunksToLoad))
 
     val blockSpecs = BlockSpecs.default
     val blockTextureMapping = BlockTextureLoader.loadBlockTextures(blockSpecs, blockLoader).unwrap()
@@ -517,8 +518,13 @@ class GameClient(
 
   private var tickFut: Option[Future[Seq[Nbt]]] = None
 
+  /** Numbers the sent inputs, so the server can tell the client which of them it has processed */
+  private var inputSeq: Long = 0
+  private var clientTick: Long = 0
+
   def tick(ctx: TickContext): Unit = {
     if isLoggingOut then return
+    clientTick += 1
 
     if shouldOpenChat then {
       shouldOpenChat = false
@@ -534,12 +540,19 @@ class GameClient(
       this.chatMessagesToSend.clear()
     }
 
-    // Act on the server info requested last tick, and send a new request to be used in the next tick
+    // Act on the server info requested last tick, and send a new request to be used in the next tick.
+    // The info is pushed by the server if it can, otherwise it has to be polled.
     val currentTickFut = tickFut
     tickFut = Some(Future {
-      val packets =
-        Seq(NetworkPacket.GetPlayerState, NetworkPacket.GetEvents, NetworkPacket.GetWorldLoadingEvents(maxChunksToLoad))
-      socket.sendMultiplePacketsAndWait(packets)
+      val pushed = socket.takePushes()
+      if pushed.complete && pushed.updates.nonEmpty then {
+        GameClientSocket.combinePushes(pushed.updates)
+      } else {
+        val packets =
+          Seq(NetworkPacket.GetPlayerState, NetworkPacket.GetEvents, NetworkPacket.GetWorldLoadingEvents(maxChunksToLoad))
+        val Seq(playerState, events, worldLoadingEvents) = socket.sendMultiplePacketsAndWait(packets)
+        Seq(playerState, GameClientSocket.withPushedMessages(events, pushed.updates), worldLoadingEvents)
+      }
     })
     if currentTickFut.isEmpty then return // the first tick has no server data to act on
 
@@ -656,6 +669,8 @@ class GameClient(
       val playerCoords = CoordUtils.approximateIntCoords(CylCoords(player.position).toBlockCoords)
 
       val isInLoadedChunk = world.getChunk(playerCoords.getChunkRelWorld).isDefined
+      inputSeq += 1
+      val inputStamp = Some(InputStamp(inputSeq, clientTick))
       val pressedKeys = ctx.keyboard.pressedKeys
       val maxSpeed = playerInputHandler.determineMaxSpeed(pressedKeys)
       if !isPaused && !isInPopup then {
@@ -664,11 +679,11 @@ class GameClient(
 
         playerInputHandler.tick(player, pressedKeys, mouseMovement, maxSpeed, isInFluid)
 
-        socket.sendPacket(NetworkPacket.PlayerMovedMouse(mouseMovement))
-        socket.sendPacket(NetworkPacket.PlayerPressedKeys(pressedKeys))
+        socket.sendPacket(NetworkPacket.PlayerMovedMouse(mouseMovement, inputStamp))
+        socket.sendPacket(NetworkPacket.PlayerPressedKeys(pressedKeys, inputStamp))
       } else {
-        socket.sendPacket(NetworkPacket.PlayerMovedMouse(Vector2f(0, 0)))
-        socket.sendPacket(NetworkPacket.PlayerPressedKeys(Seq()))
+        socket.sendPacket(NetworkPacket.PlayerMovedMouse(Vector2f(0, 0), inputStamp))
+        socket.sendPacket(NetworkPacket.PlayerPressedKeys(Seq(), inputStamp))
       }
 
       if (!isPaused || isOnline) && isInLoadedChunk then {
@@ -3,8 +3,11 @@ package hexacraft.client
 import hexacraft.game.NetworkPacket
 import hexacraft.nbt.Nbt
 
+import scala.collection.mutable
+
 class GameClientSocket(channel: NetworkChannel) {
   private val messageTracker = new MessageTracker
+  private val pushReceiver = new PushReceiver
 
   def sendPacket(packet: NetworkPacket): Unit = {
     messageTracker.trackNotification {
@@ -16,8 +19,7 @@ class GameClientSocket(channel: NetworkChannel) {
     messageTracker.trackRequest {
       channel.send(packet.serialize())
     } {
-      val (_, tag) = Nbt.fromBinary(channel.receive())
-      tag
+      receiveResponse()
     }
   }
 
@@ -28,13 +30,116 @@ class GameClientSocket(channel: NetworkChannel) {
       }
     } {
       for i <- packets.indices yield {
-        val (_, tag) = Nbt.fromBinary(channel.receive())
-        tag
+        receiveResponse()
       }
     }
   }
 
+  /** Returns the updates pushed by the server since the last call, and whether some of them were missed.
+    *
+    * Updates are only pushed after sending `NetworkPacket.Subscribe`, and only by servers supporting it.
+    */
+  def takePushes(): PushedUpdates = {
+    messageTracker.trackRequest(()) {
+      // all earlier requests have got their responses, so anything left is a push
+      var data = channel.tryReceive()
+      while data.isDefined do {
+        val (_, tag) = Nbt.fromBinary(data.get)
+        if !pushReceiver.add(tag) then {
+          println(s"Got unexpected message from server: $tag")
+        }
+        data = channel.tryReceive()
+      }
+      pushReceiver.take()
+    }
+  }
+
+  /** Pushes can arrive at any time, so they are put aside while waiting for a response */
+  private def receiveResponse(): Nbt = {
+    val (_, tag) = Nbt.fromBinary(channel.receive())
+    if pushReceiver.add(tag) then receiveResponse() else tag
+  }
+
   def close(): Unit = {
     channel.close()
   }
 }
+
+/** Updates pushed by the server. If some of them were missed `complete` is false, and the server state has to be
+  * polled. The messages in the updates that did arrive are not sent again.
+  */
+case class PushedUpdates(updates: Seq[Nbt.MapTag], complete: Boolean)
+
+object GameClientSocket {
+
+  /** Combines pushed updates into what the server would have responded to `GetPlayerState`, `GetEvents` and
+    * `GetWorldLoadingEvents`
+    */
+  def combinePushes(pushes: Seq[Nbt.MapTag]): Seq[Nbt] = {
+    def concat(maps: Seq[Nbt.MapTag], name: String): Nbt =
+      Nbt.ListTag(maps.flatMap(_.getList(name).getOrElse(Seq())))
+
+    val player = pushes.last.getMap("player").getOrElse(Nbt.emptyMap)
+
+    val events = pushes.flatMap(_.getMap("events"))
+    val entityEvents = events.flatMap(_.getMap("entity_events"))
+    val combinedEvents = Nbt.makeMap(
+      "block_updates" -> concat(events, "block_updates"),
+      "entity_events" -> Nbt.makeMap(
+        "ids" -> concat(entityEvents, "ids"),
+        "events" -> concat(entityEvents, "events")
+      ),
+      "server_shutting_down" -> Nbt.ByteTag(events.exists(_.getBoolean("server_shutting_down", false))),
+      "messages" -> concat(events, "messages")
+    )
+
+    val worldLoadingEvents = pushes.flatMap(_.getMap("world_loading_events"))
+    val combinedWorldLoadingEvents = Nbt.makeMap(
+      "chunks_loaded" -> concat(worldLoadingEvents, "chunks_loaded"),
+      "chunks_unloaded" -> concat(worldLoadingEvents, "chunks_unloaded")
+    )
+
+    Seq(player, combinedEvents, combinedWorldLoadingEvents)
+  }
+
+  /** Adds the messages of pushed updates in front of the messages in a `GetEvents` response */
+  def withPushedMessages(events: Nbt, pushes: Seq[Nbt.MapTag]): Nbt = {
+    val pushedMessages = pushes.flatMap(_.getMap("events")).flatMap(_.getList("messages").getOrElse(Seq()))
+    events.asMap match {
+      case Some(e) if pushedMessages.nonEmpty =>
+        e.withField("messages", Nbt.ListTag(pushedMessages ++ e.getList("messages").getOrElse(Seq())))
+      case _ =>
+        events
+    }
+  }
+}
+
+/** Collects updates pushed by the server, and notices gaps in their sequence numbers */
+private class PushReceiver {
+  private val received = mutable.ArrayBuffer.empty[Nbt.MapTag]
+  private var lastSeq: Option[Long] = None
+  private var missedSome = false
+
+  /** Stores the message if it is a push, and returns whether it was one */
+  def add(tag: Nbt): Boolean = this.synchronized {
+    tag.asMap.filter(_.vs.size == 1).flatMap(_.getMap("push")) match {
+      case Some(push) =>
+        val seq = push.getLong("seq", -1)
+        if lastSeq.exists(_ + 1 != seq) then {
+          missedSome = true
+        }
+        lastSeq = Some(seq)
+        received += push
+        true
+      case None =>
+        false
+    }
+  }
+
+  def take(): PushedUpdates = this.synchronized {
+    val res = PushedUpdates(received.toSeq, !missedSome)
+    received.clear()
+    missedSome = false
+    res
+  }
+}
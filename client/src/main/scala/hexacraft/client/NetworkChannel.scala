@@ -30,9 +30,18 @@ trait NetworkChannel {
 }
 
 object NetworkChannel {
+  private val ConnectionClosed = 3
+
+  /** The most received messages that can be waiting to be picked up */
+  private val ReceiveQueueSize = 10000
+  /** Stop receiving when the queue is full, which eventually slows the server down */
+  private val BlockWhenFull = 0
+
+  private def newClientId(): String = (new Random().nextInt(1000000) + 1000000).toString
+
   def client(serverIp: String, serverPort: Int): NetworkChannel = new NetworkChannel {
-    private val clientId = (new Random().nextInt(1000000) + 1000000).toString
-    private val socketHandle = RustLib.ClientSocket.create(clientId.getBytes())
+    private val clientId = newClientId()
+    private val socketHandle = RustLib.ClientSocket.create(clientId.getBytes(), ReceiveQueueSize, BlockWhenFull)
     RustLib.ClientSocket.connect(socketHandle, serverIp, serverPort)
 
     private var _isClosed = false
@@ -45,6 +54,18 @@ object NetworkChannel {
       }
     }
 
+    override def receive(): Array[Byte] = {
+      val timeoutMs = 1000
+      if isClosed then {
+        throw new NetworkException("Channel is closed")
+      }
+      RustLib.ClientSocket.receive(socketHandle, timeoutMs) match {
+        case null if isClosed => throw new NetworkException("Channel is closed")
+        case null             => throw new NetworkException(s"Timed out receiving data after $timeoutMs ms")
+        case data             => data
+      }
+    }
+
     override def tryReceive() = {
       try {
         Option(RustLib.ClientSocket.tryReceive(socketHandle))
@@ -58,6 +79,40 @@ object NetworkChannel {
       _isClosed = true
     }
 
+    override def isClosed: Boolean =
+      _isClosed || RustLib.ClientSocket.connectionState(socketHandle) == NetworkChannel.ConnectionClosed
+  }
+
+  /** A channel to a server in the same process, which skips the network entirely.
+    *
+    * @param connect connects a client with the given id to the server, and returns a `RustLib.InProcessClient` handle
+    */
+  def inProcess(connect: Array[Byte] => Long): NetworkChannel = new NetworkChannel {
+    private val clientHandle = connect(newClientId().getBytes())
+
+    private var _isClosed = false
+
+    override def send(data: Array[Byte]): Unit = {
+      try {
+        RustLib.InProcessClient.send(clientHandle, data)
+      } catch {
+        case e: RuntimeException => throw new NetworkException(s"Could not send message: ${e.getMessage}")
+      }
+    }
+
+    override def tryReceive() = {
+      try {
+        Option(RustLib.InProcessClient.tryReceive(clientHandle))
+      } catch {
+        case e: RuntimeException => throw new NetworkException(s"Could not receive message: ${e.getMessage}")
+      }
+    }
+
+    override def close(): Unit = {
+      RustLib.InProcessClient.close(clientHandle)
+      _isClosed = true
+    }
+
     override def isClosed: Boolean = _isClosed
   }
 }
@@ -0,0 +1,21 @@
+package hexacraft.client
+
+import hexacraft.nbt.Nbt
+
+import munit.FunSuite
+
+class GameClientSocketTest extends FunSuite {
+  private def message(text: String): Nbt = Nbt.makeMap("text" -> Nbt.StringTag(text))
+
+  test("messages in pushes that arrived are kept when the events are polled") {
+    val push = Nbt.makeMap(
+      "seq" -> Nbt.LongTag(3),
+      "events" -> Nbt.makeMap("messages" -> Nbt.ListTag(Seq(message("a"))))
+    )
+    val polled = Nbt.makeMap("messages" -> Nbt.ListTag(Seq(message("b"))))
+
+    val events = GameClientSocket.withPushedMessages(polled, Seq(push))
+
+    assertEquals(events.asMap.get.getList("messages"), Some(Seq(message("a"), message("b"))))
+  }
+}
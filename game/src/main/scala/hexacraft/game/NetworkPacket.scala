@@ -11,6 +11,9 @@ import java.util.UUID
 import scala.collection.immutable.ArraySeq
 import scala.util.Try
 
+/** Identifies the input of one client tick, so the client can tell which of its inputs the server has processed */
+case class InputStamp(seq: Long, tick: Long)
+
 case class ServerMessage(
     text: String,
     sender: ServerMessage.Sender
@@ -74,7 +77,8 @@ object ServerMessage {
 }
 
 enum NetworkPacket {
-  case Login(id: UUID, name: String)
+  /** The token is only needed on online servers, where it proves who the player is */
+  case Login(id: UUID, name: String, token: Option[ArraySeq.ofByte] = None)
   case Logout
 
   case GetWorldInfo
@@ -84,13 +88,17 @@ enum NetworkPacket {
   case GetEvents
   case GetWorldLoadingEvents(maxChunksToLoad: Int)
 
+  /** Asks the server to push the player state and events every tick, so they don't have to be polled */
+  case Subscribe(maxChunksToLoad: Int)
+  case Unsubscribe
+
   case PlayerRightClicked
   case PlayerLeftClicked
   case PlayerToggledFlying
   case PlayerSetSelectedItemSlot(slot: Short)
   case PlayerUpdatedInventory(inventory: Inventory)
-  case PlayerMovedMouse(distance: Vector2f)
-  case PlayerPressedKeys(keys: Seq[GameKeyboard.Key])
+  case PlayerMovedMouse(distance: Vector2f, input: Option[InputStamp] = None)
+  case PlayerPressedKeys(keys: Seq[GameKeyboard.Key], input: Option[InputStamp] = None)
 
   case RunCommand(command: String, args: Seq[String])
 }
@@ -105,6 +113,19 @@ object NetworkPacket {
     def serialize(): Array[Byte] = Nbt.encode(p).toBinary()
   }
 
+  private def decodeInputStamp(root: Nbt.MapTag): Option[InputStamp] = {
+    (root.getTag("seq"), root.getTag("tick")) match {
+      case (Some(Nbt.LongTag(seq)), Some(Nbt.LongTag(tick))) => Some(InputStamp(seq, tick))
+      case _                                                   => None
+    }
+  }
+
+  private def encodeInputStamp(tag: Nbt.MapTag, input: Option[InputStamp]): Nbt.MapTag = {
+    tag
+      .withOptionalField("seq", input.map(i => Nbt.LongTag(i.seq)))
+      .withOptionalField("tick", input.map(i => Nbt.LongTag(i.tick)))
+  }
+
   given NbtDecoder[NetworkPacket] with {
     override def decode(tag: Nbt.MapTag): Option[NetworkPacket] = {
       val (packetName, packetDataTag) = tag.vs.head
@@ -114,6 +135,7 @@ object NetworkPacket {
         case "login" =>
           val idBytes = root.getByteArray("id").get
           val name = root.getString("name").get
+          val token = root.getByteArray("token")
 
           if idBytes.length != 16 then {
             throw new RuntimeException("UUIDs must be 16 bytes")
@@ -124,7 +146,7 @@ object NetworkPacket {
           val lsb = bb.getLong
           val id = new UUID(msb, lsb)
 
-          NetworkPacket.Login(id, name)
+          NetworkPacket.Login(id, name, token)
         case "logout" =>
           NetworkPacket.Logout
         case "get_world_info" =>
@@ -139,6 +161,11 @@ object NetworkPacket {
         case "get_world_loading_events" =>
           val maxChunksToLoad = root.getShort("max_chunks", 1)
           NetworkPacket.GetWorldLoadingEvents(maxChunksToLoad)
+        case "subscribe" =>
+          val maxChunksToLoad = root.getShort("max_chunks", 1)
+          NetworkPacket.Subscribe(maxChunksToLoad)
+        case "unsubscribe" =>
+          NetworkPacket.Unsubscribe
         case "right_mouse_clicked" =>
           NetworkPacket.PlayerRightClicked
         case "left_mouse_clicked" =>
@@ -154,11 +181,11 @@ object NetworkPacket {
         case "mouse_moved" =>
           val dx = root.getFloat("dx", 0)
           val dy = root.getFloat("dy", 0)
-          NetworkPacket.PlayerMovedMouse(new Vector2f(dx, dy))
+          NetworkPacket.PlayerMovedMouse(new Vector2f(dx, dy), decodeInputStamp(root))
         case "keys_pressed" =>
           val keyNames = root.getList("keys").get.map(_.asInstanceOf[Nbt.StringTag].v)
           val keys = keyNames.map(GameKeyboard.Key.valueOf)
-          NetworkPacket.PlayerPressedKeys(keys)
+          NetworkPacket.PlayerPressedKeys(keys, decodeInputStamp(root))
         case "run_command" =>
           val commandNbt = root.getMap("command").get
           val name = commandNbt.getString("name").get
@@ -175,39 +202,43 @@ object NetworkPacket {
   given NbtEncoder[NetworkPacket] with {
     override def encode(p: NetworkPacket): Nbt.MapTag = {
       val name: String = p match {
-        case NetworkPacket.Login(_, _)                  => "login"
+        case NetworkPacket.Login(_, _, _)               => "login"
         case NetworkPacket.Logout                       => "logout"
         case NetworkPacket.GetWorldInfo                 => "get_world_info"
         case NetworkPacket.LoadColumnData(_)            => "load_column_data"
         case NetworkPacket.GetPlayerState               => "get_player_state"
         case NetworkPacket.GetEvents                    => "get_events"
         case NetworkPacket.GetWorldLoadingEvents(_)     => "get_world_loading_events"
+        case NetworkPacket.Subscribe(_)                 => "subscribe"
+        case NetworkPacket.Unsubscribe                  => "unsubscribe"
         case NetworkPacket.PlayerRightClicked           => "right_mouse_clicked"
         case NetworkPacket.PlayerLeftClicked            => "left_mouse_clicked"
         case NetworkPacket.PlayerToggledFlying          => "toggle_flying"
         case NetworkPacket.PlayerSetSelectedItemSlot(_) => "set_selected_inventory_slot"
         case NetworkPacket.PlayerUpdatedInventory(_)    => "inventory_updated"
-        case NetworkPacket.PlayerMovedMouse(_)          => "mouse_moved"
-        case NetworkPacket.PlayerPressedKeys(_)         => "keys_pressed"
+        case NetworkPacket.PlayerMovedMouse(_, _)       => "mouse_moved"
+        case NetworkPacket.PlayerPressedKeys(_, _)      => "keys_pressed"
         case NetworkPacket.RunCommand(_, _)             => "run_command"
       }
 
       val tag: Nbt.MapTag = p match {
         case NetworkPacket.Logout | NetworkPacket.GetWorldInfo | NetworkPacket.PlayerRightClicked |
             NetworkPacket.PlayerLeftClicked | NetworkPacket.GetPlayerState | NetworkPacket.PlayerToggledFlying |
-            NetworkPacket.GetEvents =>
+            NetworkPacket.GetEvents | NetworkPacket.Unsubscribe =>
           Nbt.emptyMap
 
-        case NetworkPacket.Login(id, name) =>
+        case NetworkPacket.Login(id, name, token) =>
           val bb = ByteBuffer.allocate(16)
           bb.putLong(id.getMostSignificantBits)
           bb.putLong(id.getLeastSignificantBits)
           val idBytes = ArraySeq.ofByte(bb.array)
 
-          Nbt.makeMap(
-            "id" -> Nbt.ByteArrayTag(idBytes),
-            "name" -> Nbt.StringTag(name)
-          )
+          Nbt
+            .makeMap(
+              "id" -> Nbt.ByteArrayTag(idBytes),
+              "name" -> Nbt.StringTag(name)
+            )
+            .withOptionalField("token", token.map(Nbt.ByteArrayTag(_)))
         case NetworkPacket.LoadColumnData(coords) =>
           Nbt.makeMap(
             "coords" -> Nbt.LongTag(coords.value)
@@ -220,14 +251,20 @@ object NetworkPacket {
           Nbt.makeMap(
             "inventory" -> Nbt.encode(inv)
           )
-        case NetworkPacket.PlayerMovedMouse(dist) =>
-          Nbt.makeMap(
-            "dx" -> Nbt.FloatTag(dist.x),
-            "dy" -> Nbt.FloatTag(dist.y)
+        case NetworkPacket.PlayerMovedMouse(dist, input) =>
+          encodeInputStamp(
+            Nbt.makeMap(
+              "dx" -> Nbt.FloatTag(dist.x),
+              "dy" -> Nbt.FloatTag(dist.y)
+            ),
+            input
           )
-        case NetworkPacket.PlayerPressedKeys(keys) =>
-          Nbt.makeMap(
-            "keys" -> Nbt.ListTag(keys.map(key => Nbt.StringTag(key.toString)))
+        case NetworkPacket.PlayerPressedKeys(keys, input) =>
+          encodeInputStamp(
+            Nbt.makeMap(
+              "keys" -> Nbt.ListTag(keys.map(key => Nbt.StringTag(key.toString)))
+            ),
+            input
           )
         case NetworkPacket.RunCommand(command, args) =>
           Nbt.makeMap(
@@ -241,6 +278,11 @@ object NetworkPacket {
           Nbt.makeMap(
             "max_chunks" -> Nbt.ShortTag(maxChunksToLoad.toShort)
           )
+
+        case NetworkPacket.Subscribe(maxChunksToLoad) =>
+          Nbt.makeMap(
+            "max_chunks" -> Nbt.ShortTag(maxChunksToLoad.toShort)
+          )
       }
 
       Nbt.makeMap(name -> tag)
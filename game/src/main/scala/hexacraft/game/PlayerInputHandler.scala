@@ -1,5 +1,6 @@
 package hexacraft.game
 
+import hexacraft.rs.RustLib
 import hexacraft.world.Player
 
 import org.joml.{Vector2fc, Vector3d, Vector3dc}
@@ -27,7 +28,7 @@ class PlayerInputHandler:
       isInFluid: Boolean
   ): Unit = {
     updateVelocity(pressedKeys, player.velocity, player.rotation, player.flying, maxSpeed, isInFluid)
-    updateRotation(pressedKeys, player.rotation, mouseMovement, 0.05)
+    updateRotation(pressedKeys, player.rotation, mouseMovement)
   }
 
   private def updateVelocity(
@@ -86,54 +87,20 @@ class PlayerInputHandler:
     }
   }
 
+  /** The rotation is calculated by the same code as on the server, so the two never disagree */
   private def updateRotation(
       pressedKeys: Seq[GameKeyboard.Key],
       rotation: Vector3d,
-      mouseMovement: Vector2fc,
-      rSpeed: Float
+      mouseMovement: Vector2fc
   ): Unit = {
-    import GameKeyboard.Key.*
-
-    if pressedKeys.contains(LookUp) then {
-      rotation.x -= rSpeed
-    }
-    if pressedKeys.contains(LookDown) then {
-      rotation.x += rSpeed
-    }
-    if pressedKeys.contains(LookLeft) then {
-      rotation.y -= rSpeed
-    }
-    if pressedKeys.contains(LookRight) then {
-      rotation.y += rSpeed
-    }
-    if pressedKeys.contains(TurnHeadLeft) then {
-      rotation.z -= rSpeed
-    }
-    if pressedKeys.contains(TurnHeadRight) then {
-      rotation.z += rSpeed
-    }
-    if pressedKeys.contains(ResetRotation) then {
-      rotation.set(0, 0, 0)
-    }
-
-    rotation.y += mouseMovement.x * rSpeed * 0.05
-    rotation.x -= mouseMovement.y * rSpeed * 0.05
-
-    if rotation.x < -math.Pi / 2 then {
-      rotation.x = (-math.Pi / 2).toFloat
-    } else if rotation.x > math.Pi / 2 then {
-      rotation.x = (math.Pi / 2).toFloat
-    }
-
-    if rotation.y < 0 then {
-      rotation.y += (math.Pi * 2)
-    } else if rotation.y > math.Pi * 2 then {
-      rotation.y -= (math.Pi * 2)
-    }
-
-    if rotation.z < 0 then {
-      rotation.z += (math.Pi * 2)
-    } else if rotation.z > math.Pi * 2 then {
-      rotation.z -= (math.Pi * 2)
-    }
+    val r = Array(rotation.x, rotation.y, rotation.z)
+    RustLib.PlayerMovement.updateRotation(
+      r,
+      mouseMovement.x,
+      mouseMovement.y,
+      0,
+      0,
+      pressedKeys.map(_.toString).toArray
+    )
+    rotation.set(r(0), r(1), r(2))
   }
@@ -4,6 +4,7 @@ import hexacraft.infra.audio.AudioSystem
 import hexacraft.infra.fs.FileSystem
 import hexacraft.infra.os.OS
 import hexacraft.infra.window.WindowSystem
+import hexacraft.rs.RustLib
 
 import java.io.File
 import java.nio.file.Files
@@ -18,6 +19,11 @@ class Application(
     val saveFolder: File = this.createSaveFolder()
 
     try {
+      RustLib.Logging.init(
+        if config.isDebug then "info,hexacraft_core=debug" else "info",
+        new File(saveFolder, "logs").getAbsolutePath
+      )
+
       var gameIsRunning = true
 
       val gameThread = new Thread(() => {
@@ -38,19 +38,28 @@ object GameScene {
     val maxChunksToLoad = 5
     val renderDistance = 8 * CylinderSize.y60
 
+    // offline games are only played by the player hosting them, so they don't need to go through the network
+    val inProcess = serverParams.isDefined && !c.isOnline
+
     val server = serverParams.map { s =>
-      GameServer.create(
-        c.isOnline,
-        c.serverPort,
-        s.worldInfo,
-        s.worldProvider,
-        renderDistance
-      )
+      if inProcess then {
+        GameServer.createInProcess(c.isOnline, s.worldInfo, s.worldProvider, renderDistance)
+      } else {
+        GameServer.create(
+          c.isOnline,
+          c.serverPort,
+          s.worldInfo,
+          s.worldProvider,
+          renderDistance
+        )
+      }
     }
 
     val client =
       try {
-        val channel = NetworkChannel.client(c.serverIp, c.serverPort)
+        val channel =
+          if inProcess then NetworkChannel.inProcess(server.get.connectInProcess)
+          else NetworkChannel.client(c.serverIp, c.serverPort)
         val (client, clientEvents) = GameClient.create(
           c.playerId,
           c.playerName,
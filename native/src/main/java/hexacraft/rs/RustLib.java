@@ -13,6 +13,19 @@ public class RustLib {
         RustLib.loadNative();
     }
 
+    public static class Logging {
+        static {
+            RustLib.loadNative();
+        }
+
+        /**
+         * Sends the logs of the native code to {@code java.util.logging}, using loggers named like {@code hexacraft_core.server.state}.
+         * The filter is like "info" or "warn,hexacraft_core::zmq=debug". If logDir is not null the logs are also written to files there, one per day.
+         * This can only be called once.
+         */
+        public static native void init(String filter, String logDir) throws RuntimeException;
+    }
+
     public static class NoiseGenerator3D {
         static {
             RustLib.loadNative();
@@ -44,15 +57,43 @@ public class RustLib {
         public static native void destroy(long handle);
     }
 
+    public static class PlayerMovement {
+        static {
+            RustLib.loadNative();
+        }
+
+        /**
+         * Updates the rotation (x, y, z) in place, exactly like the server does every tick.
+         * The look axes are from -1 to 1, and the keys are the names of {@code GameKeyboard.Key}.
+         */
+        public static native void updateRotation(double[] rotation, float mouseDx, float mouseDy, float lookX, float lookY, String[] pressedKeys);
+    }
+
     public static class ClientSocket {
         static {
             RustLib.loadNative();
         }
 
-        public static native long create(byte[] clientId);
+        /**
+         * At most highWaterMark received messages are queued. When the queue is full the overflowPolicy decides what
+         * happens: 0 (block until there is room), 1 (drop the oldest message) or 2 (disconnect)
+         */
+        public static native long create(byte[] clientId, int highWaterMark, int overflowPolicy);
+        /**
+         * If serverKey is null the server key is trusted on first use and saved in the knownServers file.
+         * The receive queue is configured like in create.
+         */
+        public static native long createEncrypted(
+                byte[] clientId, byte[] serverKey, String knownServers, int highWaterMark, int overflowPolicy);
         public static native void connect(long handle, String host, int port) throws RuntimeException;
         public static native void send(long handle, byte[] data) throws RuntimeException;
         public static native byte[] tryReceive(long handle) throws RuntimeException;
+        /** Waits at most timeoutMs milliseconds for data to arrive. Returns null if none arrived. */
+        public static native byte[] receive(long handle, long timeoutMs);
+        /** Returns 0 (connecting), 1 (connected), 2 (reconnecting) or 3 (closed) */
+        public static native int connectionState(long handle);
+        public static native long queueDepth(long handle);
+        public static native long droppedMessages(long handle);
         public static native void close(long handle);
     }
 
@@ -62,18 +103,81 @@ public class RustLib {
         }
 
         public static native long create();
-        public static native void bind(long handle, int port) throws RuntimeException;
+        /** Binds to an endpoint like "tcp://0.0.0.0:1234" and returns the bound port (or -1 if there is none) */
+        public static native int bind(long handle, String endpoint) throws RuntimeException;
         public static native void send(long handle, byte[] clientId, byte[] data) throws RuntimeException;
-        public static native byte[] receive(long handle) throws RuntimeException;
+        /** Returns the client id followed by the frames of the received message */
+        public static native byte[][] receive(long handle) throws RuntimeException;
         public static native void close(long handle);
     }
     
+    /** A server that clients in the same process can connect to without going through the network */
+    public static class InProcessServer {
+        static {
+            RustLib.loadNative();
+        }
+
+        public static native long create();
+        /** Returns a handle to be used with InProcessClient */
+        public static native long connect(long handle, byte[] clientId);
+        public static native void send(long handle, byte[] clientId, byte[] data) throws RuntimeException;
+        /** Returns the client id followed by the frames of the received message */
+        public static native byte[][] receive(long handle) throws RuntimeException;
+        public static native void close(long handle);
+    }
+    public static class InProcessClient {
+        static {
+            RustLib.loadNative();
+        }
+
+        public static native void send(long handle, byte[] data) throws RuntimeException;
+        public static native byte[] tryReceive(long handle) throws RuntimeException;
+        public static native void close(long handle);
+    }
     public static class GameServer {
         static {
             RustLib.loadNative();
         }
         
-        public static native long start(boolean isOnline, int port, String path);
+        /**
+         * The server key is stored in the world folder. Clients can always encrypt, but only have to if requireEncryption is set.
+         * In online mode players are verified using authService, which is either the url of an auth service or the path of a public key file.
+         */
+        public static native long start(boolean isOnline, String endpoint, String path, boolean requireEncryption, String authService) throws RuntimeException;
+        /** Starts a server that only clients in this process can connect to. Use the in-process functions with the handle. */
+        public static native long startInProcess(boolean isOnline, String path, String authService) throws RuntimeException;
+        /** Returns a handle to be used with InProcessClient */
+        public static native long connectInProcess(long handle, byte[] clientId);
+        public static native void stopInProcess(long handle);
+        public static native int localPort(long handle);
+        public static native byte[] publicKey(long handle);
+        /** Announces the server on the local network. If address is null the default multicast group is used. */
+        public static native void announce(long handle, String name, String address) throws RuntimeException;
+        public static native void stop(long handle);
+    }
+
+    public static class World {
+        static {
+            RustLib.loadNative();
+        }
+
+        /**
+         * Opens the world in the given folder, or creates it if it does not exist, and returns its settings like they are stored in world.dat.
+         * The settings are NBT shaped like world.dat where everything is optional, or null for the defaults.
+         * Settings that differ from the ones of an existing world are only applied if changeExisting is set, and are an error otherwise.
+         */
+        public static native byte[] open(String path, byte[] settings, boolean changeExisting) throws RuntimeException;
+    }
+
+    public static class Discovery {
+        static {
+            RustLib.loadNative();
+        }
+
+        /** Listens for servers announcing themselves on an address like "239.255.72.67:34254", or the default one if address is null */
+        public static native long listen(String address) throws RuntimeException;
+        /** Returns the servers seen in the last few seconds as an NBT map with a list named "servers" */
+        public static native byte[] servers(long handle);
         public static native void stop(long handle);
     }
 }
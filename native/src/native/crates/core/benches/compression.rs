@@ -0,0 +1,97 @@
+//! Measures how much chunk data shrinks when compressed, and how long it takes.
+//!
+//! Run with `cargo bench -p hexacraft-core --bench compression`. The sizes are printed before the
+//! timings.
+
+use std::hint::black_box;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use hexacraft_core::{noise_3d, server::nbt, zmq::Compression};
+
+/// A permutation table like the ones the game uses for terrain noise
+fn permutation(seed: u64) -> Vec<i32> {
+    let mut state = seed;
+    let mut perm: Vec<i32> = (0..256).collect();
+    for i in (1..256).rev() {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        perm.swap(i, (state >> 33) as usize % (i + 1));
+    }
+    perm.extend_from_within(..);
+    perm
+}
+
+/// Generates a column of chunks the way they are sent to clients: stone below a noisy surface,
+/// dirt and grass on top, and air above.
+fn generate_column(perms: &[&[i32]], column_x: i32, column_z: i32) -> nbt::Tag {
+    let mut chunks = Vec::new();
+    for chunk_y in -4..4 {
+        let mut blocks = vec![0i8; 16 * 16 * 16];
+        let metadata = vec![0i8; 16 * 16 * 16];
+        for x in 0..16 {
+            for z in 0..16 {
+                let wx = (column_x * 16 + x) as f64;
+                let wz = (column_z * 16 + z) as f64;
+                let height = noise_3d::noise_with_octaves(perms, 0.02, wx, 0.0, wz) * 24.0;
+                for y in 0..16 {
+                    let wy = (chunk_y * 16 + y) as f64;
+                    let depth = height - wy;
+                    let cave = noise_3d::noise_with_octaves(perms, 0.05, wx, wy, wz) > 0.4;
+                    blocks[((x * 16 + y) * 16 + z) as usize] = match depth {
+                        _ if cave => 0,
+                        d if d > 4.0 => 1,
+                        d if d > 1.0 => 2,
+                        d if d > 0.0 => 3,
+                        _ => 0,
+                    };
+                }
+            }
+        }
+        chunks.push(
+            nbt::MapTag::new()
+                .set("blocks", nbt::Tag::ByteArray(blocks))
+                .set("metadata", nbt::Tag::ByteArray(metadata))
+                .set("entities", nbt::Tag::List(Vec::new()))
+                .set("isDecorated", nbt::Tag::Byte(1))
+                .build(),
+        );
+    }
+    nbt::MapTag::new()
+        .set("chunks", nbt::Tag::List(chunks))
+        .build()
+}
+
+fn compression(c: &mut Criterion) {
+    let perms: Vec<Vec<i32>> = (0..4).map(permutation).collect();
+    let perms: Vec<&[i32]> = perms.iter().map(Vec::as_slice).collect();
+    let columns: Vec<Vec<u8>> = (0..16)
+        .map(|i| generate_column(&perms, i % 4, i / 4).to_binary())
+        .collect();
+    let raw_size: usize = columns.iter().map(Vec::len).sum();
+
+    let mut group = c.benchmark_group("compress_column");
+    for level in [1, 3, 9] {
+        let compression = Compression {
+            threshold: 0,
+            level,
+        };
+
+        let compressed_size: usize = columns
+            .iter()
+            .map(|data| compression.compress(data).map_or(data.len(), |c| c.len()))
+            .sum();
+        println!(
+            "zstd level {level}: {raw_size} -> {compressed_size} bytes ({:.1}% saved)",
+            100.0 * (1.0 - compressed_size as f64 / raw_size as f64)
+        );
+
+        group.bench_function(format!("level {level}"), |b| {
+            b.iter(|| compression.compress(black_box(&columns[0])))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, compression);
+criterion_main!(benches);
@@ -0,0 +1,151 @@
+//! Measures how fast the server gets through the requests of many clients.
+//!
+//! Every iteration all clients send their input for one tick and ask for their player state, just
+//! like the game client does, and waits until every client has got its response. The server ticks
+//! in the background meanwhile.
+//!
+//! Both `GameState`, which runs the simulation as a single task fed by commands, and
+//! `MutexGameState`, which shares it behind a mutex like the game state used to, are measured.
+//!
+//! Run with `cargo bench -p hexacraft-core --bench game_state`.
+
+use std::{sync::Arc, time::Duration};
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use hexacraft_core::{
+    server::{GameServer, GameState, WorldInfo, baseline::MutexGameState, nbt},
+    transport::{ClientTransport, InMemoryClient, InMemoryServer},
+};
+use tokio::task::JoinHandle;
+
+fn packet(name: &str, body: nbt::Tag) -> Vec<u8> {
+    nbt::MapTag::new().set(name, body).build().to_binary()
+}
+
+async fn receive(client: &InMemoryClient) -> Vec<u8> {
+    client.receive().await.unwrap()
+}
+
+/// Which game state the server runs
+#[derive(Clone, Copy)]
+enum Design {
+    Actor,
+    Mutex,
+}
+
+impl Design {
+    fn name(self) -> &'static str {
+        match self {
+            Design::Actor => "actor",
+            Design::Mutex => "mutex",
+        }
+    }
+}
+
+/// Starts a server and logs in the given number of clients. The server runs until the returned
+/// tasks are aborted.
+async fn start_server(
+    design: Design,
+    client_count: usize,
+) -> (Vec<InMemoryClient>, Vec<JoinHandle<()>>) {
+    let transport = Arc::new(InMemoryServer::new());
+    let tasks = match design {
+        Design::Actor => {
+            let state = Arc::new(GameState::create(false, WorldInfo::default()));
+            let server = Arc::new(GameServer::new(transport.clone(), state.clone()));
+            vec![
+                tokio::spawn(async move { server.run_receiver().await }),
+                tokio::spawn(async move { state.run_ticks().await }),
+            ]
+        }
+        Design::Mutex => {
+            let state = Arc::new(MutexGameState::new());
+            let server = Arc::new(GameServer::new(transport.clone(), state.clone()));
+            vec![
+                tokio::spawn(async move { server.run_receiver().await }),
+                tokio::spawn(async move { state.run_ticks().await }),
+            ]
+        }
+    };
+
+    let mut clients = Vec::new();
+    for i in 0..client_count {
+        let client = transport.connect(i.to_string().into_bytes());
+        let login = nbt::MapTag::new()
+            .set("id", nbt::Tag::ByteArray(vec![i as i8; 16]))
+            .set("name", nbt::Tag::String(format!("Player {i}")))
+            .build();
+        client.send(packet("login", login)).await.unwrap();
+        receive(&client).await;
+        clients.push(client);
+    }
+    (clients, tasks)
+}
+
+/// Sends the packets the game client sends every tick, and waits for the response
+async fn client_tick(client: &InMemoryClient, seq: i64) {
+    let keys = nbt::MapTag::new()
+        .set(
+            "keys",
+            nbt::Tag::List(vec![nbt::Tag::String("LookLeft".to_string())]),
+        )
+        .set("seq", nbt::Tag::Long(seq))
+        .set("tick", nbt::Tag::Long(seq))
+        .build();
+    let mouse = nbt::MapTag::new()
+        .set("dx", nbt::Tag::Float(3.0))
+        .set("dy", nbt::Tag::Float(-1.0))
+        .set("seq", nbt::Tag::Long(seq))
+        .set("tick", nbt::Tag::Long(seq))
+        .build();
+    client.send(packet("keys_pressed", keys)).await.unwrap();
+    client.send(packet("mouse_moved", mouse)).await.unwrap();
+    client
+        .send(packet("get_player_state", nbt::MapTag::new().build()))
+        .await
+        .unwrap();
+    receive(client).await;
+}
+
+fn many_clients(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("client_tick");
+    group.measurement_time(Duration::from_secs(10));
+    for (design, client_count) in [
+        (Design::Actor, 100),
+        (Design::Mutex, 100),
+        (Design::Actor, 500),
+        (Design::Mutex, 500),
+    ] {
+        let (clients, server_tasks) = runtime.block_on(start_server(design, client_count));
+        let clients = Arc::new(clients);
+
+        let mut seq = 0;
+        let id = BenchmarkId::new(design.name(), client_count);
+        group.bench_function(id, |b| {
+            b.to_async(&runtime).iter(|| {
+                seq += 1;
+                let clients = clients.clone();
+                async move {
+                    let tasks = (0..clients.len()).map(|i| {
+                        let clients = clients.clone();
+                        tokio::spawn(async move { client_tick(&clients[i], seq).await })
+                    });
+                    for task in tasks.collect::<Vec<_>>() {
+                        task.await.unwrap();
+                    }
+                }
+            })
+        });
+
+        // so the next server has the runtime to itself
+        for task in server_tasks {
+            task.abort();
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, many_clients);
+criterion_main!(benches);
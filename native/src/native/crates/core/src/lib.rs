@@ -1,6 +1,8 @@
+pub mod logging;
 pub mod noise_3d;
 pub mod noise_4d;
 pub mod server;
+pub mod transport;
 pub mod vorbis;
 pub mod zmq;
 
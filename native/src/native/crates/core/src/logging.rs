@@ -0,0 +1,309 @@
+//! Where logs go. Everything in this crate logs through `tracing`, and nothing is written
+//! anywhere until a subscriber has been set up, usually by calling `LogConfig::init` once at
+//! startup.
+
+use std::{fmt::Write, fs, io, io::IsTerminal, path::PathBuf, str::FromStr};
+
+use tokio::sync::broadcast;
+use tracing::{Event, Level, Subscriber, field::Field, field::Visit, span};
+use tracing_appender::{non_blocking::WorkerGuard, rolling};
+use tracing_subscriber::{
+    EnvFilter, Layer, Registry, layer::Context, layer::SubscriberExt, registry::LookupSpan,
+    util::SubscriberInitExt,
+};
+
+/// When a new log file is started
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+    Never,
+}
+
+impl FromStr for LogRotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hourly" => Ok(LogRotation::Hourly),
+            "daily" => Ok(LogRotation::Daily),
+            "never" => Ok(LogRotation::Never),
+            _ => Err(format!(
+                "unknown log rotation '{s}', expected hourly, daily or never"
+            )),
+        }
+    }
+}
+
+/// Log files named like `server.2025-01-31.log`, of which only the newest are kept
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFile {
+    pub directory: PathBuf,
+    pub prefix: String,
+    pub rotation: LogRotation,
+    pub max_files: usize,
+}
+
+impl LogFile {
+    pub fn new(directory: impl Into<PathBuf>, prefix: &str) -> Self {
+        LogFile {
+            directory: directory.into(),
+            prefix: prefix.to_string(),
+            rotation: LogRotation::Daily,
+            max_files: 7,
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: LogRotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+}
+
+pub struct LogConfig {
+    /// Which logs to keep, like `info` or `warn,hexacraft_core::zmq=debug`
+    filter: String,
+    stderr: bool,
+    file: Option<LogFile>,
+}
+
+/// Keeps writing to the log file in the background until it is dropped
+pub struct LogGuard {
+    _file: Option<WorkerGuard>,
+}
+
+impl LogConfig {
+    /// Logs to stderr, using the filter syntax of `EnvFilter`
+    pub fn new(filter: &str) -> Self {
+        LogConfig {
+            filter: filter.to_string(),
+            stderr: true,
+            file: None,
+        }
+    }
+
+    pub fn without_stderr(mut self) -> Self {
+        self.stderr = false;
+        self
+    }
+
+    pub fn with_file(mut self, file: LogFile) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    /// Sets up the global subscriber. This can only be done once per process.
+    pub fn init(self) -> Result<LogGuard, String> {
+        self.init_with(None)
+    }
+
+    /// Like `init`, but also sends the logs to the given layer, like a `ForwardLayer`
+    pub fn init_with(
+        self,
+        extra: Option<Box<dyn Layer<Registry> + Send + Sync>>,
+    ) -> Result<LogGuard, String> {
+        let filter = EnvFilter::try_new(&self.filter)
+            .map_err(|err| format!("invalid log filter '{}': {err}", self.filter))?;
+
+        let mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>> = Vec::new();
+        if self.stderr {
+            layers.push(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(io::stderr().is_terminal())
+                    .with_writer(io::stderr)
+                    .boxed(),
+            );
+        }
+        let mut guard = None;
+        if let Some(file) = self.file {
+            let rotation = match file.rotation {
+                LogRotation::Hourly => rolling::Rotation::HOURLY,
+                LogRotation::Daily => rolling::Rotation::DAILY,
+                LogRotation::Never => rolling::Rotation::NEVER,
+            };
+            let open_failed = |err: &dyn std::fmt::Display| {
+                format!(
+                    "failed to open log file in {}: {err}",
+                    file.directory.display()
+                )
+            };
+            fs::create_dir_all(&file.directory).map_err(|err| open_failed(&err))?;
+            let appender = rolling::RollingFileAppender::builder()
+                .rotation(rotation)
+                .filename_prefix(&file.prefix)
+                .filename_suffix("log")
+                .max_log_files(file.max_files)
+                .build(&file.directory)
+                .map_err(|err| open_failed(&err))?;
+            let (writer, file_guard) = tracing_appender::non_blocking(appender);
+            guard = Some(file_guard);
+            layers.push(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(writer)
+                    .boxed(),
+            );
+        }
+        layers.extend(extra);
+
+        tracing_subscriber::registry()
+            .with(layers)
+            .with(filter)
+            .try_init()
+            .map_err(|err| format!("failed to set up logging: {err}"))?;
+        Ok(LogGuard { _file: guard })
+    }
+}
+
+/// Hands every event to a function, formatted like `request{client_id=3}: Got invalid message`.
+/// This is how logs reach loggers outside of Rust.
+pub struct ForwardLayer<F> {
+    forward: F,
+}
+
+impl<F: Fn(Level, &str, String)> ForwardLayer<F> {
+    /// The function gets the level, the target (like `hexacraft_core::zmq`) and the message
+    pub fn new(forward: F) -> Self {
+        ForwardLayer { forward }
+    }
+}
+
+/// Sends every event to a channel as a line like `WARN hexacraft_core::zmq: Got invalid message`,
+/// so the logs can be followed while the program runs. Lines are dropped if nobody follows them.
+pub fn broadcast_layer(
+    lines: broadcast::Sender<String>,
+) -> ForwardLayer<impl Fn(Level, &str, String) + Send + Sync + 'static> {
+    ForwardLayer::new(move |level, target: &str, message| {
+        let _ = lines.send(format!("{level} {target}: {message}"));
+    })
+}
+
+/// The fields of a span, formatted once when the span is created
+struct SpanFields(String);
+
+impl<S, F> Layer<S> for ForwardLayer<F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    F: Fn(Level, &str, String) + 'static,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = FieldFormatter::default();
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(SpanFields(fields.fields));
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut text = String::new();
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                text.push_str(span.name());
+                if let Some(SpanFields(fields)) = span.extensions().get::<SpanFields>()
+                    && !fields.is_empty()
+                {
+                    let _ = write!(text, "{{{fields}}}");
+                }
+                text.push_str(": ");
+            }
+        }
+
+        let mut fields = FieldFormatter::default();
+        event.record(&mut fields);
+        text.push_str(&fields.message);
+        if !fields.fields.is_empty() {
+            let _ = write!(text, " {}", fields.fields);
+        }
+
+        let metadata = event.metadata();
+        (self.forward)(*metadata.level(), metadata.target(), text);
+    }
+}
+
+#[derive(Default)]
+struct FieldFormatter {
+    message: String,
+    /// Every field but the message, like `client_id=3 packet="login"`
+    fields: String,
+}
+
+impl Visit for FieldFormatter {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+            return;
+        }
+        if !self.fields.is_empty() {
+            self.fields.push(' ');
+        }
+        let _ = write!(self.fields, "{}={value:?}", field.name());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::Level;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::{ForwardLayer, LogRotation, broadcast_layer};
+
+    #[test]
+    fn forwarded_events_include_their_spans() {
+        let forwarded = Arc::new(Mutex::new(Vec::new()));
+        let layer = ForwardLayer::new({
+            let forwarded = forwarded.clone();
+            move |level, target: &str, message| {
+                forwarded
+                    .lock()
+                    .unwrap()
+                    .push((level, target.to_string(), message))
+            }
+        });
+        let subscriber = tracing_subscriber::registry().with(layer);
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("request", client_id = 3, packet = "login").entered();
+            tracing::warn!(frames = 2, "Got message with {} frames", 2);
+        });
+
+        assert_eq!(
+            *forwarded.lock().unwrap(),
+            vec![(
+                Level::WARN,
+                "hexacraft_core::logging::tests".to_string(),
+                "request{client_id=3 packet=\"login\"}: Got message with 2 frames frames=2"
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn broadcast_lines_have_the_level_and_target() {
+        let (lines, mut received) = tokio::sync::broadcast::channel(4);
+        let subscriber = tracing_subscriber::registry().with(broadcast_layer(lines));
+
+        tracing::subscriber::with_default(subscriber, || tracing::info!("Server started"));
+
+        assert_eq!(
+            received.try_recv().unwrap(),
+            "INFO hexacraft_core::logging::tests: Server started"
+        );
+    }
+
+    #[test]
+    fn rotation_can_be_parsed() {
+        assert_eq!("daily".parse(), Ok(LogRotation::Daily));
+        assert!("weekly".parse::<LogRotation>().is_err());
+    }
+}
@@ -0,0 +1,235 @@
+//! Verification of the session tokens players log in with on online servers.
+//!
+//! A token is handed out to a player by some authority, and proves which player they are. The
+//! server checks it using a `TokenVerifier`, so a player cannot log in using someone else's id.
+
+use std::{
+    fs, io,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use uuid::Uuid;
+
+/// The player a token was issued for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedPlayer {
+    pub id: Uuid,
+    pub name: String,
+}
+
+pub trait TokenVerifier: Send + Sync {
+    /// Checks that the token is valid and returns the player it belongs to. This is allowed to
+    /// block, since the server calls it on a thread meant for blocking work.
+    fn verify(&self, token: &[u8]) -> Result<VerifiedPlayer, String>;
+}
+
+/// Creates a verifier from a config value. Urls use an `HttpTokenVerifier`, anything else is
+/// taken to be the path of a public key file for a `LocalTokenVerifier`.
+pub fn verifier_from_config(value: &str) -> Result<Arc<dyn TokenVerifier>, String> {
+    if value.starts_with("http://") || value.starts_with("https://") {
+        Ok(Arc::new(HttpTokenVerifier::new(value)))
+    } else {
+        let verifier = LocalTokenVerifier::load(Path::new(value))
+            .map_err(|err| format!("could not load public key from {value}: {err}"))?;
+        Ok(Arc::new(verifier))
+    }
+}
+
+/// Asks an auth service over http. The token is posted as is, and the service is expected to
+/// respond with json like `{"id": "<uuid>", "name": "<name>"}` if the token is valid.
+pub struct HttpTokenVerifier {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl HttpTokenVerifier {
+    pub fn new(url: &str) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(5)))
+            .build()
+            .into();
+        Self {
+            url: url.to_string(),
+            agent,
+        }
+    }
+}
+
+impl TokenVerifier for HttpTokenVerifier {
+    fn verify(&self, token: &[u8]) -> Result<VerifiedPlayer, String> {
+        let body = self
+            .agent
+            .post(&self.url)
+            .content_type("application/octet-stream")
+            .send(token)
+            .and_then(|mut res| res.body_mut().read_to_string())
+            .map_err(|err| match err {
+                ureq::Error::StatusCode(401 | 403) => "token was rejected".to_string(),
+                err => format!("auth service failed: {err}"),
+            })?;
+
+        let json: serde_json::Value =
+            serde_json::from_str(&body).map_err(|_| "auth service sent invalid json")?;
+        let id = json["id"]
+            .as_str()
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .ok_or("auth service sent no valid id")?;
+        let name = json["name"]
+            .as_str()
+            .ok_or("auth service sent no name")?
+            .to_string();
+
+        Ok(VerifiedPlayer { id, name })
+    }
+}
+
+/// Checks tokens signed by a `LocalTokenIssuer`, for testing online mode without an auth service.
+///
+/// A token consists of the player id (16 bytes), the expiry time in unix seconds (8 bytes), the
+/// player name, and finally an ed25519 signature of all that (64 bytes).
+pub struct LocalTokenVerifier {
+    public_key: VerifyingKey,
+}
+
+impl LocalTokenVerifier {
+    pub fn new(public_key: VerifyingKey) -> Self {
+        Self { public_key }
+    }
+
+    /// Loads a public key file, as written by `LocalTokenIssuer::load_or_create`
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes: [u8; 32] = fs::read(path)?
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "key must be 32 bytes"))?;
+        let public_key = VerifyingKey::from_bytes(&bytes)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid public key"))?;
+        Ok(Self { public_key })
+    }
+}
+
+impl TokenVerifier for LocalTokenVerifier {
+    fn verify(&self, token: &[u8]) -> Result<VerifiedPlayer, String> {
+        if token.len() < 16 + 8 + 64 {
+            return Err("token is too short".to_string());
+        }
+        let (payload, signature) = token.split_at(token.len() - 64);
+        let signature = Signature::from_slice(signature).map_err(|_| "invalid signature")?;
+        self.public_key
+            .verify(payload, &signature)
+            .map_err(|_| "token has an invalid signature")?;
+
+        let (id, rest) = payload.split_at(16);
+        let (expires_at, name) = rest.split_at(8);
+        let expires_at = u64::from_be_bytes(expires_at.try_into().unwrap());
+        if unix_time() >= expires_at {
+            return Err("token has expired".to_string());
+        }
+
+        Ok(VerifiedPlayer {
+            id: Uuid::from_slice(id).unwrap(),
+            name: String::from_utf8(name.to_vec()).map_err(|_| "name was not utf8")?,
+        })
+    }
+}
+
+/// Hands out tokens for a `LocalTokenVerifier`
+pub struct LocalTokenIssuer {
+    signing_key: SigningKey,
+}
+
+impl LocalTokenIssuer {
+    /// Loads the secret key at `path`, or creates one if there is none. The public key is stored
+    /// next to it, with `.pub` added to the file name.
+    pub fn load_or_create(path: &Path) -> io::Result<Self> {
+        let secret: [u8; 32] = match fs::read(path) {
+            Ok(bytes) => bytes
+                .try_into()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "key must be 32 bytes"))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let mut secret = [0; 32];
+                getrandom::fill(&mut secret).map_err(|err| io::Error::other(err.to_string()))?;
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(path, secret)?;
+                secret
+            }
+            Err(err) => return Err(err),
+        };
+
+        let issuer = Self {
+            signing_key: SigningKey::from_bytes(&secret),
+        };
+        let mut public_path = path.as_os_str().to_owned();
+        public_path.push(".pub");
+        fs::write(public_path, issuer.public_key().as_bytes())?;
+        Ok(issuer)
+    }
+
+    pub fn from_secret(secret: [u8; 32]) -> Self {
+        Self {
+            signing_key: SigningKey::from_bytes(&secret),
+        }
+    }
+
+    pub fn public_key(&self) -> VerifyingKey {
+        self.signing_key.verifying_key()
+    }
+
+    pub fn issue(&self, id: Uuid, name: &str, valid_for: Duration) -> Vec<u8> {
+        let mut token = Vec::new();
+        token.extend_from_slice(id.as_bytes());
+        token.extend_from_slice(&(unix_time() + valid_for.as_secs()).to_be_bytes());
+        token.extend_from_slice(name.as_bytes());
+        let signature = self.signing_key.sign(&token);
+        token.extend_from_slice(&signature.to_bytes());
+        token
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use uuid::Uuid;
+
+    use super::{LocalTokenIssuer, LocalTokenVerifier, TokenVerifier, VerifiedPlayer};
+
+    #[test]
+    fn local_tokens() {
+        let issuer = LocalTokenIssuer::from_secret([7; 32]);
+        let verifier = LocalTokenVerifier::new(issuer.public_key());
+        let id = Uuid::from_bytes([3; 16]);
+
+        let token = issuer.issue(id, "Alice", Duration::from_secs(60));
+        assert_eq!(
+            verifier.verify(&token),
+            Ok(VerifiedPlayer {
+                id,
+                name: "Alice".to_string()
+            })
+        );
+
+        // changing the id breaks the signature
+        let mut forged = token.clone();
+        forged[0] ^= 1;
+        assert!(verifier.verify(&forged).is_err());
+
+        let expired = issuer.issue(id, "Alice", Duration::ZERO);
+        assert!(verifier.verify(&expired).is_err());
+
+        let other_issuer = LocalTokenIssuer::from_secret([8; 32]);
+        let token = other_issuer.issue(id, "Alice", Duration::from_secs(60));
+        assert!(verifier.verify(&token).is_err());
+    }
+}
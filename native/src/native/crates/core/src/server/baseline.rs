@@ -0,0 +1,118 @@
+//! A game state shared behind a mutex, the way it was before `GameState` became a single task fed
+//! by commands. Requests are handled right in the receiver, and the ticks lock out everything
+//! else while they run.
+//!
+//! It only knows the packets used by `benches/game_state.rs`, which compares the two designs.
+
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use glam::Vec2;
+
+use crate::server::{
+    RequestHandler,
+    input::{self, AnalogInput, PlayerInput},
+    nbt,
+    request::{InputStamp, NetworkPacket},
+    response::{GetPlayerStateResponse, LoginResponse},
+    tick::DEFAULT_TICK_RATE,
+    world::{Inventory, Player},
+};
+
+struct PlayerState {
+    player: Player,
+    mouse_movement: Vec2,
+    pressed_keys: PlayerInput,
+    received_input: Option<InputStamp>,
+    processed_input: Option<InputStamp>,
+}
+
+impl PlayerState {
+    fn input_received(&mut self, input: Option<InputStamp>) {
+        if let Some(input) = input
+            && self.received_input.is_none_or(|r| r.seq < input.seq)
+        {
+            self.received_input = Some(input);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MutexGameState {
+    players: Mutex<HashMap<u64, PlayerState>>,
+}
+
+impl MutexGameState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ticks at the default tick rate until the task is dropped
+    pub async fn run_ticks(&self) {
+        let mut interval = tokio::time::interval(Duration::from_secs(1) / DEFAULT_TICK_RATE.get());
+        loop {
+            interval.tick().await;
+            for p in self.players.lock().unwrap().values_mut() {
+                input::update_player(
+                    &mut p.player,
+                    p.mouse_movement,
+                    p.pressed_keys,
+                    AnalogInput::default(),
+                );
+                p.mouse_movement = Vec2::new(0.0, 0.0);
+                p.processed_input = p.received_input;
+            }
+        }
+    }
+
+    fn handle_now(&self, client_id: u64, packet: NetworkPacket) -> Option<nbt::Tag> {
+        let mut players = self.players.lock().unwrap();
+        match packet {
+            NetworkPacket::Login { id, name, .. } => {
+                players.insert(
+                    client_id,
+                    PlayerState {
+                        player: Player::new(id, name, Inventory::new()),
+                        mouse_movement: Vec2::new(0.0, 0.0),
+                        pressed_keys: PlayerInput::empty(),
+                        received_input: None,
+                        processed_input: None,
+                    },
+                );
+                Some(LoginResponse::success().into())
+            }
+            NetworkPacket::GetPlayerState => {
+                let p = players.get(&client_id)?;
+                Some(
+                    GetPlayerStateResponse {
+                        player: &p.player,
+                        last_processed_input: p.processed_input,
+                    }
+                    .into(),
+                )
+            }
+            NetworkPacket::PlayerMovedMouse { distance: d, input } => {
+                let p = players.get_mut(&client_id)?;
+                p.mouse_movement = Vec2::new(p.mouse_movement.x + d.x, p.mouse_movement.y + d.y);
+                p.input_received(input);
+                None
+            }
+            NetworkPacket::PlayerPressedKeys { keys, input } => {
+                let p = players.get_mut(&client_id)?;
+                p.pressed_keys = keys;
+                p.input_received(input);
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+impl RequestHandler for MutexGameState {
+    fn handle(
+        &self,
+        client_id: u64,
+        packet: NetworkPacket,
+    ) -> impl Future<Output = Option<nbt::Tag>> + Send {
+        std::future::ready(self.handle_now(client_id, packet))
+    }
+}
@@ -0,0 +1,315 @@
+//! Finding servers on the local network. Servers announce themselves over UDP every few seconds,
+//! and clients listen for the announcements to show the servers that are currently visible.
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+use crate::server::nbt;
+
+/// The port announcements are sent to by default
+pub const DISCOVERY_PORT: u16 = 34254;
+
+/// The multicast group announcements are sent to by default. It is in the organization-local
+/// scope, so routers do not forward it.
+pub const DISCOVERY_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 72, 67);
+
+/// The name of the root tag of an announcement, so other traffic on the port is ignored
+const ANNOUNCEMENT_TAG: &str = "hexacraft_server";
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Servers that have not been heard from for this long are assumed to be gone
+const SERVER_TIMEOUT: Duration = Duration::from_secs(6);
+
+/// Longer names are cut off, so that an announcement always fits in one datagram
+const MAX_NAME_LENGTH: usize = 100;
+const MAX_ANNOUNCEMENT_SIZE: usize = 1024;
+
+/// Where announcements go if nothing else is configured
+pub fn default_address() -> SocketAddr {
+    SocketAddr::new(DISCOVERY_GROUP.into(), DISCOVERY_PORT)
+}
+
+/// A server as it announces itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct Announcement {
+    pub name: String,
+    /// The port the game server listens on, which is not the port of the announcements
+    pub port: u16,
+    pub protocol_version: u16,
+    pub players: u32,
+}
+
+impl Announcement {
+    fn encode(&self) -> Vec<u8> {
+        nbt::MapTag::new()
+            .set("name", nbt::Tag::String(self.name.clone()))
+            .set("port", nbt::Tag::Short(self.port as i16))
+            .set(
+                "protocol_version",
+                nbt::Tag::Short(self.protocol_version as i16),
+            )
+            .set("players", nbt::Tag::Int(self.players as i32))
+            .build()
+            .to_named_binary(ANNOUNCEMENT_TAG)
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, String> {
+        let (name, tag) = nbt::Tag::from_binary(data)?;
+        if name != ANNOUNCEMENT_TAG {
+            return Err(format!("not an announcement: {name}"));
+        }
+        let tag = match tag {
+            nbt::Tag::Map(items) => items.into_iter().collect::<HashMap<_, _>>(),
+            _ => return Err("announcement was not a map tag")?,
+        };
+
+        Ok(Announcement {
+            name: match tag.get("name").ok_or("missing name field")? {
+                nbt::Tag::String(v) => v.clone(),
+                _ => return Err("wrong type for name field")?,
+            },
+            port: match tag.get("port").ok_or("missing port field")? {
+                nbt::Tag::Short(v) => *v as u16,
+                _ => return Err("wrong type for port field")?,
+            },
+            protocol_version: match tag
+                .get("protocol_version")
+                .ok_or("missing protocol_version field")?
+            {
+                nbt::Tag::Short(v) => *v as u16,
+                _ => return Err("wrong type for protocol_version field")?,
+            },
+            players: match tag.get("players").ok_or("missing players field")? {
+                nbt::Tag::Int(v) => *v as u32,
+                _ => return Err("wrong type for players field")?,
+            },
+        })
+    }
+}
+
+/// Sends announcements for a server, see `GameServer::run_announcer`
+pub struct Announcer {
+    socket: UdpSocket,
+    address: SocketAddr,
+    pub(crate) name: String,
+    pub(crate) interval: Duration,
+}
+
+impl Announcer {
+    /// Prepares to announce a server with the given name to an address, usually
+    /// `default_address()`. Broadcast addresses like `255.255.255.255` work as well.
+    pub fn bind(name: &str, address: SocketAddr) -> io::Result<Self> {
+        let local = match address {
+            SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+            SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+        };
+        let socket = Socket::new(Domain::for_address(local), Type::DGRAM, Some(Protocol::UDP))?;
+        if address.is_ipv4() {
+            socket.set_broadcast(true)?;
+            // announcements should not leave the local network
+            socket.set_multicast_ttl_v4(1)?;
+            // and should reach clients on the same machine
+            socket.set_multicast_loop_v4(true)?;
+        }
+        socket.set_nonblocking(true)?;
+        socket.bind(&local.into())?;
+
+        Ok(Announcer {
+            socket: UdpSocket::from_std(socket.into())?,
+            address,
+            name: name.chars().take(MAX_NAME_LENGTH).collect(),
+            interval: DEFAULT_INTERVAL,
+        })
+    }
+
+    /// Sets how often the server is announced, every 2 seconds by default
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub(crate) async fn send(&self, announcement: &Announcement) {
+        if let Err(err) = self
+            .socket
+            .send_to(&announcement.encode(), self.address)
+            .await
+        {
+            debug!(%err, address = %self.address, "Failed to send announcement");
+        }
+    }
+}
+
+/// A server found by a `DiscoveryListener`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredServer {
+    /// The address the announcement came from
+    pub host: IpAddr,
+    pub announcement: Announcement,
+}
+
+/// Listens for announcements and remembers the servers that sent them
+pub struct DiscoveryListener {
+    socket: UdpSocket,
+    servers: Mutex<HashMap<(IpAddr, u16), (Announcement, Instant)>>,
+    cancel_token: CancellationToken,
+}
+
+impl DiscoveryListener {
+    /// Listens on an address, usually `default_address()`. Multicast groups are joined, and other
+    /// programs on the same machine can listen on the same port at the same time.
+    pub fn bind(address: SocketAddr) -> io::Result<Self> {
+        let socket = Socket::new(
+            Domain::for_address(address),
+            Type::DGRAM,
+            Some(Protocol::UDP),
+        )?;
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        match address.ip() {
+            IpAddr::V4(group) if group.is_multicast() => {
+                let local = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), address.port());
+                socket.bind(&local.into())?;
+                socket.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)?;
+            }
+            IpAddr::V6(group) if group.is_multicast() => {
+                let local = SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), address.port());
+                socket.bind(&local.into())?;
+                socket.join_multicast_v6(&group, 0)?;
+            }
+            _ => socket.bind(&address.into())?,
+        }
+
+        Ok(DiscoveryListener {
+            socket: UdpSocket::from_std(socket.into())?,
+            servers: Mutex::new(HashMap::new()),
+            cancel_token: CancellationToken::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Receives announcements until the listener is closed
+    pub async fn run(&self) {
+        let mut buf = [0; MAX_ANNOUNCEMENT_SIZE];
+        loop {
+            let (len, sender) = tokio::select! {
+                res = self.socket.recv_from(&mut buf) => match res {
+                    Ok(received) => received,
+                    Err(err) => {
+                        debug!(%err, "Failed to receive announcement");
+                        continue;
+                    }
+                },
+                _ = self.cancel_token.cancelled() => return,
+            };
+            match Announcement::decode(&buf[..len]) {
+                Ok(announcement) => {
+                    let key = (sender.ip(), announcement.port);
+                    let mut servers = self.servers.lock().unwrap();
+                    servers.insert(key, (announcement, Instant::now()));
+                }
+                Err(err) => debug!(%sender, err, "Got invalid announcement"),
+            }
+        }
+    }
+
+    /// The servers that have announced themselves recently, sorted by name
+    pub fn servers(&self) -> Vec<DiscoveredServer> {
+        let mut servers = self.servers.lock().unwrap();
+        servers.retain(|_, (_, last_seen)| last_seen.elapsed() < SERVER_TIMEOUT);
+
+        let mut found = servers
+            .iter()
+            .map(|(&(host, _), (announcement, _))| DiscoveredServer {
+                host,
+                announcement: announcement.clone(),
+            })
+            .collect::<Vec<_>>();
+        found.sort_by(|a, b| {
+            (&a.announcement.name, a.host, a.announcement.port).cmp(&(
+                &b.announcement.name,
+                b.host,
+                b.announcement.port,
+            ))
+        });
+        found
+    }
+
+    /// Stops `run`
+    pub fn close(&self) {
+        self.cancel_token.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, sync::Arc, time::Duration};
+
+    use tokio::net::UdpSocket;
+
+    use crate::server::{GameServer, GameState, PROTOCOL_VERSION, WorldInfo};
+    use crate::zmq::endpoint_port;
+
+    use super::{Announcer, DiscoveryListener};
+
+    #[tokio::test]
+    async fn servers_are_discovered_over_loopback() {
+        let listener = Arc::new(DiscoveryListener::bind("127.0.0.1:0".parse().unwrap()).unwrap());
+        let listener_addr = listener.local_addr().unwrap();
+        tokio::spawn({
+            let listener = listener.clone();
+            async move { listener.run().await }
+        });
+
+        // other traffic on the port is ignored
+        let other = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        other.send_to(b"hello", listener_addr).await.unwrap();
+
+        let state = Arc::new(GameState::create(false, WorldInfo::default()));
+        let server = Arc::new(
+            GameServer::start("tcp://127.0.0.1:0", state.clone())
+                .await
+                .unwrap(),
+        );
+        let port = endpoint_port(server.local_endpoint().unwrap()).unwrap();
+        let announcer = Announcer::bind("Alice's world", listener_addr)
+            .unwrap()
+            .with_interval(Duration::from_millis(10));
+        tokio::spawn(async move { server.run_announcer(announcer).await });
+
+        let servers = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                let servers = listener.servers();
+                if !servers.is_empty() {
+                    return servers;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("server was not discovered");
+
+        assert_eq!(servers.len(), 1);
+        let server = &servers[0];
+        assert_eq!(server.host, "127.0.0.1".parse::<IpAddr>().unwrap());
+        assert_eq!(server.announcement.name, "Alice's world");
+        assert_eq!(server.announcement.port, port);
+        assert_eq!(server.announcement.protocol_version, PROTOCOL_VERSION);
+        assert_eq!(server.announcement.players, 0);
+
+        listener.close();
+    }
+}
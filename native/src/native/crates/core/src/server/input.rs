@@ -1,41 +1,126 @@
 use std::f64::consts::PI;
 
+use bitflags::bitflags;
 use glam::{DVec3, Vec2};
 
-use crate::server::world::{Player, };
+use crate::server::world::Player;
 
-pub fn update_player(player: &mut Player, mouse_movement: Vec2, pressed_keys: &[&str]) {
-    update_rotation(&mut player.rotation, mouse_movement, pressed_keys, 0.05);
+/// How fast the player turns when the look keys are held
+const ROTATION_SPEED: f32 = 0.05;
+
+bitflags! {
+    /// The keys a player is holding down. The names match `GameKeyboard.Key` on the client.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct PlayerInput: u16 {
+        const MOVE_FORWARD = 1 << 0;
+        const MOVE_BACKWARD = 1 << 1;
+        const MOVE_LEFT = 1 << 2;
+        const MOVE_RIGHT = 1 << 3;
+
+        const JUMP = 1 << 4;
+        const SNEAK = 1 << 5;
+
+        const LOOK_UP = 1 << 6;
+        const LOOK_DOWN = 1 << 7;
+        const LOOK_LEFT = 1 << 8;
+        const LOOK_RIGHT = 1 << 9;
+        const TURN_HEAD_LEFT = 1 << 10;
+        const TURN_HEAD_RIGHT = 1 << 11;
+
+        const MOVE_SLOWLY = 1 << 12;
+        const MOVE_FAST = 1 << 13;
+        const MOVE_SUPER_FAST = 1 << 14;
+
+        const RESET_ROTATION = 1 << 15;
+    }
+}
+
+impl PlayerInput {
+    const NAMES: [(&'static str, PlayerInput); 16] = [
+        ("MoveForward", Self::MOVE_FORWARD),
+        ("MoveBackward", Self::MOVE_BACKWARD),
+        ("MoveLeft", Self::MOVE_LEFT),
+        ("MoveRight", Self::MOVE_RIGHT),
+        ("Jump", Self::JUMP),
+        ("Sneak", Self::SNEAK),
+        ("LookUp", Self::LOOK_UP),
+        ("LookDown", Self::LOOK_DOWN),
+        ("LookLeft", Self::LOOK_LEFT),
+        ("LookRight", Self::LOOK_RIGHT),
+        ("TurnHeadLeft", Self::TURN_HEAD_LEFT),
+        ("TurnHeadRight", Self::TURN_HEAD_RIGHT),
+        ("MoveSlowly", Self::MOVE_SLOWLY),
+        ("MoveFast", Self::MOVE_FAST),
+        ("MoveSuperFast", Self::MOVE_SUPER_FAST),
+        ("ResetRotation", Self::RESET_ROTATION),
+    ];
+
+    /// Parses key names like "LookUp". Unknown names are an error rather than being ignored.
+    pub fn from_key_names<'a>(names: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        names.into_iter().try_fold(Self::empty(), |keys, name| {
+            let (_, key) = Self::NAMES
+                .iter()
+                .find(|(n, _)| *n == name)
+                .ok_or_else(|| format!("unknown key: {name}"))?;
+            Ok(keys | *key)
+        })
+    }
+}
+
+/// Input that is not just on or off, like the sticks of a gamepad
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AnalogInput {
+    /// How far the look stick is pushed, from -1 to 1 along each axis. Fully pushed it turns the
+    /// player as fast as the look keys.
+    pub look: Vec2,
+}
+
+pub fn update_player(
+    player: &mut Player,
+    mouse_movement: Vec2,
+    pressed_keys: PlayerInput,
+    analog: AnalogInput,
+) {
+    update_rotation(&mut player.rotation, mouse_movement, pressed_keys, analog);
 }
 
-fn update_rotation(
+/// Turns the player according to the input of one tick.
+///
+/// The client calls this through JNI when predicting its own movement, so that it ends up exactly
+/// where the server will put it.
+pub fn update_rotation(
     rotation: &mut DVec3,
     mouse_movement: Vec2,
-    pressed_keys: &[&str],
-    r_speed: f32,
+    pressed_keys: PlayerInput,
+    analog: AnalogInput,
 ) {
-    if pressed_keys.contains(&"LookUp") {
+    let r_speed = ROTATION_SPEED;
+
+    if pressed_keys.contains(PlayerInput::LOOK_UP) {
         rotation[0] -= r_speed as f64;
     }
-    if pressed_keys.contains(&"LookDown") {
+    if pressed_keys.contains(PlayerInput::LOOK_DOWN) {
         rotation[0] += r_speed as f64;
     }
-    if pressed_keys.contains(&"LookLeft") {
+    if pressed_keys.contains(PlayerInput::LOOK_LEFT) {
         rotation[1] -= r_speed as f64;
     }
-    if pressed_keys.contains(&"LookRight") {
+    if pressed_keys.contains(PlayerInput::LOOK_RIGHT) {
         rotation[1] += r_speed as f64;
     }
-    if pressed_keys.contains(&"TurnHeadLeft") {
+    if pressed_keys.contains(PlayerInput::TURN_HEAD_LEFT) {
         rotation[2] -= r_speed as f64;
     }
-    if pressed_keys.contains(&"TurnHeadRight") {
+    if pressed_keys.contains(PlayerInput::TURN_HEAD_RIGHT) {
         rotation[2] += r_speed as f64;
     }
-    if pressed_keys.contains(&"ResetRotation") {
+    if pressed_keys.contains(PlayerInput::RESET_ROTATION) {
         *rotation = DVec3::ZERO;
     }
 
+    rotation[1] += (analog.look.x * r_speed) as f64;
+    rotation[0] -= (analog.look.y * r_speed) as f64;
+
     rotation[1] += (mouse_movement.x * r_speed * 0.05) as f64;
     rotation[0] -= (mouse_movement.y * r_speed * 0.05) as f64;
 
@@ -53,3 +138,18 @@ fn update_rotation(
         rotation[2] -= PI * 2.0;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::PlayerInput;
+
+    #[test]
+    fn key_names_are_parsed_strictly() {
+        assert_eq!(
+            PlayerInput::from_key_names(["LookUp", "Jump"]),
+            Ok(PlayerInput::LOOK_UP | PlayerInput::JUMP)
+        );
+        assert_eq!(PlayerInput::from_key_names([]), Ok(PlayerInput::empty()));
+        assert!(PlayerInput::from_key_names(["LookUp", "lookup"]).is_err());
+    }
+}
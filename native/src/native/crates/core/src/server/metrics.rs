@@ -0,0 +1,261 @@
+//! Counters for keeping an eye on a running server, in the Prometheus text format.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    io,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::debug;
+
+use crate::server::tick::HISTOGRAM_BUCKETS;
+
+/// Scrapers that take longer than this to send their request are disconnected
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// Everything is updated as it happens, so reading the metrics never waits for the simulation
+#[derive(Default)]
+pub struct Metrics {
+    pub(crate) players: AtomicU64,
+    /// Packets received per type, like `login`
+    packets: Mutex<BTreeMap<&'static str, u64>>,
+    pub(crate) decode_errors: AtomicU64,
+    /// Bytes of the messages handled by the server, after decryption and decompression
+    pub(crate) bytes_received: AtomicU64,
+    pub(crate) bytes_sent: AtomicU64,
+    /// Ticks per bucket of `HISTOGRAM_BUCKETS`, followed by the slower ones
+    tick_buckets: [AtomicU64; HISTOGRAM_BUCKETS.len() + 1],
+    tick_nanos: AtomicU64,
+    pub(crate) chunk_loads: AtomicU64,
+    /// Commands waiting for the simulation
+    pub(crate) queued_commands: AtomicU64,
+    /// Updates waiting to be pushed to clients
+    pub(crate) queued_pushes: AtomicU64,
+    /// Requests that have been handled, but whose responses have not been sent yet
+    pub(crate) pending_responses: AtomicU64,
+}
+
+impl Metrics {
+    pub(crate) fn packet_received(&self, name: &'static str) {
+        *self.packets.lock().unwrap().entry(name).or_default() += 1;
+    }
+
+    pub(crate) fn packets_received(&self) -> u64 {
+        self.packets.lock().unwrap().values().sum()
+    }
+
+    pub(crate) fn tick_finished(&self, duration: Duration) {
+        let bucket = HISTOGRAM_BUCKETS
+            .iter()
+            .position(|&bound| duration <= bound)
+            .unwrap_or(HISTOGRAM_BUCKETS.len());
+        self.tick_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.tick_nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// The metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let get = |value: &AtomicU64| value.load(Ordering::Relaxed);
+
+        gauge(
+            &mut out,
+            "hexacraft_players",
+            "Players online",
+            get(&self.players),
+        );
+
+        header(
+            &mut out,
+            "hexacraft_packets_received_total",
+            "counter",
+            "Packets received, per type",
+        );
+        for (name, count) in self.packets.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "hexacraft_packets_received_total{{type=\"{name}\"}} {count}"
+            );
+        }
+
+        counter(
+            &mut out,
+            "hexacraft_decode_errors_total",
+            "Messages that could not be decoded",
+            get(&self.decode_errors),
+        );
+        counter(
+            &mut out,
+            "hexacraft_received_bytes_total",
+            "Bytes received in messages",
+            get(&self.bytes_received),
+        );
+        counter(
+            &mut out,
+            "hexacraft_sent_bytes_total",
+            "Bytes sent in responses and pushes",
+            get(&self.bytes_sent),
+        );
+
+        header(
+            &mut out,
+            "hexacraft_tick_duration_seconds",
+            "histogram",
+            "How long ticks take",
+        );
+        let mut ticks = 0;
+        for (bound, count) in HISTOGRAM_BUCKETS.iter().zip(&self.tick_buckets) {
+            ticks += get(count);
+            let _ = writeln!(
+                out,
+                "hexacraft_tick_duration_seconds_bucket{{le=\"{}\"}} {ticks}",
+                bound.as_secs_f64()
+            );
+        }
+        ticks += get(&self.tick_buckets[HISTOGRAM_BUCKETS.len()]);
+        let _ = writeln!(
+            out,
+            "hexacraft_tick_duration_seconds_bucket{{le=\"+Inf\"}} {ticks}"
+        );
+        let _ = writeln!(
+            out,
+            "hexacraft_tick_duration_seconds_sum {}",
+            Duration::from_nanos(get(&self.tick_nanos)).as_secs_f64()
+        );
+        let _ = writeln!(out, "hexacraft_tick_duration_seconds_count {ticks}");
+
+        counter(
+            &mut out,
+            "hexacraft_chunk_loads_total",
+            "Chunk columns loaded for clients",
+            get(&self.chunk_loads),
+        );
+        gauge(
+            &mut out,
+            "hexacraft_queued_commands",
+            "Commands waiting for the simulation",
+            get(&self.queued_commands),
+        );
+        gauge(
+            &mut out,
+            "hexacraft_queued_pushes",
+            "Updates waiting to be pushed to clients",
+            get(&self.queued_pushes),
+        );
+        gauge(
+            &mut out,
+            "hexacraft_pending_responses",
+            "Responses waiting to be sent",
+            get(&self.pending_responses),
+        );
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, "counter", help);
+    let _ = writeln!(out, "{name} {value}");
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, "gauge", help);
+    let _ = writeln!(out, "{name} {value}");
+}
+
+/// Answers `GET /metrics` over HTTP, for Prometheus to scrape
+pub async fn serve(listener: TcpListener, metrics: Arc<Metrics>) {
+    loop {
+        let Ok((stream, _)) = listener.accept().await else {
+            continue;
+        };
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            if let Err(err) = respond(stream, &metrics).await {
+                debug!(%err, "Failed to serve metrics");
+            }
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    let mut request = Vec::new();
+    let read_request = async {
+        let mut buf = [0; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            let len = stream.read(&mut buf).await?;
+            if len == 0 || request.len() + len > MAX_REQUEST_SIZE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "incomplete request",
+                ));
+            }
+            request.extend_from_slice(&buf[..len]);
+        }
+        Ok(())
+    };
+    tokio::time::timeout(REQUEST_TIMEOUT, read_request).await??;
+
+    let (status, body) = if request.starts_with(b"GET /metrics ") {
+        ("200 OK", metrics.render())
+    } else {
+        ("404 Not Found", "Not found\n".to_string())
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::Metrics;
+
+    #[tokio::test]
+    async fn metrics_are_served_over_http() {
+        let metrics = Arc::new(Metrics::default());
+        metrics.packet_received("login");
+        metrics.packet_received("login");
+        metrics.tick_finished(Duration::from_millis(3));
+        metrics.tick_finished(Duration::from_secs(1));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(super::serve(listener, metrics));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\nhexacraft_packets_received_total{type=\"login\"} 2\n"));
+        assert!(response.contains("\nhexacraft_tick_duration_seconds_bucket{le=\"0.002\"} 0\n"));
+        assert!(response.contains("\nhexacraft_tick_duration_seconds_bucket{le=\"0.005\"} 1\n"));
+        assert!(response.contains("\nhexacraft_tick_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(response.contains("\nhexacraft_tick_duration_seconds_sum 1.003\n"));
+    }
+}
@@ -0,0 +1,361 @@
+//! Upgrading worlds saved by older versions of the game, like the `MigrationManager` of the game.
+//!
+//! Every change to the save format gets a new version and a step that upgrades worlds from the
+//! version before it. The version is saved after every step, so a migration that was interrupted
+//! continues where it stopped. The steps are written so that running them again does no harm.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use tracing::{info, warn};
+
+use crate::server::{
+    nbt,
+    world::WorldInfo,
+    world_config::{read_world_file, write_world_file},
+};
+
+/// The version of worlds without a version in their `world.dat`, which were saved before
+/// versions were introduced
+const UNVERSIONED: u16 = 1;
+
+/// The directory next to the world that backups are put in. The game lists every directory in
+/// its saves folder that has a `world.dat`, so backups cannot be put right next to the world.
+const BACKUP_DIR: &str = "backups";
+
+/// Upgrades a world from version `from` to `from + 1`
+struct Migration {
+    from: u16,
+    description: &'static str,
+    apply: fn(&Path) -> Result<(), String>,
+}
+
+/// Every migration, in order
+const MIGRATIONS: &[Migration] = &[Migration {
+    from: 1,
+    description: "move the chunks into columns",
+    apply: migrate_from_v1,
+}];
+
+/// The version of the world in a directory, or `None` if there is no world there yet
+pub fn detect_version(dir: &Path) -> Result<Option<u16>, String> {
+    match read_world_file(dir)? {
+        None => Ok(None),
+        Some((_, tag)) => Ok(Some(version_of(&tag)?)),
+    }
+}
+
+fn version_of(tag: &nbt::Tag) -> Result<u16, String> {
+    let nbt::Tag::Map(items) = tag else {
+        return Err("world info was not a map tag".to_string());
+    };
+    match items.iter().find(|(name, _)| name == "version") {
+        None => Ok(UNVERSIONED),
+        Some((_, nbt::Tag::Short(v))) => Ok(*v as u16),
+        Some(_) => Err("wrong type for version field".to_string()),
+    }
+}
+
+fn with_version(tag: nbt::Tag, version: u16) -> nbt::Tag {
+    let nbt::Tag::Map(mut items) = tag else {
+        return tag;
+    };
+    let version_tag = nbt::Tag::Short(version as i16);
+    match items.iter_mut().find(|(name, _)| name == "version") {
+        Some((_, v)) => *v = version_tag,
+        None => items.insert(0, ("version".to_string(), version_tag)),
+    }
+    nbt::Tag::Map(items)
+}
+
+/// Upgrades the world in a directory to `WorldInfo::LATEST_VERSION` if it is older, after
+/// copying it to the `BACKUP_DIR` next to it. Returns the version the world had.
+///
+/// Worlds from newer versions of the game are refused, since they could be damaged by opening
+/// them. Directories without a world are left alone.
+pub fn migrate_if_needed(dir: &Path) -> Result<Option<u16>, String> {
+    let Some((root_name, mut tag)) = read_world_file(dir)? else {
+        return Ok(None);
+    };
+    let version = version_of(&tag)?;
+
+    if version > WorldInfo::LATEST_VERSION {
+        return Err(format!(
+            "the world in {} was saved by a newer version of the game. The latest supported \
+             version is {}, but the world has version {version}",
+            dir.display(),
+            WorldInfo::LATEST_VERSION
+        ));
+    }
+    if version == WorldInfo::LATEST_VERSION {
+        return Ok(Some(version));
+    }
+
+    let backup = backup_world(dir, version)?;
+    info!(
+        "Upgrading the world in {} from version {version} to {}, with a backup in {}",
+        dir.display(),
+        WorldInfo::LATEST_VERSION,
+        backup.display()
+    );
+
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= version) {
+        info!(
+            "Upgrading to version {}: {}",
+            migration.from + 1,
+            migration.description
+        );
+        (migration.apply)(dir).map_err(|err| {
+            format!(
+                "failed to upgrade the world in {} to version {}: {err}",
+                dir.display(),
+                migration.from + 1
+            )
+        })?;
+        tag = with_version(tag, migration.from + 1);
+        write_world_file(dir, &root_name, &tag)?;
+    }
+    Ok(Some(version))
+}
+
+/// Copies the world to a directory like `backups/world-v1` next to it, unless that was already
+/// done by a migration that was interrupted
+fn backup_world(dir: &Path, version: u16) -> Result<PathBuf, String> {
+    let dir = dir
+        .canonicalize()
+        .map_err(|err| format!("failed to find {}: {err}", dir.display()))?;
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "world".to_string());
+    let backup_dir = dir.with_file_name(BACKUP_DIR);
+    if backup_dir == dir {
+        return Err(format!(
+            "cannot back up {}, since backups are put in a directory with the same name",
+            dir.display()
+        ));
+    }
+    let backup = backup_dir.join(format!("{name}-v{version}"));
+    if backup.exists() {
+        return Ok(backup);
+    }
+
+    // the backup is copied under another name first, so a partial copy is never taken for a backup
+    let partial = backup_dir.join(format!("{name}-v{version}.partial"));
+    let backup_failed = |err: io::Error| format!("failed to back up {}: {err}", dir.display());
+    if partial.exists() {
+        fs::remove_dir_all(&partial).map_err(backup_failed)?;
+    }
+    copy_dir(&dir, &partial).map_err(backup_failed)?;
+    fs::rename(&partial, &backup).map_err(backup_failed)?;
+    Ok(backup)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Chunks used to be stored in `chunks/<coords>.dat`, and are now stored in
+/// `data/<column coords>/<y>.dat`. Chunks that have already been moved are left alone.
+fn migrate_from_v1(dir: &Path) -> Result<(), String> {
+    let old_chunks_dir = dir.join("chunks");
+    if !old_chunks_dir.is_dir() {
+        return Ok(());
+    }
+
+    let entries = fs::read_dir(&old_chunks_dir)
+        .map_err(|err| format!("failed to read {}: {err}", old_chunks_dir.display()))?;
+    for entry in entries {
+        let file = entry
+            .map_err(|err| format!("failed to read {}: {err}", old_chunks_dir.display()))?
+            .path();
+        if !file.is_file() {
+            continue;
+        }
+        let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+        let coords = file_name
+            .split_once('.')
+            .and_then(|(coords, _)| coords.parse::<i64>().ok());
+        // other files are not chunks, so they are ignored
+        let Some(coords) = coords else {
+            continue;
+        };
+
+        let to = dir
+            .join("data")
+            .join((coords >> 12).to_string())
+            .join(format!("{}.dat", coords & 0xfff));
+        if to.exists() {
+            continue;
+        }
+        let moved = fs::create_dir_all(to.parent().unwrap()).and_then(|_| fs::rename(&file, &to));
+        if let Err(err) = moved {
+            warn!(
+                "Failed to move {} to {}: {err}",
+                file.display(),
+                to.display()
+            );
+        }
+    }
+
+    // chunks that could not be moved are kept
+    let _ = fs::remove_dir(old_chunks_dir);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use crate::server::{
+        world::WorldInfo,
+        world_config::{read_world_file, write_world_file},
+    };
+
+    use super::{
+        MIGRATIONS, UNVERSIONED, copy_dir, detect_version, migrate_if_needed, with_version,
+    };
+
+    /// A copy of a world in `testdata/worlds`, named `world` in a directory of its own so there
+    /// is room for the backup
+    fn copy_fixture(fixture: &str, name: &str) -> PathBuf {
+        let parent =
+            std::env::temp_dir().join(format!("hexacraft-migration-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&parent);
+        let dir = parent.join("world");
+        copy_dir(&fixture_dir(fixture), &dir).unwrap();
+        dir
+    }
+
+    fn fixture_dir(fixture: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/worlds")
+            .join(fixture)
+    }
+
+    fn remove_test_dir(dir: &Path) {
+        let _ = fs::remove_dir_all(dir.parent().unwrap());
+    }
+
+    fn read_file(path: &Path) -> Vec<u8> {
+        fs::read(path).unwrap()
+    }
+
+    #[test]
+    fn migrations_cover_every_version() {
+        let versions = MIGRATIONS.iter().map(|m| m.from).collect::<Vec<_>>();
+        assert_eq!(
+            versions,
+            (UNVERSIONED..WorldInfo::LATEST_VERSION).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn v1_worlds_get_their_chunks_moved() {
+        let dir = copy_fixture("v1", "v1");
+        let fixture = fixture_dir("v1");
+
+        assert_eq!(detect_version(&dir).unwrap(), Some(1));
+        assert_eq!(migrate_if_needed(&dir).unwrap(), Some(1));
+
+        assert_eq!(
+            read_file(&dir.join("data/5/7.dat")),
+            read_file(&fixture.join("chunks/20487.dat"))
+        );
+        assert_eq!(
+            read_file(&dir.join("data/-1/4095.dat")),
+            read_file(&fixture.join("chunks/-1.dat"))
+        );
+        assert!(!dir.join("chunks").exists());
+        assert_eq!(detect_version(&dir).unwrap(), Some(2));
+
+        // the rest of world.dat is kept
+        let (_, original) = read_world_file(&fixture).unwrap().unwrap();
+        let (root_name, tag) = read_world_file(&dir).unwrap().unwrap();
+        assert_eq!(root_name, "");
+        assert_eq!(tag, with_version(original, 2));
+
+        // the backup is the world as it was, where the game does not take it for another world
+        let backup = dir.with_file_name("backups").join("world-v1");
+        assert_eq!(
+            read_file(&backup.join("world.dat")),
+            read_file(&fixture.join("world.dat"))
+        );
+        assert_eq!(
+            read_file(&backup.join("chunks/20487.dat")),
+            read_file(&fixture.join("chunks/20487.dat"))
+        );
+        for entry in fs::read_dir(dir.parent().unwrap()).unwrap() {
+            let path = entry.unwrap().path();
+            assert!(path == dir || !path.join("world.dat").exists(), "{path:?}");
+        }
+
+        remove_test_dir(&dir);
+    }
+
+    #[test]
+    fn interrupted_v1_migrations_can_be_run_again() {
+        let dir = copy_fixture("v1", "v1-interrupted");
+        // this chunk was moved before the migration stopped, so the old copy is ignored
+        fs::create_dir_all(dir.join("data/5")).unwrap();
+        fs::write(dir.join("data/5/7.dat"), "moved").unwrap();
+        fs::write(dir.join("chunks/notes.txt"), "not a chunk").unwrap();
+
+        migrate_if_needed(&dir).unwrap();
+        assert_eq!(read_file(&dir.join("data/5/7.dat")), b"moved");
+        assert!(dir.join("data/-1/4095.dat").exists());
+        assert!(dir.join("chunks/20487.dat").exists());
+        assert!(dir.join("chunks/notes.txt").exists());
+
+        // running it again changes nothing
+        assert_eq!(
+            migrate_if_needed(&dir).unwrap(),
+            Some(WorldInfo::LATEST_VERSION)
+        );
+        assert_eq!(read_file(&dir.join("data/5/7.dat")), b"moved");
+
+        remove_test_dir(&dir);
+    }
+
+    #[test]
+    fn latest_and_newer_worlds_are_not_migrated() {
+        let dir = copy_fixture("v2", "v2");
+        assert_eq!(migrate_if_needed(&dir).unwrap(), Some(2));
+        assert_eq!(
+            read_file(&dir.join("world.dat")),
+            read_file(&fixture_dir("v2").join("world.dat"))
+        );
+        assert!(!dir.with_file_name("backups").exists());
+        remove_test_dir(&dir);
+
+        let dir = copy_fixture("v2", "v3");
+        let (root_name, tag) = read_world_file(&dir).unwrap().unwrap();
+        write_world_file(&dir, &root_name, &with_version(tag, 3)).unwrap();
+        assert!(migrate_if_needed(&dir).is_err());
+        assert_eq!(detect_version(&dir).unwrap(), Some(3));
+        remove_test_dir(&dir);
+
+        let dir = std::env::temp_dir().join(format!(
+            "hexacraft-migration-empty-{}/world",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(migrate_if_needed(&dir).unwrap(), None);
+        remove_test_dir(&dir);
+    }
+}
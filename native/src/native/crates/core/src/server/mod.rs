@@ -1,19 +1,108 @@
+use crate::server::discovery::{Announcement, Announcer};
+use crate::server::metrics::Metrics;
 use crate::server::request::NetworkPacket;
-use crate::zmq::ServerSocket;
+use crate::transport::{InMemoryClient, InMemoryServer, PeerEvent, PeerId, Transport};
+use crate::zmq::{Endpoint, ServerSocket, endpoint_port};
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, StreamExt};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
 use std::time::Duration;
+use tracing::{debug, error, info_span, warn};
+use zeromq::{ZmqError, ZmqResult};
 
-pub use state::GameState;
+pub use state::{DEFAULT_MOTD, GameState};
+pub use status::{ServerStatus, query_status};
+pub use world::{CylinderSize, WorldGenSettings, WorldInfo};
 
-mod input;
-mod nbt;
+pub mod auth;
+#[cfg(feature = "mutex-baseline")]
+pub mod baseline;
+pub mod discovery;
+pub mod input;
+pub mod metrics;
+pub mod migration;
+pub mod nbt;
 mod request;
 mod response;
 mod state;
+mod status;
+pub mod tick;
 mod world;
+pub mod world_config;
+
+/// Changes whenever clients and servers can no longer understand each other, so that clients can
+/// tell which servers they can join
+pub const PROTOCOL_VERSION: u16 = 1;
 
 pub trait RequestHandler {
-    fn handle(&self, client_id: u64, packet: NetworkPacket) -> Option<nbt::Tag>;
+    /// Handles a packet from a client, and returns the response to send back if there is one.
+    ///
+    /// The packet has to be taken care of before this returns, so that packets are handled in the
+    /// order they arrived. The returned future only waits for the response, which lets the server
+    /// receive more packets in the meantime.
+    fn handle(
+        &self,
+        client_id: u64,
+        packet: NetworkPacket,
+    ) -> impl Future<Output = Option<nbt::Tag>> + Send;
+
+    fn client_connected(&self, _client_id: u64) {}
+
+    /// Called when the connection to a client has been lost without the client logging out
+    fn client_disconnected(&self, _client_id: u64) {}
+
+    /// Waits for the next message that should be pushed to a client without it asking, and
+    /// returns it together with the id of that client. By default nothing is ever pushed.
+    fn next_push(&self) -> impl Future<Output = (u64, nbt::Tag)> + Send {
+        std::future::pending()
+    }
+
+    /// Where the server records what goes over the network, if anywhere
+    fn network_metrics(&self) -> Option<&Metrics> {
+        None
+    }
+}
+
+/// Keeps track of the requests of a client, so that the responses can be sent in the same order
+#[derive(Default)]
+struct ResponseOrder {
+    /// The number given to the next request
+    next_request: u64,
+    /// The number of the request that should be answered next
+    next_response: u64,
+    /// Responses that are done, but have to wait for an earlier one
+    waiting: BTreeMap<u64, Option<nbt::Tag>>,
+}
+
+impl ResponseOrder {
+    /// Returns the number of a new request
+    fn start(&mut self) -> u64 {
+        self.next_request += 1;
+        self.next_request - 1
+    }
+
+    /// Records the response to a request, and returns the responses that can be sent now
+    fn finish(&mut self, request: u64, response: Option<nbt::Tag>) -> Vec<nbt::Tag> {
+        let mut ready = Vec::new();
+        if request != self.next_response {
+            self.waiting.insert(request, response);
+            return ready;
+        }
+        ready.extend(response);
+        self.next_response += 1;
+        while let Some(response) = self.waiting.remove(&self.next_response) {
+            ready.extend(response);
+            self.next_response += 1;
+        }
+        ready
+    }
+
+    /// Whether every request has been answered
+    fn is_done(&self) -> bool {
+        self.next_response == self.next_request
+    }
 }
 
 pub trait GracefulShutdown {
@@ -21,23 +110,64 @@ pub trait GracefulShutdown {
     fn done(&self) -> bool;
 }
 
-pub struct GameServer<H> {
-    socket: Arc<ServerSocket>,
+pub struct GameServer<H, T = ServerSocket> {
+    socket: Arc<T>,
     handler: Arc<H>,
+    local_endpoint: Option<Endpoint>,
 }
 
 impl<H> GameServer<H> {
-    pub async fn start(port: u16, handler: Arc<H>) -> Self {
-        let socket = ServerSocket::new();
-        socket.bind(port).await.unwrap();
-        Self {
+    /// Starts a server listening on an endpoint, like `tcp://0.0.0.0:1234`.
+    /// See `ServerSocket::bind` for more examples.
+    pub async fn start(endpoint: &str, handler: Arc<H>) -> ZmqResult<Self> {
+        Self::start_with_socket(ServerSocket::new(), endpoint, handler).await
+    }
+
+    /// Like `start`, but with a socket that has already been configured, like one with encryption
+    /// or compression. Responses are then compressed by the socket when they are large enough.
+    pub async fn start_with_socket(
+        socket: ServerSocket,
+        endpoint: &str,
+        handler: Arc<H>,
+    ) -> ZmqResult<Self> {
+        let local_endpoint = socket.bind(endpoint).await?;
+        Ok(Self {
             socket: Arc::new(socket),
             handler,
+            local_endpoint: Some(local_endpoint),
+        })
+    }
+
+    /// The public key clients can use to verify the server, if encryption is enabled
+    pub fn public_key(&self) -> Option<&[u8]> {
+        self.socket.public_key()
+    }
+}
+
+impl<H, T> GameServer<H, T> {
+    /// Creates a server using an already set up transport, like an `InMemoryServer`
+    pub fn new(socket: Arc<T>, handler: Arc<H>) -> Self {
+        Self {
+            socket,
+            handler,
+            local_endpoint: None,
         }
     }
+
+    /// The endpoint the server is listening on, with the real port if it was started on port 0
+    pub fn local_endpoint(&self) -> Option<&Endpoint> {
+        self.local_endpoint.as_ref()
+    }
+}
+
+impl<H> GameServer<H, InMemoryServer> {
+    /// Connects a client in the same process, see `InMemoryServer::connect`
+    pub fn connect(&self, client_id: Vec<u8>) -> InMemoryClient {
+        self.socket.connect(client_id)
+    }
 }
 
-impl<H: GracefulShutdown> GameServer<H> {
+impl<H: GracefulShutdown, T> GameServer<H, T> {
     pub async fn shutdown(&self) {
         self.handler.initiate();
         loop {
@@ -49,23 +179,181 @@ impl<H: GracefulShutdown> GameServer<H> {
     }
 }
 
-impl<H: RequestHandler> GameServer<H> {
+impl<H: RequestHandler + GracefulShutdown, T> GameServer<H, T> {
+    /// Announces the server on the local network until it has shut down, so that players nearby
+    /// can find it. Only servers listening on a port can be announced.
+    pub async fn run_announcer(&self, announcer: Announcer) {
+        let Some(port) = self.local_endpoint().and_then(endpoint_port) else {
+            warn!("Not announcing the server, since it is not listening on a port");
+            return;
+        };
+
+        let mut interval = tokio::time::interval(announcer.interval);
+        while !self.handler.done() {
+            interval.tick().await;
+            let players = self
+                .handler
+                .network_metrics()
+                .map_or(0, |metrics| metrics.players.load(Ordering::Relaxed));
+            let announcement = Announcement {
+                name: announcer.name.clone(),
+                port,
+                protocol_version: PROTOCOL_VERSION,
+                players: players as u32,
+            };
+            announcer.send(&announcement).await;
+        }
+    }
+}
+
+impl<H: RequestHandler, T: Transport> GameServer<H, T> {
+    /// Makes `run_receiver` return, after which no more messages are received or sent
+    pub fn cancel(&self) {
+        self.socket.cancel();
+    }
+
     pub async fn run_receiver(&self) {
+        let mut peer_events_closed = false;
+        // responses to a client are sent in the order its requests arrived, but a slow response
+        // does not hold up the ones to other clients
+        let mut responses = FuturesUnordered::new();
+        let mut response_orders: HashMap<PeerId, ResponseOrder> = HashMap::new();
         loop {
-            let client_id_bytes = self.socket.receive().await.unwrap();
-            let message = self.socket.receive().await.unwrap();
+            // the receive is restarted after every push or response, since a `ServerSocket` cannot
+            // send while it is receiving
+            let received = tokio::select! {
+                res = self.socket.receive_message() => res,
+                Some(first) = responses.next(), if !responses.is_empty() => {
+                    // many responses are often ready at once, so they are all sent before
+                    // receiving again
+                    let mut next: Option<(PeerId, u64, Option<nbt::Tag>)> = Some(first);
+                    while let Some((peer_id, seq, response)) = next {
+                        // the order is kept until all of its requests have been answered
+                        let order = response_orders.get_mut(&peer_id).unwrap();
+                        let ready = order.finish(seq, response);
+                        if order.is_done() {
+                            response_orders.remove(&peer_id);
+                        }
+                        for response in ready {
+                            self.send_response(peer_id.clone(), response).await;
+                        }
+                        next = responses.next().now_or_never().flatten();
+                    }
+                    self.set_pending_responses(responses.len());
+                    continue;
+                }
+                (client_id, push) = self.handler.next_push() => {
+                    self.send_push(client_id, push).await;
+                    continue;
+                }
+                event = self.socket.next_peer_event(), if !peer_events_closed => {
+                    match event {
+                        Some(event) => self.handle_peer_event(event),
+                        None => peer_events_closed = true,
+                    }
+                    continue;
+                }
+            };
+            let (peer_id, frames) = match received {
+                Ok(received) => received,
+                // the transport has been closed
+                Err(ZmqError::Other("cancelled")) => return,
+                Err(err) => {
+                    error!(%err, "Failed to receive from clients, the server stops receiving");
+                    return;
+                }
+            };
+            let metrics = self.handler.network_metrics();
+            if let Some(metrics) = metrics {
+                let len: usize = frames.iter().map(|frame| frame.len()).sum();
+                metrics
+                    .bytes_received
+                    .fetch_add(len as u64, Ordering::Relaxed);
+            }
 
-            match decode_request(&client_id_bytes, &message) {
-                Err(err) => eprintln!("{err}"),
+            let [message] = frames.as_slice() else {
+                warn!(
+                    peer = ?peer_id,
+                    frames = frames.len(),
+                    "Got message with the wrong number of frames, expected 1"
+                );
+                if let Some(metrics) = metrics {
+                    metrics.decode_errors.fetch_add(1, Ordering::Relaxed);
+                }
+                continue;
+            };
+
+            match decode_request(peer_id.as_bytes(), message) {
+                Err(err) => {
+                    warn!(peer = ?peer_id, "{err}");
+                    if let Some(metrics) = metrics {
+                        metrics.decode_errors.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
                 Ok((client_id, packet)) => {
-                    if let Some(response) = self.handler.handle(client_id, packet) {
-                        self.socket
-                            .send(client_id_bytes, response.to_binary())
-                            .await
-                            .unwrap();
+                    if let Some(metrics) = metrics {
+                        metrics.packet_received(packet.name());
                     }
+                    // the handler can pick up the span to handle the packet in it
+                    let span = info_span!("request", client_id, packet = packet.name());
+                    let response = span.in_scope(|| self.handler.handle(client_id, packet));
+
+                    let seq = response_orders.entry(peer_id.clone()).or_default().start();
+                    responses.push(async move { (peer_id, seq, response.await) });
                 }
             }
+            self.set_pending_responses(responses.len());
+        }
+    }
+
+    fn set_pending_responses(&self, count: usize) {
+        if let Some(metrics) = self.handler.network_metrics() {
+            metrics
+                .pending_responses
+                .store(count as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn record_sent(&self, data: &[u8]) {
+        if let Some(metrics) = self.handler.network_metrics() {
+            metrics
+                .bytes_sent
+                .fetch_add(data.len() as u64, Ordering::Relaxed);
+        }
+    }
+
+    async fn send_response(&self, peer_id: PeerId, response: nbt::Tag) {
+        let data = response.to_binary();
+        self.record_sent(&data);
+        if let Err(err) = self.socket.send(peer_id.clone(), data).await {
+            // the client left before getting its response, so it is treated like a lost connection
+            debug!(peer = ?peer_id, %err, "Failed to send response to client");
+            self.handle_peer_event(PeerEvent::Disconnected(peer_id));
+        }
+    }
+
+    async fn send_push(&self, client_id: u64, push: nbt::Tag) {
+        let peer_id = PeerId::from(client_id.to_string().into_bytes());
+        let data = push.to_binary();
+        self.record_sent(&data);
+        if let Err(err) = self.socket.send(peer_id, data).await {
+            // the client is probably gone, which will be noticed elsewhere
+            debug!(client_id, %err, "Failed to push update to client");
+        }
+    }
+
+    fn handle_peer_event(&self, event: PeerEvent) {
+        let (PeerEvent::Connected(peer_id) | PeerEvent::Disconnected(peer_id)) = &event;
+        let client_id = match decode_client_id(peer_id.as_bytes()) {
+            Ok(client_id) => client_id,
+            Err(err) => {
+                warn!(peer = ?peer_id, "Got invalid client id: {err}");
+                return;
+            }
+        };
+        match event {
+            PeerEvent::Connected(_) => self.handler.client_connected(client_id),
+            PeerEvent::Disconnected(_) => self.handler.client_disconnected(client_id),
         }
     }
 }
@@ -104,3 +392,517 @@ fn decode_message(message_bytes: &[u8]) -> Result<NetworkPacket, String> {
 
     Ok(packet)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use tokio::sync::Notify;
+    use uuid::Uuid;
+
+    use crate::{
+        server::{
+            GameServer, GameState, GracefulShutdown, RequestHandler, ResponseOrder, WorldInfo,
+            auth::{LocalTokenIssuer, LocalTokenVerifier, TokenVerifier, VerifiedPlayer},
+            nbt,
+            request::NetworkPacket,
+        },
+        transport::{ClientTransport, InMemoryClient, InMemoryServer},
+    };
+
+    #[test]
+    fn responses_wait_for_earlier_ones_to_the_same_client() {
+        let mut order = ResponseOrder::default();
+        let (first, second, third) = (order.start(), order.start(), order.start());
+
+        assert_eq!(order.finish(second, Some(nbt::Tag::Byte(2))), vec![]);
+        assert_eq!(order.finish(third, None), vec![]);
+        assert!(!order.is_done());
+        assert_eq!(
+            order.finish(first, Some(nbt::Tag::Byte(1))),
+            vec![nbt::Tag::Byte(1), nbt::Tag::Byte(2)]
+        );
+        assert!(order.is_done());
+    }
+
+    /// Runs a server for the game state, with clients connecting through the returned transport
+    fn start_server(state: Arc<GameState>) -> Arc<InMemoryServer> {
+        let transport = Arc::new(InMemoryServer::new());
+        let server = Arc::new(GameServer::new(transport.clone(), state.clone()));
+        tokio::spawn(async move { server.run_receiver().await });
+        tokio::spawn(async move { state.run_ticks().await });
+        transport
+    }
+
+    async fn request(client: &InMemoryClient, name: &str, body: nbt::Tag) -> nbt::Tag {
+        notify(client, name, body).await;
+        receive(client).await
+    }
+
+    async fn notify(client: &InMemoryClient, name: &str, body: nbt::Tag) {
+        let packet = nbt::MapTag::new().set(name, body).build();
+        client.send(packet.to_binary()).await.unwrap();
+    }
+
+    async fn receive(client: &InMemoryClient) -> nbt::Tag {
+        let response = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                if let Some(data) = client.try_receive().await {
+                    return data;
+                }
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("timed out waiting for response");
+
+        nbt::Tag::from_binary(&response).unwrap().1
+    }
+
+    /// Answers every request with an empty map, but only once it has been released
+    struct DelayedHandler {
+        release: Arc<Notify>,
+    }
+
+    impl RequestHandler for DelayedHandler {
+        fn handle(
+            &self,
+            _client_id: u64,
+            _packet: NetworkPacket,
+        ) -> impl Future<Output = Option<nbt::Tag>> + Send {
+            let release = self.release.clone();
+            async move {
+                release.notified().await;
+                Some(nbt::MapTag::new().build())
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn clients_leaving_before_their_response_do_not_stop_the_server() {
+        let release = Arc::new(Notify::new());
+        let transport = Arc::new(InMemoryServer::new());
+        let handler = Arc::new(DelayedHandler {
+            release: release.clone(),
+        });
+        let server = Arc::new(GameServer::new(transport.clone(), handler));
+        let receiver = tokio::spawn(async move { server.run_receiver().await });
+
+        let alice = transport.connect(b"1".to_vec());
+        notify(&alice, "get_world_info", nbt::MapTag::new().build()).await;
+        drop(alice);
+        // give the server time to receive the request before the response is released
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        release.notify_one();
+
+        let bob = transport.connect(b"2".to_vec());
+        notify(&bob, "get_world_info", nbt::MapTag::new().build()).await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        release.notify_one();
+        receive(&bob).await;
+        assert!(!receiver.is_finished());
+    }
+
+    #[tokio::test]
+    async fn login_through_in_memory_transport() {
+        let state = Arc::new(GameState::create(false, WorldInfo::default()));
+        let transport = start_server(state.clone());
+
+        let client = transport.connect(b"123".to_vec());
+
+        let login = nbt::MapTag::new()
+            .set("id", nbt::Tag::ByteArray(vec![7; 16]))
+            .set("name", nbt::Tag::String("Alice".to_string()))
+            .build();
+        let response = request(&client, "login", login).await;
+
+        let nbt::Tag::Map(fields) = response else {
+            panic!("expected a map, got {response:?}");
+        };
+        assert_eq!(fields, vec![("success".to_string(), nbt::Tag::Byte(1))]);
+    }
+
+    #[tokio::test]
+    async fn player_is_removed_when_connection_is_lost() {
+        let state = Arc::new(GameState::create(false, WorldInfo::default()));
+        let transport = start_server(state.clone());
+
+        let client = transport.connect(b"123".to_vec());
+        let login = nbt::MapTag::new()
+            .set("id", nbt::Tag::ByteArray(vec![7; 16]))
+            .set("name", nbt::Tag::String("Alice".to_string()))
+            .build();
+        request(&client, "login", login).await;
+        assert_eq!(state.player_count().await, 1);
+
+        drop(client);
+
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while state.player_count().await != 0 {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("player was not removed");
+    }
+
+    #[tokio::test]
+    async fn online_login_requires_a_valid_token() {
+        let issuer = LocalTokenIssuer::from_secret([1; 32]);
+        let verifier = Arc::new(LocalTokenVerifier::new(issuer.public_key()));
+
+        let state =
+            Arc::new(GameState::create(true, WorldInfo::default()).with_token_verifier(verifier));
+        let transport = start_server(state.clone());
+
+        let client = transport.connect(b"123".to_vec());
+        let login = |id: i8, token: Option<Vec<u8>>| {
+            nbt::MapTag::new()
+                .set("id", nbt::Tag::ByteArray(vec![id; 16]))
+                .set("name", nbt::Tag::String("Mallory".to_string()))
+                .set_opt(
+                    "token",
+                    token.map(|t| nbt::Tag::ByteArray(t.into_iter().map(|b| b as i8).collect())),
+                )
+                .build()
+        };
+        let succeeded = |response: nbt::Tag| match response {
+            nbt::Tag::Map(fields) => fields.contains(&("success".to_string(), nbt::Tag::Byte(1))),
+            _ => panic!("expected a map, got {response:?}"),
+        };
+
+        let alice = Uuid::from_bytes([7; 16]);
+        let token = issuer.issue(alice, "Alice", Duration::from_secs(60));
+
+        assert!(!succeeded(request(&client, "login", login(7, None)).await));
+        assert!(!succeeded(
+            request(&client, "login", login(8, Some(token.clone()))).await
+        ));
+        assert_eq!(state.player_count().await, 0);
+
+        assert!(succeeded(
+            request(&client, "login", login(7, Some(token))).await
+        ));
+        assert_eq!(state.player_count().await, 1);
+    }
+
+    /// Accepts every token as Bob, except for `slow`, which takes a long time to be rejected
+    struct SlowVerifier;
+
+    impl TokenVerifier for SlowVerifier {
+        fn verify(&self, token: &[u8]) -> Result<VerifiedPlayer, String> {
+            if token == b"slow" {
+                std::thread::sleep(Duration::from_millis(500));
+                return Err("too slow".to_string());
+            }
+            Ok(VerifiedPlayer {
+                id: Uuid::from_bytes([7; 16]),
+                name: "Bob".to_string(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_logins_do_not_hold_up_other_clients() {
+        let state = Arc::new(
+            GameState::create(true, WorldInfo::default())
+                .with_token_verifier(Arc::new(SlowVerifier)),
+        );
+        let transport = start_server(state.clone());
+
+        let login = |id: i8, token: &[u8]| {
+            nbt::MapTag::new()
+                .set("id", nbt::Tag::ByteArray(vec![id; 16]))
+                .set("name", nbt::Tag::String("Someone".to_string()))
+                .set(
+                    "token",
+                    nbt::Tag::ByteArray(token.iter().map(|&b| b as i8).collect()),
+                )
+                .build()
+        };
+        let bob = transport.connect(b"1".to_vec());
+        request(&bob, "login", login(7, b"bob")).await;
+        let subscribe = nbt::MapTag::new()
+            .set("max_chunks", nbt::Tag::Short(4))
+            .build();
+        notify(&bob, "subscribe", subscribe).await;
+        request(&bob, "get_player_state", nbt::MapTag::new().build()).await;
+        while bob.try_receive().await.is_some() {}
+
+        let mallory = transport.connect(b"2".to_vec());
+        notify(&mallory, "login", login(8, b"slow")).await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        // Bob keeps getting pushes and responses while Mallory's token is being checked
+        let start = Instant::now();
+        receive(&bob).await;
+        notify(&bob, "get_player_state", nbt::MapTag::new().build()).await;
+        loop {
+            let nbt::Tag::Map(fields) = receive(&bob).await else {
+                panic!("expected a map");
+            };
+            if !fields.iter().any(|(name, _)| name == "push") {
+                break;
+            }
+        }
+        assert!(start.elapsed() < Duration::from_millis(200));
+        assert_eq!(state.player_count().await, 1);
+
+        let response = receive(&mallory).await;
+        let nbt::Tag::Map(fields) = response else {
+            panic!("expected a map, got {response:?}");
+        };
+        assert!(fields.contains(&("success".to_string(), nbt::Tag::Byte(0))));
+    }
+
+    #[tokio::test]
+    async fn subscribed_clients_get_updates_pushed_every_tick() {
+        let state = Arc::new(GameState::create(false, WorldInfo::default()));
+        let transport = start_server(state.clone());
+
+        let client = transport.connect(b"123".to_vec());
+        let login = nbt::MapTag::new()
+            .set("id", nbt::Tag::ByteArray(vec![7; 16]))
+            .set("name", nbt::Tag::String("Alice".to_string()))
+            .build();
+        request(&client, "login", login).await;
+
+        let subscribe = nbt::MapTag::new()
+            .set("max_chunks", nbt::Tag::Short(4))
+            .build();
+        notify(&client, "subscribe", subscribe).await;
+        let command = nbt::MapTag::new()
+            .set("name", nbt::Tag::String("chat".to_string()))
+            .set(
+                "args",
+                nbt::Tag::List(vec![nbt::Tag::String("hello".to_string())]),
+            )
+            .build();
+        let chat = nbt::MapTag::new().set("command", command).build();
+        notify(&client, "run_command", chat).await;
+        // requests are handled in order, so this makes sure the notifications have been handled
+        request(&client, "get_player_state", nbt::MapTag::new().build()).await;
+
+        let mut messages = Vec::new();
+        for expected_seq in 0..3 {
+            let push = receive(&client).await;
+            let nbt::Tag::Map(fields) = push else {
+                panic!("expected a map, got {push:?}");
+            };
+            let [(name, nbt::Tag::Map(push))] = fields.as_slice() else {
+                panic!("expected a push, got {fields:?}");
+            };
+            assert_eq!(name, "push");
+
+            let field = |name: &str| &push.iter().find(|(n, _)| n == name).unwrap().1;
+            assert_eq!(field("seq"), &nbt::Tag::Long(expected_seq));
+            let nbt::Tag::Map(events) = field("events") else {
+                panic!("events should be a map");
+            };
+            if let Some((_, nbt::Tag::List(new_messages))) =
+                events.iter().find(|(n, _)| n == "messages")
+            {
+                messages.extend(new_messages.iter().cloned());
+            }
+        }
+        assert_eq!(messages.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn player_state_reports_the_last_processed_input() {
+        let state = Arc::new(GameState::create(false, WorldInfo::default()));
+        let transport = start_server(state.clone());
+
+        let client = transport.connect(b"123".to_vec());
+        let login = nbt::MapTag::new()
+            .set("id", nbt::Tag::ByteArray(vec![7; 16]))
+            .set("name", nbt::Tag::String("Alice".to_string()))
+            .build();
+        request(&client, "login", login).await;
+
+        let last_input = |player_state: nbt::Tag| {
+            let nbt::Tag::Map(fields) = player_state else {
+                panic!("expected a map, got {player_state:?}");
+            };
+            let field = |name: &str| {
+                fields
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .map(|(_, tag)| tag.clone())
+            };
+            (field("last_input_seq"), field("last_input_tick"))
+        };
+        let get_player_state = || request(&client, "get_player_state", nbt::MapTag::new().build());
+
+        // nothing has been processed yet
+        assert_eq!(last_input(get_player_state().await), (None, None));
+
+        let keys = nbt::MapTag::new()
+            .set("keys", nbt::Tag::List(vec![]))
+            .set("seq", nbt::Tag::Long(5))
+            .set("tick", nbt::Tag::Long(100))
+            .build();
+        notify(&client, "keys_pressed", keys).await;
+        // inputs that arrive late do not count
+        let mouse = nbt::MapTag::new()
+            .set("dx", nbt::Tag::Float(1.0))
+            .set("dy", nbt::Tag::Float(0.0))
+            .set("seq", nbt::Tag::Long(4))
+            .set("tick", nbt::Tag::Long(99))
+            .build();
+        notify(&client, "mouse_moved", mouse).await;
+
+        tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                let (seq, tick) = last_input(get_player_state().await);
+                if seq.is_some() {
+                    assert_eq!(seq, Some(nbt::Tag::Long(5)));
+                    assert_eq!(tick, Some(nbt::Tag::Long(100)));
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("input was never processed");
+    }
+
+    #[tokio::test]
+    async fn shutdown_is_done_when_every_player_has_left() {
+        let state = Arc::new(GameState::create(false, WorldInfo::default()));
+        let transport = start_server(state.clone());
+
+        let login = |name: &str| {
+            nbt::MapTag::new()
+                .set("id", nbt::Tag::ByteArray(vec![7; 16]))
+                .set("name", nbt::Tag::String(name.to_string()))
+                .build()
+        };
+        let alice = transport.connect(b"1".to_vec());
+        request(&alice, "login", login("Alice")).await;
+        let subscribe = nbt::MapTag::new()
+            .set("max_chunks", nbt::Tag::Short(4))
+            .build();
+        notify(&alice, "subscribe", subscribe).await;
+
+        state.initiate();
+
+        // subscribed clients are told about the shutdown in their pushes
+        let shutting_down = nbt::Tag::Byte(1);
+        tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                let push = receive(&alice).await;
+                let nbt::Tag::Map(fields) = push else {
+                    panic!("expected a map, got {push:?}");
+                };
+                let [(_, nbt::Tag::Map(push))] = fields.as_slice() else {
+                    panic!("expected a push, got {fields:?}");
+                };
+                let events = &push.iter().find(|(n, _)| n == "events").unwrap().1;
+                let nbt::Tag::Map(events) = events else {
+                    panic!("events should be a map");
+                };
+                if events.contains(&("server_shutting_down".to_string(), shutting_down.clone())) {
+                    return;
+                }
+            }
+        })
+        .await
+        .expect("no push said that the server is shutting down");
+
+        // the shutdown command is handled before the next login
+        let bob = transport.connect(b"2".to_vec());
+        let response = request(&bob, "login", login("Bob")).await;
+        let nbt::Tag::Map(fields) = response else {
+            panic!("expected a map, got {response:?}");
+        };
+        assert!(fields.contains(&("success".to_string(), nbt::Tag::Byte(0))));
+        assert!(!state.done());
+
+        notify(&alice, "logout", nbt::MapTag::new().build()).await;
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !state.done() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("shutdown never finished");
+    }
+
+    #[tokio::test]
+    async fn login_fails_when_the_server_is_full() {
+        let state = Arc::new(GameState::create(false, WorldInfo::default()).with_max_players(1));
+        let transport = start_server(state.clone());
+
+        let login = nbt::MapTag::new()
+            .set("id", nbt::Tag::ByteArray(vec![7; 16]))
+            .set("name", nbt::Tag::String("Alice".to_string()))
+            .build();
+        let succeeded = |response: nbt::Tag| match response {
+            nbt::Tag::Map(fields) => fields.contains(&("success".to_string(), nbt::Tag::Byte(1))),
+            _ => panic!("expected a map, got {response:?}"),
+        };
+
+        let alice = transport.connect(b"1".to_vec());
+        assert!(succeeded(request(&alice, "login", login.clone()).await));
+        let bob = transport.connect(b"2".to_vec());
+        assert!(!succeeded(request(&bob, "login", login.clone()).await));
+
+        notify(&alice, "logout", nbt::MapTag::new().build()).await;
+        assert!(succeeded(request(&bob, "login", login).await));
+    }
+
+    #[tokio::test]
+    async fn the_server_can_run_commands() {
+        let state = Arc::new(GameState::create(false, WorldInfo::default()));
+        let transport = start_server(state.clone());
+
+        let client = transport.connect(b"123".to_vec());
+        let login = nbt::MapTag::new()
+            .set("id", nbt::Tag::ByteArray(vec![7; 16]))
+            .set("name", nbt::Tag::String("Alice".to_string()))
+            .build();
+        request(&client, "login", login).await;
+
+        let mut output = state.follow_output();
+        assert_eq!(
+            state.run_command("chat hello there").await,
+            Vec::<String>::new()
+        );
+        assert_eq!(output.recv().await.unwrap(), "[Server] hello there");
+
+        assert_eq!(
+            state.run_command("list").await,
+            vec!["1 players online: Alice"]
+        );
+        assert_eq!(state.run_command("kick Alice").await, vec!["Kicked Alice"]);
+        assert_eq!(output.recv().await.unwrap(), "[Server] Alice was kicked");
+        assert_eq!(state.player_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn metrics_track_players_and_packets() {
+        let state = Arc::new(GameState::create(false, WorldInfo::default()));
+        let transport = start_server(state.clone());
+
+        let client = transport.connect(b"123".to_vec());
+        client.send(b"not nbt".to_vec()).await.unwrap();
+        let login = nbt::MapTag::new()
+            .set("id", nbt::Tag::ByteArray(vec![7; 16]))
+            .set("name", nbt::Tag::String("Alice".to_string()))
+            .build();
+        request(&client, "login", login).await;
+
+        let metrics = state.metrics().render();
+        assert!(metrics.contains("\nhexacraft_players 1\n"));
+        assert!(metrics.contains("\nhexacraft_packets_received_total{type=\"login\"} 1\n"));
+        assert!(metrics.contains("\nhexacraft_decode_errors_total 1\n"));
+
+        let status = state.run_command("status").await;
+        assert_eq!(status[0], "Players: 1/unlimited");
+        assert_eq!(status[2], "Packets: 1 received, 1 could not be decoded");
+    }
+}
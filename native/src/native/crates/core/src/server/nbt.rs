@@ -1,295 +1,541 @@
-use std::mem::transmute;
-
-    use bytes::BufMut;
-    use glam::DVec3;
-
-    #[derive(Debug, Clone)]
-    pub enum Tag {
-        End,
-        Byte(i8),
-        Short(i16),
-        Int(i32),
-        Long(i64),
-        Float(f32),
-        Double(f64),
-        ByteArray(Vec<i8>),
-        String(String),
-        List(Vec<Tag>),
-        Map(Vec<(String, Tag)>),
-        IntArray(Vec<i32>),
-        ShortArray(Vec<i16>),
-    }
-
-    impl Tag {
-        fn tag_id(&self) -> u8 {
-            match self {
-                Tag::End => 0,
-                Tag::Byte(_) => 1,
-                Tag::Short(_) => 2,
-                Tag::Int(_) => 3,
-                Tag::Long(_) => 4,
-                Tag::Float(_) => 5,
-                Tag::Double(_) => 6,
-                Tag::ByteArray(_) => 7,
-                Tag::String(_) => 8,
-                Tag::List(_) => 9,
-                Tag::Map(_) => 10,
-                Tag::IntArray(_) => 11,
-                Tag::ShortArray(_) => 100, // yes, it should be 100
-            }
+use std::{borrow::Cow, marker::PhantomData, mem::transmute};
+
+use bytes::BufMut;
+use glam::DVec3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    End,
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Map(Vec<(String, Tag)>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+    ShortArray(Vec<i16>),
+}
+
+impl Tag {
+    fn tag_id(&self) -> u8 {
+        match self {
+            Tag::End => 0,
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Map(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+            Tag::ShortArray(_) => 100, // yes, it should be 100
         }
+    }
+
+    pub fn to_binary(&self) -> Vec<u8> {
+        self.to_named_binary("")
+    }
 
-        pub fn to_binary(&self) -> Vec<u8> {
-            let mut stream = TagOutputStream::new();
-            stream.write_tag("", self);
-            stream.data
+    pub fn to_named_binary(&self, name: &str) -> Vec<u8> {
+        let mut stream = TagOutputStream::new();
+        stream.write_tag(name, self);
+        stream.data
+    }
+
+    pub fn from_binary(data: &[u8]) -> Result<(String, Tag), String> {
+        let (name, tag) = TagRef::from_binary(data)?;
+        Ok((name.into_owned(), tag.to_tag()))
+    }
+}
+
+/// A view of a tag that borrows strings and arrays from the buffer it was decoded from.
+///
+/// Lists and maps still allocate a `Vec` for their items, but their payloads are not copied.
+#[derive(Debug, Clone)]
+pub enum TagRef<'a> {
+    End,
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(&'a [i8]),
+    String(Cow<'a, str>),
+    List(Vec<TagRef<'a>>),
+    Map(Vec<(Cow<'a, str>, TagRef<'a>)>),
+    IntArray(ArrayRef<'a, i32>),
+    LongArray(ArrayRef<'a, i64>),
+    ShortArray(ArrayRef<'a, i16>),
+}
+
+impl<'a> TagRef<'a> {
+    fn tag_id(&self) -> u8 {
+        match self {
+            TagRef::End => 0,
+            TagRef::Byte(_) => 1,
+            TagRef::Short(_) => 2,
+            TagRef::Int(_) => 3,
+            TagRef::Long(_) => 4,
+            TagRef::Float(_) => 5,
+            TagRef::Double(_) => 6,
+            TagRef::ByteArray(_) => 7,
+            TagRef::String(_) => 8,
+            TagRef::List(_) => 9,
+            TagRef::Map(_) => 10,
+            TagRef::IntArray(_) => 11,
+            TagRef::LongArray(_) => 12,
+            TagRef::ShortArray(_) => 100,
         }
+    }
+
+    pub fn from_binary(data: &'a [u8]) -> Result<(Cow<'a, str>, TagRef<'a>), String> {
+        TagInputStream { data }.read_tag()
+    }
 
-        pub fn from_binary(data: &[u8]) -> Result<(String, Tag), String> {
-            TagInputStream { data }.read_tag()
+    /// Copies all borrowed data into an owned `Tag`
+    pub fn to_tag(&self) -> Tag {
+        match self {
+            TagRef::End => Tag::End,
+            TagRef::Byte(v) => Tag::Byte(*v),
+            TagRef::Short(v) => Tag::Short(*v),
+            TagRef::Int(v) => Tag::Int(*v),
+            TagRef::Long(v) => Tag::Long(*v),
+            TagRef::Float(v) => Tag::Float(*v),
+            TagRef::Double(v) => Tag::Double(*v),
+            TagRef::ByteArray(v) => Tag::ByteArray(v.to_vec()),
+            TagRef::String(v) => Tag::String(v.to_string()),
+            TagRef::List(v) => Tag::List(v.iter().map(|t| t.to_tag()).collect()),
+            TagRef::Map(v) => Tag::Map(
+                v.iter()
+                    .map(|(name, t)| (name.to_string(), t.to_tag()))
+                    .collect(),
+            ),
+            TagRef::IntArray(v) => Tag::IntArray(v.to_vec()),
+            TagRef::LongArray(v) => Tag::LongArray(v.to_vec()),
+            TagRef::ShortArray(v) => Tag::ShortArray(v.to_vec()),
         }
     }
+}
 
-    pub fn make_vector_tag(d: DVec3) -> Tag {
-        MapTag::new()
-            .set("x", Tag::Double(d.x))
-            .set("y", Tag::Double(d.y))
-            .set("z", Tag::Double(d.z))
-            .build()
+/// Big endian numbers that can be read directly from a byte slice
+pub trait ArrayItem: Copy + 'static {
+    const SIZE: usize;
+
+    fn from_be_slice(b: &[u8]) -> Self;
+}
+
+impl ArrayItem for i16 {
+    const SIZE: usize = 2;
+
+    fn from_be_slice(b: &[u8]) -> Self {
+        i16::from_be_bytes(<[u8; 2]>::try_from(b).unwrap())
     }
+}
 
-    pub struct MapTag {
-        items: Vec<(String, Tag)>,
+impl ArrayItem for i32 {
+    const SIZE: usize = 4;
+
+    fn from_be_slice(b: &[u8]) -> Self {
+        i32::from_be_bytes(<[u8; 4]>::try_from(b).unwrap())
     }
+}
 
-    impl MapTag {
-        pub fn new() -> Self {
-            Self { items: Vec::new() }
-        }
+impl ArrayItem for i64 {
+    const SIZE: usize = 8;
 
-        pub fn set(mut self, name: &str, tag: Tag) -> Self {
-            self.items.push((name.to_string(), tag));
-            self
+    fn from_be_slice(b: &[u8]) -> Self {
+        i64::from_be_bytes(<[u8; 8]>::try_from(b).unwrap())
+    }
+}
+
+/// A borrowed array of big endian numbers. The items are decoded when they are accessed.
+#[derive(Clone, Copy)]
+pub struct ArrayRef<'a, T> {
+    bytes: &'a [u8],
+    _phantom: PhantomData<T>,
+}
+
+impl<'a, T: ArrayItem> ArrayRef<'a, T> {
+    fn new(bytes: &'a [u8]) -> Self {
+        assert_eq!(bytes.len() % T::SIZE, 0);
+        Self {
+            bytes,
+            _phantom: PhantomData,
         }
+    }
 
-        pub fn set_opt(mut self, name: &str, tag: Option<Tag>) -> Self {
-            if let Some(tag) = tag {
-                self.items.push((name.to_string(), tag));
-            }
-            self
-        }
+    pub fn len(&self) -> usize {
+        self.bytes.len() / T::SIZE
+    }
 
-        pub fn build(self) -> Tag {
-            Tag::Map(self.items)
-        }
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
     }
 
-    struct TagOutputStream {
-        data: Vec<u8>,
+    pub fn get(&self, idx: usize) -> Option<T> {
+        let start = idx.checked_mul(T::SIZE)?;
+        let b = self.bytes.get(start..start.checked_add(T::SIZE)?)?;
+        Some(T::from_be_slice(b))
     }
 
-    impl TagOutputStream {
-        pub fn new() -> Self {
-            Self { data: Vec::new() }
-        }
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = T> + 'a {
+        self.bytes.chunks_exact(T::SIZE).map(T::from_be_slice)
+    }
+
+    pub fn to_vec(self) -> Vec<T> {
+        self.iter().collect()
+    }
+
+    /// The raw big endian bytes of the array
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+}
 
-        pub fn write_tag(&mut self, name: &str, tag: &Tag) {
-            assert_ne!(tag.tag_id(), Tag::End.tag_id());
+impl<'a, T: ArrayItem + std::fmt::Debug> std::fmt::Debug for ArrayRef<'a, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+pub fn make_vector_tag(d: DVec3) -> Tag {
+    MapTag::new()
+        .set("x", Tag::Double(d.x))
+        .set("y", Tag::Double(d.y))
+        .set("z", Tag::Double(d.z))
+        .build()
+}
+
+#[derive(Default)]
+pub struct MapTag {
+    items: Vec<(String, Tag)>,
+}
+
+impl MapTag {
+    pub fn new() -> Self {
+        Self { items: Vec::new() }
+    }
 
-            self.data.put_u8(tag.tag_id());
-            self.data.put_u16(name.len() as u16);
-            self.data.extend_from_slice(name.as_bytes());
+    pub fn set(mut self, name: &str, tag: Tag) -> Self {
+        self.items.push((name.to_string(), tag));
+        self
+    }
 
-            self.write_payload(tag);
+    pub fn set_opt(mut self, name: &str, tag: Option<Tag>) -> Self {
+        if let Some(tag) = tag {
+            self.items.push((name.to_string(), tag));
         }
+        self
+    }
 
-        fn write_payload(&mut self, tag: &Tag) {
-            match tag {
-                Tag::End => {}
-                Tag::Byte(v) => {
-                    self.data.put_i8(*v);
-                }
-                Tag::Short(v) => {
-                    self.data.put_i16(*v);
-                }
-                Tag::Int(v) => {
-                    self.data.put_i32(*v);
-                }
-                Tag::Long(v) => {
-                    self.data.put_i64(*v);
-                }
-                Tag::Float(v) => {
-                    self.data.put_f32(*v);
-                }
-                Tag::Double(v) => {
-                    self.data.put_f64(*v);
-                }
-                Tag::ByteArray(v) => {
-                    self.data.put_u32(v.len() as u32);
-                    self.data
-                        .extend_from_slice(unsafe { transmute::<&[i8], &[u8]>(v.as_slice()) });
-                }
-                Tag::String(v) => {
-                    self.data.put_u16(v.len() as u16);
-                    self.data.extend_from_slice(v.as_bytes());
+    pub fn build(self) -> Tag {
+        Tag::Map(self.items)
+    }
+}
+
+struct TagOutputStream {
+    data: Vec<u8>,
+}
+
+impl TagOutputStream {
+    pub fn new() -> Self {
+        Self { data: Vec::new() }
+    }
+
+    pub fn write_tag(&mut self, name: &str, tag: &Tag) {
+        assert_ne!(tag.tag_id(), Tag::End.tag_id());
+
+        self.data.put_u8(tag.tag_id());
+        self.data.put_u16(name.len() as u16);
+        self.data.extend_from_slice(name.as_bytes());
+
+        self.write_payload(tag);
+    }
+
+    fn write_payload(&mut self, tag: &Tag) {
+        match tag {
+            Tag::End => {}
+            Tag::Byte(v) => {
+                self.data.put_i8(*v);
+            }
+            Tag::Short(v) => {
+                self.data.put_i16(*v);
+            }
+            Tag::Int(v) => {
+                self.data.put_i32(*v);
+            }
+            Tag::Long(v) => {
+                self.data.put_i64(*v);
+            }
+            Tag::Float(v) => {
+                self.data.put_f32(*v);
+            }
+            Tag::Double(v) => {
+                self.data.put_f64(*v);
+            }
+            Tag::ByteArray(v) => {
+                self.data.put_u32(v.len() as u32);
+                self.data
+                    .extend_from_slice(unsafe { transmute::<&[i8], &[u8]>(v.as_slice()) });
+            }
+            Tag::String(v) => {
+                self.data.put_u16(v.len() as u16);
+                self.data.extend_from_slice(v.as_bytes());
+            }
+            Tag::List(v) => {
+                let item_tag_id = if v.is_empty() {
+                    Tag::End.tag_id()
+                } else {
+                    v[0].tag_id()
+                };
+                self.data.put_u8(item_tag_id);
+                self.data.put_u32(v.len() as u32);
+                for item in v {
+                    self.write_payload(item);
                 }
-                Tag::List(v) => {
-                    let item_tag_id = if v.is_empty() {
-                        Tag::End.tag_id()
-                    } else {
-                        v[0].tag_id()
-                    };
-                    self.data.put_u8(item_tag_id);
-                    self.data.put_u32(v.len() as u32);
-                    for item in v {
-                        self.write_payload(item);
-                    }
+            }
+            Tag::Map(v) => {
+                for (name, tag) in v {
+                    self.write_tag(name, tag);
                 }
-                Tag::Map(v) => {
-                    for (name, tag) in v {
-                        self.write_tag(name, tag);
-                    }
-                    self.data.put_u8(Tag::End.tag_id());
+                self.data.put_u8(Tag::End.tag_id());
+            }
+            Tag::IntArray(v) => {
+                self.data.put_u32(v.len() as u32);
+                for item in v {
+                    self.data.put_i32(*item);
                 }
-                Tag::IntArray(v) => {
-                    self.data.put_u32(v.len() as u32);
-                    for item in v {
-                        self.data.put_i32(*item);
-                    }
+            }
+            Tag::LongArray(v) => {
+                self.data.put_u32(v.len() as u32);
+                for item in v {
+                    self.data.put_i64(*item);
                 }
-                Tag::ShortArray(v) => {
-                    self.data.put_u32(v.len() as u32);
-                    for item in v {
-                        self.data.put_i16(*item);
-                    }
+            }
+            Tag::ShortArray(v) => {
+                self.data.put_u32(v.len() as u32);
+                for item in v {
+                    self.data.put_i16(*item);
                 }
             }
         }
     }
+}
 
-    struct TagInputStream<'a> {
-        data: &'a [u8],
-    }
+struct TagInputStream<'a> {
+    data: &'a [u8],
+}
 
-    impl<'a> TagInputStream<'a> {
-        pub fn read_tag(&mut self) -> Result<(String, Tag), String> {
-            let tag_id = self.read_u8()?;
+impl<'a> TagInputStream<'a> {
+    pub fn read_tag(&mut self) -> Result<(Cow<'a, str>, TagRef<'a>), String> {
+        let tag_id = self.read_u8()?;
 
-            let name = if tag_id == Tag::End.tag_id() {
-                "".to_string()
-            } else {
-                let name_len = self.read_u16()?;
-                let name_bytes = self.take_n_bytes(name_len as usize)?;
-                String::from_utf8_lossy(name_bytes).to_string()
-            };
+        let name = if tag_id == TagRef::End.tag_id() {
+            Cow::Borrowed("")
+        } else {
+            let name_len = self.read_u16()?;
+            let name_bytes = self.take_n_bytes(name_len as usize)?;
+            String::from_utf8_lossy(name_bytes)
+        };
 
-            let tag = self.read_payload(tag_id)?;
+        let tag = self.read_payload(tag_id)?;
 
-            Ok((name, tag))
-        }
-
-        fn read_payload(&mut self, tag_id: u8) -> Result<Tag, String> {
-            let tag = match tag_id {
-                0 => Tag::End,
-                1 => Tag::Byte(self.read_u8()? as i8),
-                2 => Tag::Short(self.read_u16()? as i16),
-                3 => Tag::Int(self.read_u32()? as i32),
-                4 => Tag::Long(self.read_u64()? as i64),
-                5 => Tag::Float(self.read_f32()?),
-                6 => Tag::Double(self.read_f64()?),
-                7 => {
-                    let len = self.read_u32()? as usize;
-                    let bytes = self.take_n_bytes(len)?;
-                    Tag::ByteArray(unsafe { transmute::<&[u8], &[i8]>(bytes).to_vec() })
-                }
-                8 => {
-                    let len = self.read_u16()? as usize;
-                    let bytes = self.take_n_bytes(len)?;
-                    Tag::String(String::from_utf8_lossy(bytes).to_string())
-                }
-                9 => {
-                    let item_tag_id = self.read_u8()?;
-                    let len = self.read_u32()?;
+        Ok((name, tag))
+    }
 
-                    if len != 0 && item_tag_id == Tag::End.tag_id() {
-                        return Err("non-empty list of end tags is not allowed")?;
-                    }
+    fn read_payload(&mut self, tag_id: u8) -> Result<TagRef<'a>, String> {
+        let tag = match tag_id {
+            0 => TagRef::End,
+            1 => TagRef::Byte(self.read_u8()? as i8),
+            2 => TagRef::Short(self.read_u16()? as i16),
+            3 => TagRef::Int(self.read_u32()? as i32),
+            4 => TagRef::Long(self.read_u64()? as i64),
+            5 => TagRef::Float(self.read_f32()?),
+            6 => TagRef::Double(self.read_f64()?),
+            7 => {
+                let len = self.read_u32()? as usize;
+                let bytes = self.take_n_bytes(len)?;
+                TagRef::ByteArray(unsafe { transmute::<&[u8], &[i8]>(bytes) })
+            }
+            8 => {
+                let len = self.read_u16()? as usize;
+                let bytes = self.take_n_bytes(len)?;
+                TagRef::String(String::from_utf8_lossy(bytes))
+            }
+            9 => {
+                let item_tag_id = self.read_u8()?;
+                let len = self.read_u32()?;
 
-                    let mut items = Vec::with_capacity(len as usize);
-                    for _ in 0..len {
-                        items.push(self.read_payload(item_tag_id)?);
-                    }
-                    Tag::List(items)
-                }
-                10 => {
-                    let mut items = Vec::new();
-                    loop {
-                        let (name, item) = self.read_tag()?;
-                        if item.tag_id() == Tag::End.tag_id() {
-                            break;
-                        }
-                        items.push((name, item));
-                    }
-                    Tag::Map(items)
+                if len != 0 && item_tag_id == TagRef::End.tag_id() {
+                    return Err("non-empty list of end tags is not allowed")?;
                 }
-                11 => {
-                    let len = self.read_u32()? as usize;
-                    let mut items = Vec::with_capacity(len);
-                    for _ in 0..len {
-                        items.push(self.read_u32()? as i32);
-                    }
-                    Tag::IntArray(items)
+
+                // the length comes from the input, so it can't be trusted for preallocation
+                let mut items = Vec::with_capacity((len as usize).min(self.data.len()));
+                for _ in 0..len {
+                    items.push(self.read_payload(item_tag_id)?);
                 }
-                100 => {
-                    let len = self.read_u32()? as usize;
-                    let mut items = Vec::with_capacity(len);
-                    for _ in 0..len {
-                        items.push(self.read_u16()? as i16);
+                TagRef::List(items)
+            }
+            10 => {
+                let mut items = Vec::new();
+                loop {
+                    let (name, item) = self.read_tag()?;
+                    if item.tag_id() == TagRef::End.tag_id() {
+                        break;
                     }
-                    Tag::ShortArray(items)
+                    items.push((name, item));
                 }
-                n => Err(format!("unknown tag id: {n}"))?,
-            };
+                TagRef::Map(items)
+            }
+            11 => TagRef::IntArray(self.read_array()?),
+            12 => TagRef::LongArray(self.read_array()?),
+            100 => TagRef::ShortArray(self.read_array()?),
+            n => Err(format!("unknown tag id: {n}"))?,
+        };
 
-            Ok(tag)
-        }
+        Ok(tag)
+    }
 
-        fn take_n_bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
-            if self.data.len() < n {
-                return Err("not enough bytes")?;
-            }
-            let (taken, rest) = self.data.split_at(n);
-            self.data = rest;
-            Ok(taken)
-        }
+    fn read_array<T: ArrayItem>(&mut self) -> Result<ArrayRef<'a, T>, String> {
+        let len = self.read_u32()? as usize;
+        let num_bytes = len.checked_mul(T::SIZE).ok_or("array is too long")?;
+        let bytes = self.take_n_bytes(num_bytes)?;
+        Ok(ArrayRef::new(bytes))
+    }
 
-        fn read_u8(&mut self) -> Result<u8, String> {
-            let b = self.take_n_bytes(1)?;
-            Ok(b[0])
+    fn take_n_bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < n {
+            return Err("not enough bytes")?;
         }
+        let (taken, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(taken)
+    }
 
-        fn read_u16(&mut self) -> Result<u16, String> {
-            let b = self.take_n_bytes(2)?;
-            Ok(u16::from_be_bytes(<[u8; 2]>::try_from(b).unwrap()))
-        }
+    fn read_u8(&mut self) -> Result<u8, String> {
+        let b = self.take_n_bytes(1)?;
+        Ok(b[0])
+    }
 
-        fn read_u32(&mut self) -> Result<u32, String> {
-            let b = self.take_n_bytes(4)?;
-            Ok(u32::from_be_bytes(<[u8; 4]>::try_from(b).unwrap()))
-        }
+    fn read_u16(&mut self) -> Result<u16, String> {
+        let b = self.take_n_bytes(2)?;
+        Ok(u16::from_be_bytes(<[u8; 2]>::try_from(b).unwrap()))
+    }
 
-        fn read_u64(&mut self) -> Result<u64, String> {
-            let b = self.take_n_bytes(8)?;
-            Ok(u64::from_be_bytes(<[u8; 8]>::try_from(b).unwrap()))
-        }
+    fn read_u32(&mut self) -> Result<u32, String> {
+        let b = self.take_n_bytes(4)?;
+        Ok(u32::from_be_bytes(<[u8; 4]>::try_from(b).unwrap()))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, String> {
+        let b = self.take_n_bytes(8)?;
+        Ok(u64::from_be_bytes(<[u8; 8]>::try_from(b).unwrap()))
+    }
+
+    fn read_f32(&mut self) -> Result<f32, String> {
+        let b = self.take_n_bytes(4)?;
+        Ok(f32::from_be_bytes(<[u8; 4]>::try_from(b).unwrap()))
+    }
 
-        fn read_f32(&mut self) -> Result<f32, String> {
-            let b = self.take_n_bytes(4)?;
-            Ok(f32::from_be_bytes(<[u8; 4]>::try_from(b).unwrap()))
+    fn read_f64(&mut self) -> Result<f64, String> {
+        let b = self.take_n_bytes(8)?;
+        Ok(f64::from_be_bytes(<[u8; 8]>::try_from(b).unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::{MapTag, Tag, TagRef};
+
+    #[test]
+    fn long_array_roundtrip() {
+        let tag = MapTag::new()
+            .set("coords", Tag::LongArray(vec![1, -2, i64::MAX, i64::MIN]))
+            .build();
+
+        let data = tag.to_binary();
+        let (_, decoded) = Tag::from_binary(&data).unwrap();
+
+        let Tag::Map(items) = decoded else {
+            panic!("expected a map, got {decoded:?}");
+        };
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].0, "coords");
+        match &items[0].1 {
+            Tag::LongArray(vs) => assert_eq!(vs, &vec![1, -2, i64::MAX, i64::MIN]),
+            t => panic!("expected a long array, got {t:?}"),
         }
+    }
 
-        fn read_f64(&mut self) -> Result<f64, String> {
-            let b = self.take_n_bytes(8)?;
-            Ok(f64::from_be_bytes(<[u8; 8]>::try_from(b).unwrap()))
+    #[test]
+    fn tag_ref_borrows_from_input() {
+        let tag = MapTag::new()
+            .set("name", Tag::String("abc".to_string()))
+            .set("bytes", Tag::ByteArray(vec![1, 2, -3]))
+            .set("ints", Tag::IntArray(vec![7, -8, 9]))
+            .set("shorts", Tag::ShortArray(vec![-1, 300]))
+            .build();
+
+        let data = tag.to_binary();
+        let (_, decoded) = TagRef::from_binary(&data).unwrap();
+
+        let TagRef::Map(items) = decoded else {
+            panic!("expected a map, got {decoded:?}");
+        };
+        let data_range = data.as_ptr_range();
+
+        match &items[0] {
+            (Cow::Borrowed("name"), TagRef::String(Cow::Borrowed(s))) => {
+                assert_eq!(*s, "abc");
+                assert!(data_range.contains(&s.as_ptr()));
+            }
+            t => panic!("expected a borrowed string, got {t:?}"),
+        }
+        match &items[1].1 {
+            TagRef::ByteArray(vs) => {
+                assert_eq!(*vs, &[1, 2, -3]);
+                assert!(data_range.contains(&(vs.as_ptr() as *const u8)));
+            }
+            t => panic!("expected a byte array, got {t:?}"),
+        }
+        match &items[2].1 {
+            TagRef::IntArray(vs) => {
+                assert_eq!(vs.len(), 3);
+                assert_eq!(vs.get(1), Some(-8));
+                assert_eq!(vs.get(3), None);
+                assert_eq!(vs.get(usize::MAX / 4), None);
+                assert_eq!(vs.to_vec(), vec![7, -8, 9]);
+                assert!(data_range.contains(&vs.as_bytes().as_ptr()));
+            }
+            t => panic!("expected an int array, got {t:?}"),
         }
-    }
\ No newline at end of file
+        match &items[3].1 {
+            TagRef::ShortArray(vs) => assert_eq!(vs.to_vec(), vec![-1, 300]),
+            t => panic!("expected a short array, got {t:?}"),
+        }
+    }
+
+    #[test]
+    fn truncated_array_is_rejected() {
+        let mut data = MapTag::new()
+            .set("longs", Tag::LongArray(vec![1, 2, 3]))
+            .build()
+            .to_binary();
+        data.truncate(data.len() - 5);
+
+        assert!(TagRef::from_binary(&data).is_err());
+    }
+}
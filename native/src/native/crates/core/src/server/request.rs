@@ -3,29 +3,78 @@ use std::{collections::HashMap, mem::transmute};
 use glam::Vec2;
 use uuid::Uuid;
 
+use crate::server::input::{AnalogInput, PlayerInput};
 use crate::server::nbt;
 
-
 pub enum NetworkPacket {
-    Login { id: Uuid, name: String },
+    Login {
+        id: Uuid,
+        name: String,
+        /// Proves who the player is, needed on online servers
+        token: Option<Vec<u8>>,
+    },
     Logout,
+    /// Asks what the server is and how many are playing. This is answered for any client, also
+    /// ones that have not logged in, so that servers can be listed without joining them.
+    Status {
+        /// Sent back as is, so the client can tell how long the round trip took
+        time: Option<i64>,
+    },
 
     GetWorldInfo,
-    LoadColumnData { coords: u64 },
+    LoadColumnData {
+        coords: u64,
+    },
 
     GetPlayerState,
     GetEvents,
-    GetWorldLoadingEvents { max_chunks_to_load: u16 },
+    GetWorldLoadingEvents {
+        max_chunks_to_load: u16,
+    },
+    /// Asks the server to push the player state and events at the end of every tick, so they do
+    /// not have to be polled. Subscribing again only updates `max_chunks_to_load`.
+    Subscribe {
+        max_chunks_to_load: u16,
+    },
+    Unsubscribe,
 
     PlayerRightClicked,
     PlayerLeftClicked,
     PlayerToggledFlying,
-    PlayerSetSelectedItemSlot { slot: u16 },
-    PlayerUpdatedInventory { inventory: HashMap<u8, u8> },
-    PlayerMovedMouse { distance: Vec2 },
-    PlayerPressedKeys { keys: Vec<String> },
+    PlayerSetSelectedItemSlot {
+        slot: u16,
+    },
+    PlayerUpdatedInventory {
+        inventory: HashMap<u8, u8>,
+    },
+    PlayerMovedMouse {
+        distance: Vec2,
+        input: Option<InputStamp>,
+    },
+    PlayerPressedKeys {
+        keys: PlayerInput,
+        input: Option<InputStamp>,
+    },
+    /// Replaces the analog input, which then stays in effect until the next one arrives
+    PlayerMovedAnalog {
+        analog: AnalogInput,
+        input: Option<InputStamp>,
+    },
+
+    RunCommand {
+        command: String,
+        args: Vec<String>,
+    },
+}
 
-    RunCommand { command: String, args: Vec<String> },
+/// Identifies the input a client sent during one of its ticks. The server reports the last one it
+/// has processed, so the client can replay the inputs after it on top of the server state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputStamp {
+    /// Increases by one for every input the client sends
+    pub seq: u64,
+    /// The client tick the input was made in
+    pub tick: u64,
 }
 
 impl NetworkPacket {
@@ -49,9 +98,21 @@ impl NetworkPacket {
                     nbt::Tag::String(v) => v.clone(),
                     _ => return Err("wrong type for name field")?,
                 };
-                NetworkPacket::Login { id, name }
+                let token = match tag.get("token") {
+                    None => None,
+                    Some(nbt::Tag::ByteArray(v)) => Some(v.iter().map(|&b| b as u8).collect()),
+                    _ => return Err("wrong type for token field")?,
+                };
+                NetworkPacket::Login { id, name, token }
             }
             "logout" => NetworkPacket::Logout,
+            "status" => NetworkPacket::Status {
+                time: match tag.get("time") {
+                    None => None,
+                    Some(nbt::Tag::Long(v)) => Some(*v),
+                    _ => return Err("wrong type for time field")?,
+                },
+            },
             "get_world_info" => NetworkPacket::GetWorldInfo,
             "load_column_data" => NetworkPacket::LoadColumnData {
                 coords: match tag.get("coords").ok_or("missing field coords")? {
@@ -67,6 +128,13 @@ impl NetworkPacket {
                     _ => return Err("wrong type for max_chunks field")?,
                 },
             },
+            "subscribe" => NetworkPacket::Subscribe {
+                max_chunks_to_load: match tag.get("max_chunks").ok_or("missing max_chunks")? {
+                    nbt::Tag::Short(v) => *v as u16,
+                    _ => return Err("wrong type for max_chunks field")?,
+                },
+            },
+            "unsubscribe" => NetworkPacket::Unsubscribe,
             "right_mouse_clicked" => NetworkPacket::PlayerRightClicked,
             "left_mouse_clicked" => NetworkPacket::PlayerLeftClicked,
             "toggle_flying" => NetworkPacket::PlayerToggledFlying,
@@ -98,28 +166,38 @@ impl NetworkPacket {
             },
             "mouse_moved" => NetworkPacket::PlayerMovedMouse {
                 distance: Vec2::new(
-                    match tag.get("dx").ok_or("missing dx")? {
-                        nbt::Tag::Float(v) => *v,
-                        _ => return Err("wrong type for dx field")?,
-                    },
-                    match tag.get("dy").ok_or("missing dy")? {
-                        nbt::Tag::Float(v) => *v,
-                        _ => return Err("wrong type for dy field")?,
-                    },
+                    decode_finite_float(&tag, "dx")?,
+                    decode_finite_float(&tag, "dy")?,
                 ),
+                input: decode_input_stamp(&tag)?,
             },
             "keys_pressed" => NetworkPacket::PlayerPressedKeys {
                 keys: match tag.get("keys").ok_or("missing field keys")? {
-                    nbt::Tag::List(vs) => vs
-                        .iter()
-                        .map(|key| match key {
-                            nbt::Tag::String(v) => Ok(v.clone()),
-                            _ => Err("wrong type for key"),
-                        })
-                        .collect::<Result<Vec<_>, _>>()?,
+                    nbt::Tag::List(vs) => PlayerInput::from_key_names(
+                        vs.iter()
+                            .map(|key| match key {
+                                nbt::Tag::String(v) => Ok(v.as_str()),
+                                _ => Err("wrong type for key"),
+                            })
+                            .collect::<Result<Vec<_>, _>>()?,
+                    )?,
                     _ => return Err("wrong type for keys field")?,
                 },
+                input: decode_input_stamp(&tag)?,
             },
+            "analog_moved" => {
+                let look = Vec2::new(
+                    decode_finite_float(&tag, "look_x")?,
+                    decode_finite_float(&tag, "look_y")?,
+                );
+                if look.abs().max_element() > 1.0 {
+                    return Err("look axes have to be between -1 and 1")?;
+                }
+                NetworkPacket::PlayerMovedAnalog {
+                    analog: AnalogInput { look },
+                    input: decode_input_stamp(&tag)?,
+                }
+            }
             "run_command" => match tag.get("command").ok_or("missing field command")? {
                 nbt::Tag::Map(vs) => {
                     let command = match vs
@@ -153,6 +231,55 @@ impl NetworkPacket {
 
         Ok(packet)
     }
+
+    /// The name the packet has on the wire, like `login`
+    pub fn name(&self) -> &'static str {
+        match self {
+            NetworkPacket::Login { .. } => "login",
+            NetworkPacket::Logout => "logout",
+            NetworkPacket::Status { .. } => "status",
+            NetworkPacket::GetWorldInfo => "get_world_info",
+            NetworkPacket::LoadColumnData { .. } => "load_column_data",
+            NetworkPacket::GetPlayerState => "get_player_state",
+            NetworkPacket::GetEvents => "get_events",
+            NetworkPacket::GetWorldLoadingEvents { .. } => "get_world_loading_events",
+            NetworkPacket::Subscribe { .. } => "subscribe",
+            NetworkPacket::Unsubscribe => "unsubscribe",
+            NetworkPacket::PlayerRightClicked => "right_mouse_clicked",
+            NetworkPacket::PlayerLeftClicked => "left_mouse_clicked",
+            NetworkPacket::PlayerToggledFlying => "toggle_flying",
+            NetworkPacket::PlayerSetSelectedItemSlot { .. } => "set_selected_inventory_slot",
+            NetworkPacket::PlayerUpdatedInventory { .. } => "inventory_updated",
+            NetworkPacket::PlayerMovedMouse { .. } => "mouse_moved",
+            NetworkPacket::PlayerPressedKeys { .. } => "keys_pressed",
+            NetworkPacket::PlayerMovedAnalog { .. } => "analog_moved",
+            NetworkPacket::RunCommand { .. } => "run_command",
+        }
+    }
+}
+
+/// NaN or infinite values would spread to the player state and never go away, so they are rejected
+fn decode_finite_float(tag: &HashMap<String, nbt::Tag>, name: &str) -> Result<f32, String> {
+    match tag.get(name) {
+        Some(nbt::Tag::Float(v)) if v.is_finite() => Ok(*v),
+        Some(nbt::Tag::Float(_)) => Err(format!("{name} field is not finite")),
+        Some(_) => Err(format!("wrong type for {name} field")),
+        None => Err(format!("missing {name}")),
+    }
+}
+
+/// Inputs from older clients have no stamp, but if there is one both fields have to be present
+fn decode_input_stamp(tag: &HashMap<String, nbt::Tag>) -> Result<Option<InputStamp>, String> {
+    match (tag.get("seq"), tag.get("tick")) {
+        (None, None) => Ok(None),
+        (Some(nbt::Tag::Long(seq)), Some(nbt::Tag::Long(tick))) => Ok(Some(InputStamp {
+            seq: *seq as u64,
+            tick: *tick as u64,
+        })),
+        (Some(_), None) => Err("missing field tick")?,
+        (None, Some(_)) => Err("missing field seq")?,
+        _ => Err("wrong type for seq or tick field")?,
+    }
 }
 
 fn decode_inventory_slot(vs: &[(String, nbt::Tag)]) -> Result<(u8, u8), String> {
@@ -174,3 +301,43 @@ fn decode_inventory_slot(vs: &[(String, nbt::Tag)]) -> Result<(u8, u8), String>
     };
     Ok((slot, id))
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::server::input::PlayerInput;
+    use crate::server::nbt;
+
+    use super::NetworkPacket;
+
+    fn keys_pressed(keys: &[&str]) -> nbt::Tag {
+        let keys = keys.iter().map(|k| nbt::Tag::String(k.to_string()));
+        nbt::MapTag::new()
+            .set("keys", nbt::Tag::List(keys.collect()))
+            .build()
+    }
+
+    fn analog_moved(look_x: f32, look_y: f32) -> nbt::Tag {
+        nbt::MapTag::new()
+            .set("look_x", nbt::Tag::Float(look_x))
+            .set("look_y", nbt::Tag::Float(look_y))
+            .build()
+    }
+
+    #[test]
+    fn unknown_inputs_are_rejected() {
+        let packet = NetworkPacket::decode("keys_pressed", keys_pressed(&["Jump", "Sneak"]));
+        assert!(matches!(
+            packet,
+            Ok(NetworkPacket::PlayerPressedKeys { keys, .. }) if keys == PlayerInput::JUMP | PlayerInput::SNEAK
+        ));
+
+        assert!(NetworkPacket::decode("keys_pressed", keys_pressed(&["Jump", "Fly"])).is_err());
+    }
+
+    #[test]
+    fn analog_input_has_to_be_in_range() {
+        assert!(NetworkPacket::decode("analog_moved", analog_moved(0.5, -1.0)).is_ok());
+        assert!(NetworkPacket::decode("analog_moved", analog_moved(1.5, 0.0)).is_err());
+        assert!(NetworkPacket::decode("analog_moved", analog_moved(f32::NAN, 0.0)).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use crate::server::{
-    nbt,
+    PROTOCOL_VERSION, nbt,
+    request::InputStamp,
     state::{ServerMessage, ServerMessageSender},
     world::{Inventory, Player, WorldInfo},
 };
@@ -37,52 +38,46 @@ impl<'r> From<LoginResponse<'r>> for nbt::Tag {
     }
 }
 
+pub struct StatusResponse<'r> {
+    pub motd: &'r str,
+    pub world_name: &'r str,
+    pub players: usize,
+    /// `None` if there is no limit
+    pub max_players: Option<usize>,
+    /// The time from the request, if it had one
+    pub time: Option<i64>,
+}
+
+impl<'r> From<StatusResponse<'r>> for nbt::Tag {
+    fn from(res: StatusResponse<'r>) -> Self {
+        nbt::MapTag::new()
+            .set("motd", nbt::Tag::String(res.motd.to_string()))
+            .set("protocol_version", nbt::Tag::Short(PROTOCOL_VERSION as i16))
+            .set("world_name", nbt::Tag::String(res.world_name.to_string()))
+            .set("players", nbt::Tag::Int(res.players as i32))
+            .set_opt(
+                "max_players",
+                res.max_players.map(|max| nbt::Tag::Int(max as i32)),
+            )
+            .set_opt("time", res.time.map(nbt::Tag::Long))
+            .build()
+    }
+}
+
 pub struct GetWorldInfoResponse<'r> {
     pub info: &'r WorldInfo,
 }
 
 impl<'r> From<GetWorldInfoResponse<'r>> for nbt::Tag {
     fn from(res: GetWorldInfoResponse) -> Self {
-        let info = res.info;
-
-        nbt::MapTag::new()
-            .set("version", nbt::Tag::Short(info.version as i16))
-            .set(
-                "general",
-                nbt::MapTag::new()
-                    .set("worldSize", nbt::Tag::Byte(info.world_size.0 as i8))
-                    .set("name", nbt::Tag::String(info.world_name.clone()))
-                    .build(),
-            )
-            .set("gen", {
-                let s = &info._gen;
-                nbt::MapTag::new()
-                    .set("seed", nbt::Tag::Long(s.seed as i64))
-                    .set("blockGenScale", nbt::Tag::Double(s.block_gen_scale))
-                    .set(
-                        "heightMapGenScale",
-                        nbt::Tag::Double(s.height_map_gen_scale),
-                    )
-                    .set(
-                        "blockDensityGenScale",
-                        nbt::Tag::Double(s.block_density_gen_scale),
-                    )
-                    .set(
-                        "biomeHeightGenScale",
-                        nbt::Tag::Double(s.biome_height_map_gen_scale),
-                    )
-                    .set(
-                        "biomeHeightVariationGenScale",
-                        nbt::Tag::Double(s.biome_height_variation_gen_scale),
-                    )
-                    .build()
-            })
-            .build()
+        res.info.to_nbt()
     }
 }
 
 pub struct GetPlayerStateResponse<'r> {
     pub player: &'r Player,
+    /// The last input from the client that has affected the player
+    pub last_processed_input: Option<InputStamp>,
 }
 
 impl<'r> From<GetPlayerStateResponse<'r>> for nbt::Tag {
@@ -99,6 +94,16 @@ impl<'r> From<GetPlayerStateResponse<'r>> for nbt::Tag {
                 nbt::Tag::Short(p.selected_item_slot as i16),
             )
             .set("inventory", encode_inventory(&p.inventory))
+            .set_opt(
+                "last_input_seq",
+                res.last_processed_input
+                    .map(|input| nbt::Tag::Long(input.seq as i64)),
+            )
+            .set_opt(
+                "last_input_tick",
+                res.last_processed_input
+                    .map(|input| nbt::Tag::Long(input.tick as i64)),
+            )
             .build()
     }
 }
@@ -164,6 +169,33 @@ impl From<GetWorldLoadingEventsResponse> for nbt::Tag {
     }
 }
 
+/// Sent to subscribed clients at the end of every tick, without them asking for it.
+///
+/// The sequence number goes up by one for every push to a client, so a client that sees a gap
+/// knows it has missed something and should poll instead.
+pub struct PushUpdate<'r> {
+    pub seq: u64,
+    pub player: GetPlayerStateResponse<'r>,
+    pub events: GetEventsResponse,
+    pub world_loading_events: GetWorldLoadingEventsResponse,
+}
+
+impl<'r> From<PushUpdate<'r>> for nbt::Tag {
+    fn from(res: PushUpdate<'r>) -> Self {
+        nbt::MapTag::new()
+            .set(
+                "push",
+                nbt::MapTag::new()
+                    .set("seq", nbt::Tag::Long(res.seq as i64))
+                    .set("player", res.player.into())
+                    .set("events", res.events.into())
+                    .set("world_loading_events", res.world_loading_events.into())
+                    .build(),
+            )
+            .build()
+    }
+}
+
 pub struct PlayerUpdatedInventoryResponse<'r> {
     pub inventory: &'r Inventory,
 }
@@ -1,32 +1,151 @@
 use std::{
     collections::{HashMap, VecDeque},
-    sync::Mutex,
-    time::Duration,
+    fmt::{self, Display},
+    num::NonZeroU32,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Instant,
 };
 
 use glam::Vec2;
+use tokio::sync::{Notify, broadcast, mpsc, oneshot};
+use tracing::{Span, info, info_span};
+use uuid::Uuid;
 
 use crate::server::{
-    GracefulShutdown, RequestHandler, input, nbt,
-    request::NetworkPacket,
+    GracefulShutdown, RequestHandler,
+    auth::TokenVerifier,
+    input::{self, AnalogInput, PlayerInput},
+    metrics::Metrics,
+    nbt,
+    request::{InputStamp, NetworkPacket},
     response::*,
-    world::{CylinderSize, Inventory, Player, WorldGenSettings, WorldInfo},
+    tick::{DEFAULT_TICK_RATE, TickClock, TickStats, TickStatus},
+    world::{Inventory, Player, WorldInfo},
 };
 
+/// A handle to the game simulation.
+///
+/// The simulation itself is owned by the task running `run_ticks`, so nothing in it needs to be
+/// locked. Everyone else talks to it by sending commands, and gets the results back over oneshot
+/// channels. Commands are executed in the order they were sent, between ticks.
 pub struct GameState {
     is_online: bool,
-    path: String,
+    token_verifier: Option<Arc<dyn TokenVerifier>>,
 
-    is_shutting_down: Mutex<bool>,
+    commands: mpsc::UnboundedSender<Command>,
+    /// Taken by `run_ticks` when it starts
+    simulation: Mutex<Option<(Simulation, mpsc::UnboundedReceiver<Command>)>>,
+    /// Set by the simulation once it is shutting down and every player is gone
+    done: AtomicBool,
+    output: broadcast::Sender<String>,
+    metrics: Arc<Metrics>,
+
+    /// Updates waiting to be pushed to subscribed clients, see `next_push`
+    pushes: Mutex<VecDeque<Push>>,
+    push_added: Notify,
+}
+
+/// An update waiting to be pushed to a subscribed client
+struct Push {
+    client_id: u64,
+    /// Messages are removed from the player once they are in a push, so a push with messages is
+    /// never dropped
+    has_messages: bool,
+    update: nbt::Tag,
+}
+
+enum Command {
+    Handle {
+        client_id: u64,
+        packet: NetworkPacket,
+        reply: oneshot::Sender<Option<nbt::Tag>>,
+        /// The span of the request, so that logs can be traced back to it
+        span: Span,
+    },
+    /// A login that has already been authenticated, see `GameState::authenticate`
+    Login {
+        client_id: u64,
+        id: Uuid,
+        /// The name to use, or why authentication failed
+        name: Result<String, String>,
+        reply: oneshot::Sender<Option<nbt::Tag>>,
+        span: Span,
+    },
+    ClientDisconnected {
+        client_id: u64,
+    },
+    Shutdown,
+    GetPlayerCount {
+        reply: oneshot::Sender<usize>,
+    },
+    GetTickStatus {
+        reply: oneshot::Sender<TickStatus>,
+    },
+    RunAsServer {
+        command: String,
+        args: Vec<String>,
+        reply: oneshot::Sender<Vec<String>>,
+    },
+}
+
+/// Everything in the game world, only ever touched by the tick task
+struct Simulation {
+    is_shutting_down: bool,
+    /// Shown in server lists, see `NetworkPacket::Status`
+    motd: String,
     world_info: WorldInfo,
-    players: Mutex<HashMap<u64, PlayerConnectionState>>,
+    players: HashMap<u64, PlayerConnectionState>,
+    max_players: Option<usize>,
+    /// Every message sent to all players, see `GameState::follow_output`
+    output: broadcast::Sender<String>,
+
+    tick_clock: TickClock,
+    tick_stats: TickStats,
+    /// How many ticks have been run
+    ticks: u64,
+    metrics: Arc<Metrics>,
 }
 
+/// The message of the day of servers that have not set one
+pub const DEFAULT_MOTD: &str = "A Hexacraft server";
+
+/// Followers of the server output that fall further behind than this miss some of it
+const OUTPUT_BUFFER_SIZE: usize = 256;
+
+/// If more pushes than this are waiting to be sent the oldest ones without messages are dropped.
+/// The clients will notice the gap in the sequence numbers.
+const MAX_QUEUED_PUSHES: usize = 1024;
+
+/// The most commands executed in a row before checking whether a tick is due
+const MAX_COMMANDS_PER_BATCH: usize = 256;
+
 struct PlayerConnectionState {
     player: Player,
     messages_to_send: VecDeque<ServerMessage>,
     mouse_movement: Vec2,
-    pressed_keys: Vec<String>,
+    pressed_keys: PlayerInput,
+    analog_input: AnalogInput,
+    /// The newest input received from the client, which is processed in the next tick
+    received_input: Option<InputStamp>,
+    processed_input: Option<InputStamp>,
+    subscription: Option<Subscription>,
+}
+
+impl PlayerConnectionState {
+    fn input_received(&mut self, input: Option<InputStamp>) {
+        if let Some(input) = input
+            && self.received_input.is_none_or(|r| r.seq < input.seq)
+        {
+            self.received_input = Some(input);
+        }
+    }
+}
+
+struct Subscription {
+    next_seq: u64,
 }
 
 #[derive(Clone)]
@@ -41,125 +160,578 @@ pub enum ServerMessageSender {
     Player { name: String },
 }
 
+impl Display for ServerMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.sender {
+            ServerMessageSender::Server => write!(f, "[Server] {}", self.text),
+            ServerMessageSender::Player { name } => write!(f, "<{name}> {}", self.text),
+        }
+    }
+}
+
 impl GameState {
-    pub fn create(is_online: bool, path: String) -> Self {
+    /// Creates the state of a server for a world, see `world_config::open_world`
+    pub fn create(is_online: bool, world_info: WorldInfo) -> Self {
+        let (output, _) = broadcast::channel(OUTPUT_BUFFER_SIZE);
+        let metrics = Arc::new(Metrics::default());
+        let simulation = Simulation {
+            is_shutting_down: false,
+            motd: DEFAULT_MOTD.to_string(),
+            world_info,
+            players: HashMap::new(),
+            max_players: None,
+            output: output.clone(),
+
+            tick_clock: TickClock::new(DEFAULT_TICK_RATE, Instant::now()),
+            tick_stats: TickStats::new(),
+            ticks: 0,
+            metrics: metrics.clone(),
+        };
+        let (commands, command_rx) = mpsc::unbounded_channel();
+
         Self {
             is_online,
-            path: path.to_string(),
-
-            is_shutting_down: Mutex::new(false),
-            world_info: WorldInfo {
-                version: 2,
-                world_name: "Test 123".to_string(),
-                world_size: CylinderSize(8),
-                _gen: WorldGenSettings {
-                    seed: 42,
-                    block_gen_scale: 0.1,
-                    height_map_gen_scale: 0.02,
-                    block_density_gen_scale: 0.01,
-                    biome_height_map_gen_scale: 0.002,
-                    biome_height_variation_gen_scale: 0.002,
-                },
-            },
-            players: Mutex::new(HashMap::new()),
+            token_verifier: None,
+
+            commands,
+            simulation: Mutex::new(Some((simulation, command_rx))),
+            done: AtomicBool::new(false),
+            output,
+            metrics,
+
+            pushes: Mutex::new(VecDeque::new()),
+            push_added: Notify::new(),
         }
     }
 
-    fn access_player_state<R>(
+    /// Sets how login tokens are checked when the server is in online mode
+    pub fn with_token_verifier(mut self, verifier: Arc<dyn TokenVerifier>) -> Self {
+        self.token_verifier = Some(verifier);
+        self
+    }
+
+    /// Sets how many times per second the world is updated, 60 by default
+    pub fn with_tick_rate(self, ticks_per_second: NonZeroU32) -> Self {
+        if let Some((simulation, _)) = self.simulation.lock().unwrap().as_mut() {
+            simulation.tick_clock = TickClock::new(ticks_per_second, Instant::now());
+        }
+        self
+    }
+
+    /// Makes logins fail when this many players are already logged in
+    pub fn with_max_players(self, max_players: usize) -> Self {
+        if let Some((simulation, _)) = self.simulation.lock().unwrap().as_mut() {
+            simulation.max_players = Some(max_players);
+        }
+        self
+    }
+
+    /// Sets the message of the day, which is shown to players looking for a server to join
+    pub fn with_motd(self, motd: String) -> Self {
+        if let Some((simulation, _)) = self.simulation.lock().unwrap().as_mut() {
+            simulation.motd = motd;
+        }
+        self
+    }
+
+    pub async fn player_count(&self) -> usize {
+        let (reply, response) = oneshot::channel();
+        self.send(Command::GetPlayerCount { reply });
+        response.await.unwrap_or(0)
+    }
+
+    /// Runs a command like the ones players can run, but as the server. The text is split into
+    /// words, except for chat messages. Returns what the command printed.
+    pub async fn run_command(&self, text: &str) -> Vec<String> {
+        let (command, rest) = text.trim().split_once(' ').unwrap_or((text.trim(), ""));
+        let args = match command {
+            "chat" => vec![rest.trim().to_string()],
+            _ => rest.split_whitespace().map(str::to_string).collect(),
+        };
+
+        let (reply, response) = oneshot::channel();
+        self.send(Command::RunAsServer {
+            command: command.to_string(),
+            args,
+            reply,
+        });
+        response.await.unwrap_or_default()
+    }
+
+    /// Returns a receiver of every message sent to all players, like chat messages and players
+    /// logging in, formatted as lines of text
+    pub fn follow_output(&self) -> broadcast::Receiver<String> {
+        self.output.subscribe()
+    }
+
+    /// How well the server is keeping up with its tick rate
+    pub async fn tick_status(&self) -> Option<TickStatus> {
+        let (reply, response) = oneshot::channel();
+        self.send(Command::GetTickStatus { reply });
+        response.await.ok()
+    }
+
+    /// Counters for how the server is doing, which can be served with `metrics::serve`
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// Makes sure that a player logging in is who they claim to be. Returns the name to use.
+    ///
+    /// Offline servers trust the client, while online servers require a valid token. Checking a
+    /// token can take a while, like when an auth service is asked, so it is done on a blocking
+    /// thread instead of in the simulation.
+    async fn authenticate(
         &self,
+        id: Uuid,
+        name: String,
+        token: Option<Vec<u8>>,
+    ) -> Result<String, String> {
+        if !self.is_online {
+            return Ok(name);
+        }
+        let Some(verifier) = self.token_verifier.clone() else {
+            return Err("server has no way of verifying players".to_string());
+        };
+        let Some(token) = token else {
+            return Err("server requires authentication".to_string());
+        };
+
+        let player = tokio::task::spawn_blocking(move || verifier.verify(&token))
+            .await
+            .map_err(|_| "token verification failed".to_string())?
+            .map_err(|err| format!("invalid session token: {err}"))?;
+        if player.id != id {
+            return Err("session token belongs to another player".to_string());
+        }
+        Ok(player.name)
+    }
+
+    fn send(&self, command: Command) {
+        self.metrics.queued_commands.fetch_add(1, Ordering::Relaxed);
+        // the simulation only stops when the game state is dropped
+        let _ = self.commands.send(command);
+    }
+
+    /// Runs the simulation: executes commands as they arrive and ticks at the tick rate. Ticks that
+    /// are late are run in a batch to catch up.
+    ///
+    /// This can only be called once, and nothing gets handled before it has been called.
+    pub async fn run_ticks(&self) {
+        let (mut simulation, mut commands) = self
+            .simulation
+            .lock()
+            .unwrap()
+            .take()
+            .expect("the simulation is already running");
+
+        simulation.tick_clock.restart(Instant::now());
+
+        let next_tick = |simulation: &Simulation| {
+            tokio::time::Instant::from_std(simulation.tick_clock.next_tick())
+        };
+        let tick_timer = tokio::time::sleep_until(next_tick(&simulation));
+        tokio::pin!(tick_timer);
+
+        loop {
+            tokio::select! {
+                command = commands.recv() => {
+                    let Some(command) = command else {
+                        return;
+                    };
+                    // the commands that are already waiting are executed right away, which is a
+                    // lot cheaper than going through the select for each of them
+                    let mut next = Some(command);
+                    let mut executed = 0;
+                    while let Some(command) = next {
+                        self.metrics.queued_commands.fetch_sub(1, Ordering::Relaxed);
+                        simulation.execute(command);
+                        executed += 1;
+                        next = if executed < MAX_COMMANDS_PER_BATCH {
+                            commands.try_recv().ok()
+                        } else {
+                            None
+                        };
+                    }
+                }
+                // ticks go on while shutting down, since subscribed clients only learn about the
+                // shutdown from their pushes
+                _ = &mut tick_timer => {
+                    let due = simulation.tick_clock.due_ticks(Instant::now());
+                    for _ in 0..due.run {
+                        let pushes = simulation.tick();
+                        self.queue_pushes(pushes);
+                    }
+                    tick_timer.as_mut().reset(next_tick(&simulation));
+                }
+            }
+            self.done.store(
+                simulation.is_shutting_down && simulation.players.is_empty(),
+                Ordering::Release,
+            );
+        }
+    }
+
+    fn pop_push(&self) -> Option<Push> {
+        let mut pushes = self.pushes.lock().unwrap();
+        let push = pushes.pop_front();
+        self.metrics
+            .queued_pushes
+            .store(pushes.len() as u64, Ordering::Relaxed);
+        push
+    }
+
+    fn queue_pushes(&self, new_pushes: Vec<Push>) {
+        if new_pushes.is_empty() {
+            return;
+        }
+
+        let mut pushes = self.pushes.lock().unwrap();
+        pushes.extend(new_pushes);
+        if pushes.len() > MAX_QUEUED_PUSHES {
+            let mut excess = pushes.len() - MAX_QUEUED_PUSHES;
+            pushes.retain(|push| {
+                let drop = excess > 0 && !push.has_messages;
+                if drop {
+                    excess -= 1;
+                }
+                !drop
+            });
+        }
+        self.metrics
+            .queued_pushes
+            .store(pushes.len() as u64, Ordering::Relaxed);
+        self.push_added.notify_one();
+    }
+}
+
+impl Simulation {
+    fn execute(&mut self, command: Command) {
+        match command {
+            Command::Handle {
+                client_id,
+                packet,
+                reply,
+                span,
+            } => {
+                let _span = span.enter();
+                // the client might be gone already, but the packet still has to be handled
+                let _ = reply.send(self.handle(client_id, packet));
+            }
+            Command::Login {
+                client_id,
+                id,
+                name,
+                reply,
+                span,
+            } => {
+                let _span = span.enter();
+                let _ = reply.send(Some(self.login(client_id, id, name)));
+            }
+            Command::ClientDisconnected { client_id } => {
+                self.remove_player(client_id, "lost connection");
+            }
+            Command::Shutdown => self.is_shutting_down = true,
+            Command::GetPlayerCount { reply } => {
+                let _ = reply.send(self.players.len());
+            }
+            Command::GetTickStatus { reply } => {
+                let _ = reply.send(self.tick_status());
+            }
+            Command::RunAsServer {
+                command,
+                args,
+                reply,
+            } => {
+                let output = self.run_command(ServerMessageSender::Server, &command, &args);
+                let _ = reply.send(output);
+            }
+        }
+    }
+
+    fn access_player_state<R>(
+        &mut self,
         client_id: u64,
         access: impl FnOnce(&mut PlayerConnectionState) -> R,
     ) -> Option<R> {
-        let mut players = self.players.lock().unwrap();
-        players.get_mut(&client_id).map(access)
+        self.players.get_mut(&client_id).map(access)
     }
 
-    pub async fn run_ticks(&self) {
-        let mut interval = tokio::time::interval(Duration::from_millis(1000 / 60));
-        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay); // Skip would be fine too
+    /// Adds a player that has been authenticated, unless they cannot join right now
+    fn login(&mut self, client_id: u64, id: Uuid, name: Result<String, String>) -> nbt::Tag {
+        let is_full = self
+            .max_players
+            .is_some_and(|max| !self.players.contains_key(&client_id) && self.players.len() >= max);
+        if self.is_shutting_down {
+            LoginResponse::failure("server is shutting down").into()
+            // TODO: handle more cases
+        } else if is_full {
+            LoginResponse::failure("server is full").into()
+        } else {
+            let name = match name {
+                Ok(name) => name,
+                Err(error) => {
+                    info!(client_id, %id, error, "Login failed");
+                    return LoginResponse::failure(&error).into();
+                }
+            };
+            info!(client_id, %id, name, "Player logged in");
+
+            self.broadcast(ServerMessage {
+                text: format!("{} logged in", name),
+                sender: ServerMessageSender::Server,
+            });
+            self.players.insert(
+                client_id,
+                PlayerConnectionState {
+                    player: Player::new(
+                        id,
+                        name,
+                        Inventory::new(), // TODO: load from disk
+                    ),
+                    messages_to_send: VecDeque::new(),
+                    mouse_movement: Vec2::new(0.0, 0.0),
+                    pressed_keys: PlayerInput::empty(),
+                    analog_input: AnalogInput::default(),
+                    received_input: None,
+                    processed_input: None,
+                    subscription: None,
+                },
+            );
+            self.metrics
+                .players
+                .store(self.players.len() as u64, Ordering::Relaxed);
+            LoginResponse::success().into()
+        }
+    }
+
+    /// Removes a player and tells everyone else why, like "Alice logged out"
+    fn remove_player(&mut self, client_id: u64, reason: &str) {
+        let Some(removed) = self.players.remove(&client_id) else {
+            return;
+        };
+        info!(client_id, name = removed.player.name, reason, "Player left");
+        self.metrics
+            .players
+            .store(self.players.len() as u64, Ordering::Relaxed);
 
-        while !{ *self.is_shutting_down.lock().unwrap() } {
-            interval.tick().await;
-            self.tick();
+        self.broadcast(ServerMessage {
+            text: format!("{} {}", removed.player.name, reason),
+            sender: ServerMessageSender::Server,
+        });
+    }
+
+    fn tick_status(&mut self) -> TickStatus {
+        let now = Instant::now();
+        let ticks_behind = self.tick_clock.ticks_behind(now);
+        self.tick_stats.status(now, ticks_behind)
+    }
+
+    /// Advances the world by one tick, and returns the updates to push to subscribed clients
+    fn tick(&mut self) -> Vec<Push> {
+        self.ticks += 1;
+        let _span = info_span!("tick", number = self.ticks).entered();
+        let start = Instant::now();
+        for (_, p) in self.players.iter_mut() {
+            input::update_player(
+                &mut p.player,
+                p.mouse_movement,
+                p.pressed_keys,
+                p.analog_input,
+            );
+            p.mouse_movement = Vec2::new(0.0, 0.0);
+            p.processed_input = p.received_input;
         }
+        let pushes = self.push_updates();
+
+        let now = Instant::now();
+        self.tick_stats.record(now - start, now);
+        self.metrics.tick_finished(now - start);
+        pushes
     }
 
-    fn tick(&self) {
-        {
-            let mut players = self.players.lock().unwrap();
-            for (_, p) in players.iter_mut() {
-                input::update_player(
-                    &mut p.player,
-                    p.mouse_movement,
-                    &p.pressed_keys
-                        .iter()
-                        .map(|s| s.as_str())
-                        .collect::<Vec<_>>(),
-                );
-                p.mouse_movement = Vec2::new(0.0, 0.0);
-            }
+    /// Creates an update for every subscribed client, with everything that happened this tick
+    fn push_updates(&mut self) -> Vec<Push> {
+        let server_shutting_down = self.is_shutting_down;
+
+        let mut pushes = Vec::new();
+        for (&client_id, p) in self.players.iter_mut() {
+            let Some(subscription) = &mut p.subscription else {
+                continue;
+            };
+            let seq = subscription.next_seq;
+            subscription.next_seq += 1;
+
+            let new_messages = p.messages_to_send.drain(..).collect::<Vec<_>>();
+            let has_messages = !new_messages.is_empty();
+            let update = PushUpdate {
+                seq,
+                player: GetPlayerStateResponse {
+                    player: &p.player,
+                    last_processed_input: p.processed_input,
+                },
+                events: GetEventsResponse {
+                    server_shutting_down,
+                    new_messages,
+                },
+                world_loading_events: GetWorldLoadingEventsResponse {},
+            };
+            pushes.push(Push {
+                client_id,
+                has_messages,
+                update: update.into(),
+            });
         }
+        pushes
     }
-}
 
-impl RequestHandler for GameState {
-    fn handle(&self, client_id: u64, packet: NetworkPacket) -> Option<nbt::Tag> {
-        match packet {
-            NetworkPacket::Login { id, name } => {
-                let is_shutting_down = { *self.is_shutting_down.lock().unwrap() };
-                if is_shutting_down {
-                    Some(LoginResponse::failure("server is shutting down").into())
-                    // TODO: handle more cases
-                } else {
-                    let message = ServerMessage {
-                        text: format!("{} logged in", name),
-                        sender: ServerMessageSender::Server,
-                    };
+    /// Sends a message to every player, and to everyone following the server output
+    fn broadcast(&mut self, message: ServerMessage) {
+        // nobody might be following the output, which is fine
+        let _ = self.output.send(message.to_string());
+        for (_, p) in self.players.iter_mut() {
+            p.messages_to_send.push_back(message.clone());
+        }
+    }
 
-                    let mut players = self.players.lock().unwrap();
-                    for (_, p) in players.iter_mut() {
-                        p.messages_to_send.push_back(message.clone());
+    /// A summary of how the server is doing, see `Metrics` for the details
+    fn status(&mut self) -> Vec<String> {
+        let tick_status = self.tick_status();
+        let metrics = &self.metrics;
+        let get = |value: &AtomicU64| value.load(Ordering::Relaxed);
+        let max_players = match self.max_players {
+            Some(max) => max.to_string(),
+            None => "unlimited".to_string(),
+        };
+        vec![
+            format!("Players: {}/{max_players}", self.players.len()),
+            format!(
+                "Ticks: {:.1} per second, {:.2} ms on average, {} behind",
+                tick_status.tps,
+                tick_status.mean_tick_time.as_secs_f64() * 1000.0,
+                tick_status.ticks_behind
+            ),
+            format!(
+                "Packets: {} received, {} could not be decoded",
+                metrics.packets_received(),
+                get(&metrics.decode_errors)
+            ),
+            format!(
+                "Traffic: {:.1} kB in, {:.1} kB out",
+                get(&metrics.bytes_received) as f64 / 1000.0,
+                get(&metrics.bytes_sent) as f64 / 1000.0
+            ),
+            format!("Chunk loads: {}", get(&metrics.chunk_loads)),
+            format!(
+                "Queues: {} commands, {} pushes, {} responses",
+                get(&metrics.queued_commands),
+                get(&metrics.queued_pushes),
+                get(&metrics.pending_responses)
+            ),
+        ]
+    }
+
+    /// Runs a command from a player or from the server console, and returns what the sender
+    /// should be told
+    fn run_command(
+        &mut self,
+        sender: ServerMessageSender,
+        command: &str,
+        args: &[String],
+    ) -> Vec<String> {
+        let is_server = matches!(sender, ServerMessageSender::Server);
+
+        match command {
+            "chat" => {
+                if args.is_empty() {
+                    return vec!["Usage: chat <message>".to_string()];
+                }
+                self.broadcast(ServerMessage {
+                    text: args.join(" "),
+                    sender,
+                });
+                Vec::new()
+            }
+            "tps" => {
+                let status = self.tick_status();
+                vec![format!(
+                    "TPS: {:.1}, mean tick time: {:.2} ms, ticks behind: {}",
+                    status.tps,
+                    status.mean_tick_time.as_secs_f64() * 1000.0,
+                    status.ticks_behind
+                )]
+            }
+            "status" => self.status(),
+            "list" => {
+                let mut names: Vec<_> = self.players.values().map(|p| &p.player.name).collect();
+                names.sort();
+                let names = names.iter().map(|n| n.as_str()).collect::<Vec<_>>();
+                vec![format!(
+                    "{} players online: {}",
+                    names.len(),
+                    names.join(", ")
+                )]
+            }
+            "kick" if is_server => {
+                let [name] = args else {
+                    return vec!["Usage: kick <player>".to_string()];
+                };
+                let client_id = self
+                    .players
+                    .iter()
+                    .find(|(_, p)| &p.player.name == name)
+                    .map(|(&id, _)| id);
+                match client_id {
+                    Some(client_id) => {
+                        self.remove_player(client_id, "was kicked");
+                        vec![format!("Kicked {name}")]
                     }
-                    players.insert(
-                        client_id,
-                        PlayerConnectionState {
-                            player: Player::new(
-                                id,
-                                name,
-                                Inventory::new(), // TODO: load from disk
-                            ),
-                            messages_to_send: VecDeque::new(),
-                            mouse_movement: Vec2::new(0.0, 0.0),
-                            pressed_keys: Vec::new(),
-                        },
-                    );
-                    Some(LoginResponse::success().into())
+                    None => vec![format!("{name} is not online")],
                 }
             }
-            NetworkPacket::Logout => {
-                let message = self.access_player_state(client_id, |p| ServerMessage {
-                    text: format!("{} logged out", p.player.name),
-                    sender: ServerMessageSender::Server,
-                })?;
-
-                let mut players = self.players.lock().unwrap();
-                players.remove(&client_id);
+            "kick" => vec!["Only the server can kick players".to_string()],
+            "spawn" | "kill" => vec![format!("{command} is not implemented yet")],
+            _ => vec![format!("Unknown command: {command}")],
+        }
+    }
 
-                for (_, p) in players.iter_mut() {
-                    p.messages_to_send.push_back(message.clone());
-                }
+    fn handle(&mut self, client_id: u64, packet: NetworkPacket) -> Option<nbt::Tag> {
+        match packet {
+            NetworkPacket::Login { .. } => {
+                // logins are authenticated first, and then arrive as `Command::Login`
+                Some(LoginResponse::failure("login was not authenticated").into())
+            }
+            NetworkPacket::Logout => {
+                self.remove_player(client_id, "logged out");
 
                 None
             }
+            NetworkPacket::Status { time } => Some(
+                StatusResponse {
+                    motd: &self.motd,
+                    world_name: &self.world_info.world_name,
+                    players: self.players.len(),
+                    max_players: self.max_players,
+                    time,
+                }
+                .into(),
+            ),
             NetworkPacket::GetWorldInfo => Some(
                 GetWorldInfoResponse {
                     info: &self.world_info,
                 }
                 .into(),
             ),
-            NetworkPacket::LoadColumnData { coords } => Some(nbt::MapTag::new().build()),
+            NetworkPacket::LoadColumnData { coords: _ } => {
+                self.metrics.chunk_loads.fetch_add(1, Ordering::Relaxed);
+                Some(nbt::MapTag::new().build())
+            }
             NetworkPacket::GetPlayerState => self.access_player_state(client_id, |p| {
-                GetPlayerStateResponse { player: &p.player }.into()
+                GetPlayerStateResponse {
+                    player: &p.player,
+                    last_processed_input: p.processed_input,
+                }
+                .into()
             }),
             NetworkPacket::GetEvents => {
                 let new_messages = self.access_player_state(client_id, |p| {
@@ -169,14 +741,28 @@ impl RequestHandler for GameState {
                 Some(
                     GetEventsResponse {
                         // TODO: make proper shutdown feature
-                        server_shutting_down: *self.is_shutting_down.lock().unwrap(),
+                        server_shutting_down: self.is_shutting_down,
                         new_messages,
                     }
                     .into(),
                 )
             }
-            NetworkPacket::GetWorldLoadingEvents { max_chunks_to_load } => {
-                Some(GetWorldLoadingEventsResponse {}.into())
+            NetworkPacket::GetWorldLoadingEvents {
+                max_chunks_to_load: _,
+            } => Some(GetWorldLoadingEventsResponse {}.into()),
+            NetworkPacket::Subscribe {
+                max_chunks_to_load: _, // TODO: use it once chunks are loaded on the server
+            } => {
+                self.access_player_state(client_id, |p| {
+                    if p.subscription.is_none() {
+                        p.subscription = Some(Subscription { next_seq: 0 });
+                    }
+                });
+                None
+            }
+            NetworkPacket::Unsubscribe => {
+                self.access_player_state(client_id, |p| p.subscription = None);
+                None
             }
             NetworkPacket::PlayerRightClicked => {
                 // TODO: player right clicked
@@ -211,41 +797,38 @@ impl RequestHandler for GameState {
                     .into()
                 })
             }
-            NetworkPacket::PlayerMovedMouse { distance: d } => {
+            NetworkPacket::PlayerMovedMouse { distance: d, input } => {
                 self.access_player_state(client_id, |p| {
                     let m = p.mouse_movement;
                     p.mouse_movement = Vec2::new(m.x + d.x, m.y + d.y);
+                    p.input_received(input);
                 })?;
                 None
             }
-            NetworkPacket::PlayerPressedKeys { keys } => {
+            NetworkPacket::PlayerPressedKeys { keys, input } => {
                 self.access_player_state(client_id, |p| {
                     p.pressed_keys = keys;
+                    p.input_received(input);
+                })?;
+                None
+            }
+            NetworkPacket::PlayerMovedAnalog { analog, input } => {
+                self.access_player_state(client_id, |p| {
+                    p.analog_input = analog;
+                    p.input_received(input);
                 })?;
                 None
             }
             NetworkPacket::RunCommand { command, args } => {
                 let sender_name = self.access_player_state(client_id, |p| p.player.name.clone())?;
+                let sender = ServerMessageSender::Player { name: sender_name };
 
-                match command.as_str() {
-                    "chat" => {
-                        if args.len() != 1 {
-                            println!("Wrong number of arguments to chat command: {}", args.len());
-                        } else {
-                            let message = &args[0];
-                            for (_, p) in self.players.lock().unwrap().iter_mut() {
-                                p.messages_to_send.push_back(ServerMessage {
-                                    text: message.to_string(),
-                                    sender: ServerMessageSender::Player {
-                                        name: sender_name.clone(),
-                                    },
-                                });
-                            }
-                        }
-                    }
-                    "spawn" => todo!(),
-                    "kill" => todo!(),
-                    _ => println!("Received unknown command: {command}"),
+                for line in self.run_command(sender, &command, &args) {
+                    let message = ServerMessage {
+                        text: line,
+                        sender: ServerMessageSender::Server,
+                    };
+                    self.access_player_state(client_id, |p| p.messages_to_send.push_back(message));
                 }
 
                 None
@@ -254,21 +837,104 @@ impl RequestHandler for GameState {
     }
 }
 
+impl RequestHandler for GameState {
+    fn handle(
+        &self,
+        client_id: u64,
+        packet: NetworkPacket,
+    ) -> impl Future<Output = Option<nbt::Tag>> + Send {
+        let (reply, response) = oneshot::channel();
+        let span = Span::current();
+        let login = match packet {
+            // the token is checked before the login reaches the simulation, so that the simulation
+            // never waits for it. Packets sent before the login response can overtake it.
+            NetworkPacket::Login { id, name, token } if self.is_online => Some(async move {
+                let name = self.authenticate(id, name, token).await;
+                self.send(Command::Login {
+                    client_id,
+                    id,
+                    name,
+                    reply,
+                    span,
+                });
+            }),
+            NetworkPacket::Login { id, name, .. } => {
+                self.send(Command::Login {
+                    client_id,
+                    id,
+                    name: Ok(name),
+                    reply,
+                    span,
+                });
+                None
+            }
+            // the command is sent right away, so that packets are handled in the order they arrived
+            packet => {
+                self.send(Command::Handle {
+                    client_id,
+                    packet,
+                    reply,
+                    span,
+                });
+                None
+            }
+        };
+        async move {
+            if let Some(login) = login {
+                login.await;
+            }
+            response.await.ok().flatten()
+        }
+    }
+
+    fn client_disconnected(&self, client_id: u64) {
+        self.send(Command::ClientDisconnected { client_id });
+    }
+
+    fn network_metrics(&self) -> Option<&Metrics> {
+        Some(&self.metrics)
+    }
+
+    async fn next_push(&self) -> (u64, nbt::Tag) {
+        loop {
+            if let Some(push) = self.pop_push() {
+                return (push.client_id, push.update);
+            }
+            self.push_added.notified().await;
+        }
+    }
+}
+
 impl GracefulShutdown for GameState {
     fn initiate(&self) {
-        let mut is_shutting_down = self.is_shutting_down.lock().unwrap();
-        *is_shutting_down = true;
+        self.send(Command::Shutdown);
     }
 
     fn done(&self) -> bool {
-        let is_shutting_down = { *self.is_shutting_down.lock().unwrap() };
-        if !is_shutting_down {
-            return false;
-        }
-        let has_no_players = { self.players.lock().unwrap().is_empty() };
-        if !has_no_players {
-            return false;
-        }
-        true
+        self.done.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::{nbt, world::WorldInfo};
+
+    use super::{GameState, MAX_QUEUED_PUSHES, Push};
+
+    #[test]
+    fn pushes_with_messages_are_not_dropped() {
+        let state = GameState::create(false, WorldInfo::default());
+        let push = |client_id, has_messages| Push {
+            client_id,
+            has_messages,
+            update: nbt::MapTag::new().build(),
+        };
+
+        state.queue_pushes(vec![push(1, true), push(2, false)]);
+        state.queue_pushes((0..MAX_QUEUED_PUSHES).map(|_| push(3, false)).collect());
+
+        assert_eq!(state.pop_push().map(|p| p.client_id), Some(1));
+        assert_eq!(state.pop_push().map(|p| p.client_id), Some(3));
+        assert_eq!(state.pushes.lock().unwrap().len(), MAX_QUEUED_PUSHES - 2);
     }
 }
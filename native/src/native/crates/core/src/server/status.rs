@@ -0,0 +1,186 @@
+//! Asking a server what it is and how many are playing, without logging in
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::server::nbt;
+use crate::zmq::ClientSocket;
+
+/// What a server answers to the `status` packet, like a server list would show it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerStatus {
+    pub motd: String,
+    /// Clients should only join servers with the same protocol version as themselves
+    pub protocol_version: u16,
+    pub world_name: String,
+    pub players: u32,
+    /// `None` if there is no limit
+    pub max_players: Option<u32>,
+    /// How long it took to get the answer
+    pub latency: Duration,
+}
+
+impl ServerStatus {
+    fn decode(tag: nbt::Tag, latency: Duration) -> Result<(Self, Option<i64>), String> {
+        let tag = match tag {
+            nbt::Tag::Map(items) => items.into_iter().collect::<HashMap<_, _>>(),
+            _ => return Err("status was not a map tag")?,
+        };
+
+        let motd = match tag.get("motd").ok_or("missing motd field")? {
+            nbt::Tag::String(v) => v.clone(),
+            _ => return Err("wrong type for motd field")?,
+        };
+        let protocol_version = match tag
+            .get("protocol_version")
+            .ok_or("missing protocol_version field")?
+        {
+            nbt::Tag::Short(v) => *v as u16,
+            _ => return Err("wrong type for protocol_version field")?,
+        };
+        let world_name = match tag.get("world_name").ok_or("missing world_name field")? {
+            nbt::Tag::String(v) => v.clone(),
+            _ => return Err("wrong type for world_name field")?,
+        };
+        let players = match tag.get("players").ok_or("missing players field")? {
+            nbt::Tag::Int(v) => *v as u32,
+            _ => return Err("wrong type for players field")?,
+        };
+        let max_players = match tag.get("max_players") {
+            None => None,
+            Some(nbt::Tag::Int(v)) => Some(*v as u32),
+            _ => return Err("wrong type for max_players field")?,
+        };
+        let time = match tag.get("time") {
+            None => None,
+            Some(nbt::Tag::Long(v)) => Some(*v),
+            _ => return Err("wrong type for time field")?,
+        };
+
+        let status = ServerStatus {
+            motd,
+            protocol_version,
+            world_name,
+            players,
+            max_players,
+            latency,
+        };
+        Ok((status, time))
+    }
+}
+
+/// Connects to a server, asks for its status and disconnects again. Fails if there is no answer
+/// within the timeout.
+///
+/// This uses a new client id, so it does not disturb a connection the caller might already have.
+/// Servers that require encryption cannot be queried this way.
+pub async fn query_status(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> Result<ServerStatus, String> {
+    let mut id = [0; 8];
+    getrandom::fill(&mut id).map_err(|err| format!("failed to create client id: {err}"))?;
+    let client_id = u64::from_le_bytes(id).to_string();
+
+    let socket = Arc::new(ClientSocket::new(client_id.into_bytes()));
+    let result = tokio::time::timeout(timeout, query(&socket, host, port, timeout))
+        .await
+        .unwrap_or_else(|_| Err("server did not answer in time".to_string()));
+    socket.close();
+    result
+}
+
+async fn query(
+    socket: &Arc<ClientSocket>,
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> Result<ServerStatus, String> {
+    socket
+        .connect(host, port)
+        .await
+        .map_err(|err| format!("failed to connect: {err}"))?;
+    tokio::spawn(socket.clone().run_receiver());
+
+    // the time is sent back, so an answer to an earlier query cannot be mistaken for this one
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64;
+    let request = nbt::MapTag::new()
+        .set(
+            "status",
+            nbt::MapTag::new().set("time", nbt::Tag::Long(time)).build(),
+        )
+        .build();
+
+    let sent = Instant::now();
+    socket
+        .send(request.to_binary())
+        .await
+        .map_err(|err| format!("failed to send status request: {err}"))?;
+    loop {
+        let response = socket
+            .receive_timeout(timeout)
+            .await
+            .ok_or("server did not answer")?;
+        let latency = sent.elapsed();
+
+        let (_, tag) = nbt::Tag::from_binary(&response)?;
+        let (status, echoed_time) = ServerStatus::decode(tag, latency)?;
+        if echoed_time == Some(time) {
+            return Ok(status);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use crate::server::{GameServer, GameState, PROTOCOL_VERSION, WorldInfo};
+    use crate::zmq::endpoint_port;
+
+    #[tokio::test]
+    async fn status_can_be_queried_without_logging_in() {
+        let state = Arc::new(
+            GameState::create(false, WorldInfo::default())
+                .with_motd("Welcome".to_string())
+                .with_max_players(10),
+        );
+        let server = Arc::new(
+            GameServer::start("tcp://127.0.0.1:0", state.clone())
+                .await
+                .unwrap(),
+        );
+        let port = endpoint_port(server.local_endpoint().unwrap()).unwrap();
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.run_receiver().await }
+        });
+        tokio::spawn({
+            let state = state.clone();
+            async move { state.run_ticks().await }
+        });
+        let mut output = state.follow_output();
+
+        let status = super::query_status("127.0.0.1", port, Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        assert_eq!(status.motd, "Welcome");
+        assert_eq!(status.protocol_version, PROTOCOL_VERSION);
+        assert_eq!(status.world_name, "World");
+        assert_eq!(status.players, 0);
+        assert_eq!(status.max_players, Some(10));
+        assert!(status.latency < Duration::from_secs(5));
+
+        // asking did not make anyone join
+        assert_eq!(state.player_count().await, 0);
+        assert!(output.try_recv().is_err());
+    }
+}
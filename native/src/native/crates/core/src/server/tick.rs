@@ -0,0 +1,226 @@
+//! Scheduling of server ticks, and statistics about how long they take.
+
+use std::{
+    collections::VecDeque,
+    num::NonZeroU32,
+    time::{Duration, Instant},
+};
+
+use tracing::warn;
+
+pub const DEFAULT_TICK_RATE: NonZeroU32 = NonZeroU32::new(60).unwrap();
+
+/// If the server falls further behind than this it gives up catching up, and skips the ticks
+const MAX_CATCH_UP_TICKS: u32 = 10;
+
+/// How often the server complains about not keeping up
+const WARNING_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The upper bounds of the buckets in the tick duration histogram. Ticks taking longer end up in a
+/// last bucket without a bound.
+pub const HISTOGRAM_BUCKETS: [Duration; 8] = [
+    Duration::from_millis(1),
+    Duration::from_millis(2),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(20),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+];
+
+/// Decides when to tick. If ticks take too long the ticks that are due get run in a batch, so the
+/// simulation does not drift from real time.
+pub(crate) struct TickClock {
+    period: Duration,
+    next_tick: Instant,
+    last_warning: Option<Instant>,
+}
+
+/// The ticks to run now, returned by `TickClock::due_ticks`
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct DueTicks {
+    pub run: u32,
+    /// Ticks that were so far behind that they will never be run
+    pub skipped: u64,
+}
+
+impl TickClock {
+    pub fn new(ticks_per_second: NonZeroU32, now: Instant) -> Self {
+        Self {
+            period: Duration::from_secs(1) / ticks_per_second.get(),
+            next_tick: now,
+            last_warning: None,
+        }
+    }
+
+    /// Makes the next tick run right away, like when the tick loop starts
+    pub fn restart(&mut self, now: Instant) {
+        self.next_tick = now;
+    }
+
+    /// When the next tick should run
+    pub fn next_tick(&self) -> Instant {
+        self.next_tick
+    }
+
+    /// How many ticks should have been run by now but have not
+    pub fn ticks_behind(&self, now: Instant) -> u64 {
+        if now < self.next_tick {
+            return 0;
+        }
+        ((now - self.next_tick).as_nanos() / self.period.as_nanos()) as u64 + 1
+    }
+
+    /// Returns how many ticks to run now, and schedules the next one after them
+    pub fn due_ticks(&mut self, now: Instant) -> DueTicks {
+        let behind = self.ticks_behind(now);
+        if behind <= MAX_CATCH_UP_TICKS as u64 {
+            self.next_tick += self.period * behind as u32;
+            return DueTicks {
+                run: behind as u32,
+                skipped: 0,
+            };
+        }
+
+        let skipped = behind - MAX_CATCH_UP_TICKS as u64;
+        if self
+            .last_warning
+            .is_none_or(|t| now.duration_since(t) >= WARNING_INTERVAL)
+        {
+            self.last_warning = Some(now);
+            warn!(
+                behind_ms = (now - self.next_tick).as_millis() as u64,
+                skipped, "Can't keep up! Skipping ticks"
+            );
+        }
+        self.next_tick = now + self.period;
+        DueTicks {
+            run: MAX_CATCH_UP_TICKS,
+            skipped,
+        }
+    }
+}
+
+/// Keeps track of how long ticks take
+pub(crate) struct TickStats {
+    histogram: [u64; HISTOGRAM_BUCKETS.len() + 1],
+    /// When the ticks of the last second ended, and how long they took
+    recent: VecDeque<(Instant, Duration)>,
+}
+
+/// How the tick loop is doing, see `GameState::tick_status`
+#[derive(Debug, Clone)]
+pub struct TickStatus {
+    /// Ticks run during the last second
+    pub tps: f64,
+    /// The mean duration of the ticks run during the last second
+    pub mean_tick_time: Duration,
+    pub ticks_behind: u64,
+    /// The number of ticks that took at most as long as the bucket bound, for all buckets in
+    /// `HISTOGRAM_BUCKETS` followed by the rest of the ticks
+    pub histogram: Vec<u64>,
+}
+
+impl TickStats {
+    pub fn new() -> Self {
+        Self {
+            histogram: [0; HISTOGRAM_BUCKETS.len() + 1],
+            recent: VecDeque::new(),
+        }
+    }
+
+    pub fn record(&mut self, duration: Duration, now: Instant) {
+        let bucket = HISTOGRAM_BUCKETS
+            .iter()
+            .position(|&bound| duration <= bound)
+            .unwrap_or(HISTOGRAM_BUCKETS.len());
+        self.histogram[bucket] += 1;
+
+        self.recent.push_back((now, duration));
+        self.forget_old(now);
+    }
+
+    fn forget_old(&mut self, now: Instant) {
+        while let Some(&(t, _)) = self.recent.front()
+            && now.duration_since(t) > Duration::from_secs(1)
+        {
+            self.recent.pop_front();
+        }
+    }
+
+    pub fn status(&mut self, now: Instant, ticks_behind: u64) -> TickStatus {
+        self.forget_old(now);
+
+        let total: Duration = self.recent.iter().map(|&(_, d)| d).sum();
+        TickStatus {
+            tps: self.recent.len() as f64,
+            mean_tick_time: total
+                .checked_div(self.recent.len() as u32)
+                .unwrap_or_default(),
+            ticks_behind,
+            histogram: self.histogram.to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        num::NonZeroU32,
+        time::{Duration, Instant},
+    };
+
+    use super::{DueTicks, HISTOGRAM_BUCKETS, TickClock, TickStats};
+
+    #[test]
+    fn missed_ticks_are_caught_up_in_batches() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut clock = TickClock::new(NonZeroU32::new(100).unwrap(), start);
+
+        assert_eq!(clock.due_ticks(start), DueTicks { run: 1, skipped: 0 });
+        assert_eq!(clock.next_tick(), start + ms(10));
+        assert_eq!(clock.ticks_behind(start + ms(5)), 0);
+
+        // a slow tick means the next few are late, but they are still run
+        assert_eq!(clock.ticks_behind(start + ms(35)), 3);
+        assert_eq!(
+            clock.due_ticks(start + ms(35)),
+            DueTicks { run: 3, skipped: 0 }
+        );
+        assert_eq!(clock.next_tick(), start + ms(40));
+
+        // too far behind to catch up
+        assert_eq!(
+            clock.due_ticks(start + ms(1000)),
+            DueTicks {
+                run: 10,
+                skipped: 87
+            }
+        );
+        assert_eq!(clock.next_tick(), start + ms(1010));
+    }
+
+    #[test]
+    fn stats_cover_the_last_second() {
+        let start = Instant::now();
+        let mut stats = TickStats::new();
+        for i in 0..15 {
+            stats.record(
+                Duration::from_millis(4),
+                start + Duration::from_millis(i * 100),
+            );
+        }
+        stats.record(Duration::from_secs(1), start + Duration::from_millis(1500));
+
+        // the ticks from 500 ms and on are recent
+        let status = stats.status(start + Duration::from_millis(1500), 0);
+        assert_eq!(status.tps, 11.0);
+        assert_eq!(status.mean_tick_time, Duration::from_millis(1040) / 11);
+
+        assert_eq!(status.histogram.len(), HISTOGRAM_BUCKETS.len() + 1);
+        assert_eq!(status.histogram[2], 15);
+        assert_eq!(status.histogram[HISTOGRAM_BUCKETS.len()], 1);
+    }
+}
@@ -1,17 +1,115 @@
-use std::{collections::HashMap, f64::consts::PI};
+use std::{
+    collections::HashMap,
+    f64::consts::PI,
+    fmt::{self, Display},
+};
 
 use glam::DVec3;
 use uuid::Uuid;
 
+use crate::server::{nbt, world_config::WorldConfig};
+
 const SQRT_3: f64 = 1.732050807568877293527446341505872367_f64;
 
+/// The settings of a world, stored in its `world.dat`
+#[derive(Debug, Clone, PartialEq)]
 pub struct WorldInfo {
+    /// The version of the save format. Older worlds are upgraded when they are opened, see
+    /// `migration`.
     pub version: u16,
     pub world_name: String,
     pub world_size: CylinderSize,
-    pub _gen: WorldGenSettings,
+    pub gen_settings: WorldGenSettings,
+}
+
+impl WorldInfo {
+    /// The version of the save format written by this code
+    pub const LATEST_VERSION: u16 = 2;
+
+    pub fn new(world_name: &str, world_size: CylinderSize, gen_settings: WorldGenSettings) -> Self {
+        WorldInfo {
+            version: WorldInfo::LATEST_VERSION,
+            world_name: world_name.to_string(),
+            world_size,
+            gen_settings,
+        }
+    }
+
+    /// Encodes the settings like they are stored in `world.dat`
+    pub fn to_nbt(&self) -> nbt::Tag {
+        let s = &self.gen_settings;
+        nbt::MapTag::new()
+            .set("version", nbt::Tag::Short(self.version as i16))
+            .set(
+                "general",
+                nbt::MapTag::new()
+                    .set("worldSize", nbt::Tag::Byte(self.world_size.0 as i8))
+                    .set("name", nbt::Tag::String(self.world_name.clone()))
+                    .build(),
+            )
+            .set(
+                "gen",
+                nbt::MapTag::new()
+                    .set("seed", nbt::Tag::Long(s.seed as i64))
+                    .set("blockGenScale", nbt::Tag::Double(s.block_gen_scale))
+                    .set(
+                        "heightMapGenScale",
+                        nbt::Tag::Double(s.height_map_gen_scale),
+                    )
+                    .set(
+                        "blockDensityGenScale",
+                        nbt::Tag::Double(s.block_density_gen_scale),
+                    )
+                    .set(
+                        "biomeHeightGenScale",
+                        nbt::Tag::Double(s.biome_height_map_gen_scale),
+                    )
+                    .set(
+                        "biomeHeightVariationGenScale",
+                        nbt::Tag::Double(s.biome_height_variation_gen_scale),
+                    )
+                    .build(),
+            )
+            .build()
+    }
+
+    /// Decodes the contents of a `world.dat`. Missing settings get the same defaults as in the
+    /// game, and a missing version means the world is from before versions were saved.
+    pub fn from_nbt(tag: &nbt::Tag) -> Result<Self, String> {
+        let fields = map_fields(tag).ok_or("world info was not a map tag")?;
+        let version = match fields.get("version") {
+            None => 1,
+            Some(nbt::Tag::Short(v)) => *v as u16,
+            _ => return Err("wrong type for version field")?,
+        };
+
+        let config = WorldConfig::from_nbt(tag)?;
+        let world_size = CylinderSize::new(config.size.unwrap_or(7))?;
+        let gen_settings = WorldGenSettings::from_seed(config.seed.unwrap_or(0));
+        let info = WorldInfo {
+            version,
+            ..config.apply(WorldInfo::new("World", world_size, gen_settings))
+        };
+        info.gen_settings.validate()?;
+        Ok(info)
+    }
+}
+
+impl Default for WorldInfo {
+    /// The settings the game uses when none are given, with seed 0
+    fn default() -> Self {
+        WorldInfo::new("World", CylinderSize(7), WorldGenSettings::from_seed(0))
+    }
 }
 
+pub(crate) fn map_fields(tag: &nbt::Tag) -> Option<HashMap<&str, &nbt::Tag>> {
+    match tag {
+        nbt::Tag::Map(items) => Some(items.iter().map(|(k, v)| (k.as_str(), v)).collect()),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct WorldGenSettings {
     pub seed: u64,
     pub block_gen_scale: f64,
@@ -21,17 +119,67 @@ pub struct WorldGenSettings {
     pub biome_height_variation_gen_scale: f64,
 }
 
+impl WorldGenSettings {
+    /// The default generator settings, with the given seed
+    pub fn from_seed(seed: u64) -> Self {
+        WorldGenSettings {
+            seed,
+            block_gen_scale: 0.1,
+            height_map_gen_scale: 0.02,
+            block_density_gen_scale: 0.01,
+            biome_height_map_gen_scale: 0.002,
+            biome_height_variation_gen_scale: 0.002,
+        }
+    }
+
+    /// The scales are frequencies of the noise used to generate the world, so they have to be
+    /// positive for the world to have any features
+    pub fn validate(&self) -> Result<(), String> {
+        let scales = [
+            ("block-gen-scale", self.block_gen_scale),
+            ("height-map-gen-scale", self.height_map_gen_scale),
+            ("block-density-gen-scale", self.block_density_gen_scale),
+            (
+                "biome-height-map-gen-scale",
+                self.biome_height_map_gen_scale,
+            ),
+            (
+                "biome-height-variation-gen-scale",
+                self.biome_height_variation_gen_scale,
+            ),
+        ];
+        for (name, scale) in scales {
+            if !scale.is_finite() || scale <= 0.0 {
+                return Err(format!("{name} has to be a positive number, not {scale}"));
+            }
+        }
+        Ok(())
+    }
+}
+
 /// The real cylinder size (the number of chunks around the cylinder) is:<br> <code>ringSize =
 /// 2&#94;sizeExponent</code>
 ///
 /// @param worldSize
 ///   the size exponent, <b>max-value: 20</b>
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CylinderSize(pub u8);
 
 impl CylinderSize {
     const Y60: f64 = SQRT_3 / 2.0;
 
+    pub const MAX_EXPONENT: u8 = 20;
+
+    pub fn new(size_exponent: u8) -> Result<Self, String> {
+        if size_exponent > CylinderSize::MAX_EXPONENT {
+            return Err(format!(
+                "world size has to be at most {}, not {size_exponent}",
+                CylinderSize::MAX_EXPONENT
+            ));
+        }
+        Ok(CylinderSize(size_exponent))
+    }
+
     /** The number of chunks around the cylinder */
     pub fn ring_size(self) -> u32 {
         1 << self.0
@@ -70,6 +218,12 @@ impl CylinderSize {
     }
 }
 
+impl Display for CylinderSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 pub struct HexBox {
     pub radius: f32,
     pub bottom: f32,
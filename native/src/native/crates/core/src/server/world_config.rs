@@ -0,0 +1,371 @@
+//! Choosing the settings of a world when it is created, and keeping them when it is opened again
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use tracing::{info, warn};
+
+use crate::server::{
+    migration, nbt,
+    world::{CylinderSize, WorldInfo, map_fields},
+};
+
+/// The file in the world directory that the settings are stored in
+pub const WORLD_FILE: &str = "world.dat";
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Settings for creating a world. The ones that are not given get their defaults in new worlds,
+/// and are left as they are in existing worlds.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WorldConfig {
+    pub name: Option<String>,
+    /// The size exponent, see `CylinderSize`
+    pub size: Option<u8>,
+    /// New worlds get a random seed if none is given
+    pub seed: Option<u64>,
+    pub block_gen_scale: Option<f64>,
+    pub height_map_gen_scale: Option<f64>,
+    pub block_density_gen_scale: Option<f64>,
+    pub biome_height_map_gen_scale: Option<f64>,
+    pub biome_height_variation_gen_scale: Option<f64>,
+}
+
+impl WorldConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self
+            .name
+            .as_ref()
+            .is_some_and(|name| name.trim().is_empty())
+        {
+            return Err("world name cannot be empty".to_string());
+        }
+        if let Some(size) = self.size {
+            CylinderSize::new(size)?;
+        }
+        self.apply(WorldInfo::default()).gen_settings.validate()
+    }
+
+    /// Reads a config from a tag shaped like a `world.dat`, in which every setting is optional.
+    /// `WorldInfo::from_nbt` fills in the missing ones with their defaults.
+    pub fn from_nbt(tag: &nbt::Tag) -> Result<Self, String> {
+        let fields = map_fields(tag).ok_or("world config was not a map tag")?;
+        let general = match fields.get("general") {
+            None => HashMap::new(),
+            Some(tag) => map_fields(tag).ok_or("wrong type for general field")?,
+        };
+        let generation = match fields.get("gen") {
+            None => HashMap::new(),
+            Some(tag) => map_fields(tag).ok_or("wrong type for gen field")?,
+        };
+        let scale = |names: &[&str]| match names.iter().find_map(|name| generation.get(*name)) {
+            None => Ok(None),
+            Some(nbt::Tag::Double(v)) => Ok(Some(*v)),
+            _ => Err(format!("wrong type for {} field", names[0])),
+        };
+
+        Ok(WorldConfig {
+            name: match general.get("name") {
+                None => None,
+                Some(nbt::Tag::String(v)) => Some(v.clone()),
+                _ => return Err("wrong type for name field")?,
+            },
+            size: match general.get("worldSize") {
+                None => None,
+                Some(nbt::Tag::Byte(v)) => Some(*v as u8),
+                _ => return Err("wrong type for worldSize field")?,
+            },
+            seed: match generation.get("seed") {
+                None => None,
+                Some(nbt::Tag::Long(v)) => Some(*v as u64),
+                _ => return Err("wrong type for seed field")?,
+            },
+            block_gen_scale: scale(&["blockGenScale"])?,
+            height_map_gen_scale: scale(&["heightMapGenScale"])?,
+            block_density_gen_scale: scale(&["blockDensityGenScale"])?,
+            // the game has always written the first name, but tried to read the second one
+            biome_height_map_gen_scale: scale(&["biomeHeightGenScale", "biomeHeightMapGenScale"])?,
+            biome_height_variation_gen_scale: scale(&["biomeHeightVariationGenScale"])?,
+        })
+    }
+
+    /// Replaces the settings of the world with the ones given here
+    pub(crate) fn apply(&self, mut info: WorldInfo) -> WorldInfo {
+        let s = &mut info.gen_settings;
+        if let Some(name) = &self.name {
+            info.world_name = name.clone();
+        }
+        if let Some(size) = self.size {
+            info.world_size = CylinderSize(size);
+        }
+        s.seed = self.seed.unwrap_or(s.seed);
+        s.block_gen_scale = self.block_gen_scale.unwrap_or(s.block_gen_scale);
+        s.height_map_gen_scale = self.height_map_gen_scale.unwrap_or(s.height_map_gen_scale);
+        s.block_density_gen_scale = self
+            .block_density_gen_scale
+            .unwrap_or(s.block_density_gen_scale);
+        s.biome_height_map_gen_scale = self
+            .biome_height_map_gen_scale
+            .unwrap_or(s.biome_height_map_gen_scale);
+        s.biome_height_variation_gen_scale = self
+            .biome_height_variation_gen_scale
+            .unwrap_or(s.biome_height_variation_gen_scale);
+        info
+    }
+
+    /// The settings given here that the world does not have, like `seed is 42, not 7`
+    fn differences(&self, info: &WorldInfo) -> Vec<String> {
+        let s = &info.gen_settings;
+        let mut differences = Vec::new();
+        let mut compare = |name: &str, existing: String, given: Option<String>| {
+            if let Some(given) = given
+                && given != existing
+            {
+                differences.push(format!("{name} is {existing}, not {given}"));
+            }
+        };
+
+        compare("name", info.world_name.clone(), self.name.clone());
+        compare(
+            "size",
+            info.world_size.to_string(),
+            self.size.map(|v| v.to_string()),
+        );
+        compare("seed", s.seed.to_string(), self.seed.map(|v| v.to_string()));
+        let scales = [
+            ("block-gen-scale", s.block_gen_scale, self.block_gen_scale),
+            (
+                "height-map-gen-scale",
+                s.height_map_gen_scale,
+                self.height_map_gen_scale,
+            ),
+            (
+                "block-density-gen-scale",
+                s.block_density_gen_scale,
+                self.block_density_gen_scale,
+            ),
+            (
+                "biome-height-map-gen-scale",
+                s.biome_height_map_gen_scale,
+                self.biome_height_map_gen_scale,
+            ),
+            (
+                "biome-height-variation-gen-scale",
+                s.biome_height_variation_gen_scale,
+                self.biome_height_variation_gen_scale,
+            ),
+        ];
+        for (name, existing, given) in scales {
+            compare(name, existing.to_string(), given.map(|v| v.to_string()));
+        }
+        differences
+    }
+}
+
+/// Opens the world in a directory, or creates it with the given settings if it does not exist.
+///
+/// The settings of an existing world are never changed unless `change_existing` is set, since a
+/// world with another seed or size no longer fits the chunks that have already been generated.
+/// Giving settings the world already has is fine. Worlds saved by older versions of the game are
+/// upgraded first.
+pub fn open_world(
+    dir: &Path,
+    config: &WorldConfig,
+    change_existing: bool,
+) -> Result<WorldInfo, String> {
+    config.validate()?;
+    fs::create_dir_all(dir).map_err(|err| format!("failed to create {}: {err}", dir.display()))?;
+    migration::migrate_if_needed(dir)?;
+
+    let Some((_, tag)) = read_world_file(dir)? else {
+        let seed = match config.seed {
+            Some(seed) => seed,
+            None => random_seed()?,
+        };
+        let mut info = WorldInfo::default();
+        info.gen_settings.seed = seed;
+        if let Some(name) = dir.file_name() {
+            info.world_name = name.to_string_lossy().to_string();
+        }
+        let info = config.apply(info);
+
+        write_world_file(dir, "", &info.to_nbt())?;
+        info!(
+            name = info.world_name,
+            size = info.world_size.0,
+            seed = info.gen_settings.seed,
+            "Created a new world in {}",
+            dir.display()
+        );
+        return Ok(info);
+    };
+
+    let info = WorldInfo::from_nbt(&tag)
+        .map_err(|err| format!("invalid {}: {err}", dir.join(WORLD_FILE).display()))?;
+    let differences = config.differences(&info);
+    if differences.is_empty() {
+        return Ok(info);
+    }
+    if !change_existing {
+        return Err(format!(
+            "the world in {} has other settings than the ones given: {}",
+            dir.display(),
+            differences.join(", ")
+        ));
+    }
+
+    let info = config.apply(info);
+    write_world_file(dir, "", &info.to_nbt())?;
+    warn!(
+        "Changed the settings of the world in {}: {}",
+        dir.display(),
+        differences.join(", ")
+    );
+    Ok(info)
+}
+
+fn random_seed() -> Result<u64, String> {
+    let mut seed = [0; 8];
+    getrandom::fill(&mut seed).map_err(|err| format!("failed to create seed: {err}"))?;
+    Ok(u64::from_le_bytes(seed))
+}
+
+/// Reads the `world.dat` of a world, returning the name of its root tag and the tag itself
+pub(crate) fn read_world_file(dir: &Path) -> Result<Option<(String, nbt::Tag)>, String> {
+    let path = dir.join(WORLD_FILE);
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("failed to read {}: {err}", path.display())),
+    };
+
+    // the game always compresses it, but it is nice to be able to edit it uncompressed
+    let data = if bytes.starts_with(&GZIP_MAGIC) {
+        let mut data = Vec::new();
+        GzDecoder::new(bytes.as_slice())
+            .read_to_end(&mut data)
+            .map_err(|err| format!("failed to decompress {}: {err}", path.display()))?;
+        data
+    } else {
+        bytes
+    };
+    let (name, tag) =
+        nbt::Tag::from_binary(&data).map_err(|err| format!("invalid {}: {err}", path.display()))?;
+    Ok(Some((name, tag)))
+}
+
+/// Replaces the `world.dat` of a world. The new file is written next to it first, so that the
+/// old one is left as it was if anything goes wrong.
+pub(crate) fn write_world_file(dir: &Path, name: &str, tag: &nbt::Tag) -> Result<(), String> {
+    let path = dir.join(WORLD_FILE);
+    let write_failed = |err: std::io::Error| format!("failed to write {}: {err}", path.display());
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(&tag.to_named_binary(name))
+        .map_err(write_failed)?;
+    let bytes = encoder.finish().map_err(write_failed)?;
+
+    let temp_path = dir.join(format!("{WORLD_FILE}.tmp"));
+    fs::write(&temp_path, bytes).map_err(write_failed)?;
+    fs::rename(&temp_path, &path).map_err(write_failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use crate::server::{
+        nbt,
+        world::{CylinderSize, WorldInfo},
+    };
+
+    use super::{WorldConfig, open_world};
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("hexacraft-world-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn existing_worlds_keep_their_settings() {
+        let dir = test_dir("config");
+        let config = WorldConfig {
+            size: Some(9),
+            seed: Some(42),
+            ..WorldConfig::default()
+        };
+
+        let created = open_world(&dir, &config, false).unwrap();
+        assert_eq!(created.world_size, CylinderSize(9));
+        assert_eq!(created.gen_settings.seed, 42);
+        assert!(created.world_name.starts_with("hexacraft-world-config-"));
+
+        // the settings are remembered, and giving the same ones again is fine
+        assert_eq!(
+            open_world(&dir, &WorldConfig::default(), false).unwrap(),
+            created
+        );
+        assert_eq!(open_world(&dir, &config, false).unwrap(), created);
+
+        let other_seed = WorldConfig {
+            seed: Some(7),
+            ..WorldConfig::default()
+        };
+        let err = open_world(&dir, &other_seed, false).unwrap_err();
+        assert!(err.ends_with("seed is 42, not 7"), "{err}");
+
+        let changed = open_world(&dir, &other_seed, true).unwrap();
+        assert_eq!(changed.gen_settings.seed, 7);
+        assert_eq!(changed.world_size, CylinderSize(9));
+        assert_eq!(
+            open_world(&dir, &WorldConfig::default(), false).unwrap(),
+            changed
+        );
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn the_old_biome_height_scale_name_is_read() {
+        let tag = nbt::MapTag::new()
+            .set(
+                "gen",
+                nbt::MapTag::new()
+                    .set("biomeHeightMapGenScale", nbt::Tag::Double(0.5))
+                    .build(),
+            )
+            .build();
+
+        let config = WorldConfig::from_nbt(&tag).unwrap();
+        assert_eq!(config.biome_height_map_gen_scale, Some(0.5));
+        let info = WorldInfo::from_nbt(&tag).unwrap();
+        assert_eq!(info.gen_settings.biome_height_map_gen_scale, 0.5);
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        let dir = test_dir("invalid");
+        let too_large = WorldConfig {
+            size: Some(21),
+            ..WorldConfig::default()
+        };
+        assert!(open_world(&dir, &too_large, false).is_err());
+
+        let negative_scale = WorldConfig {
+            block_gen_scale: Some(-0.1),
+            ..WorldConfig::default()
+        };
+        assert!(open_world(&dir, &negative_scale, false).is_err());
+        assert!(!dir.join(super::WORLD_FILE).exists());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
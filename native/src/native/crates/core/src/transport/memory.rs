@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use tokio::sync::{Mutex, mpsc};
+use tokio_util::sync::CancellationToken;
+use zeromq::{ZmqError, ZmqResult};
+
+use crate::transport::{ClientTransport, PeerEvent, PeerId, Transport};
+
+type Envelope = (PeerId, Vec<Bytes>);
+
+/// A transport for clients in the same process as the server. Messages are passed over channels.
+pub struct InMemoryServer {
+    incoming_tx: mpsc::UnboundedSender<Envelope>,
+    incoming_rx: Mutex<mpsc::UnboundedReceiver<Envelope>>,
+    peers: std::sync::Mutex<HashMap<PeerId, mpsc::UnboundedSender<Bytes>>>,
+    events_tx: mpsc::UnboundedSender<PeerEvent>,
+    events_rx: Mutex<mpsc::UnboundedReceiver<PeerEvent>>,
+    cancel_token: CancellationToken,
+}
+
+impl Default for InMemoryServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InMemoryServer {
+    pub fn new() -> Self {
+        let (incoming_tx, incoming_rx) = mpsc::unbounded_channel();
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        Self {
+            incoming_tx,
+            incoming_rx: Mutex::new(incoming_rx),
+            peers: std::sync::Mutex::new(HashMap::new()),
+            events_tx,
+            events_rx: Mutex::new(events_rx),
+            cancel_token: CancellationToken::new(),
+        }
+    }
+
+    /// Creates a client connected to this server. Just like for ZMQ sockets, a client connecting
+    /// with the id of an existing client takes over that identity.
+    pub fn connect(&self, client_id: Vec<u8>) -> InMemoryClient {
+        let peer_id = PeerId::from(client_id);
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.peers.lock().unwrap().insert(peer_id.clone(), tx);
+        let _ = self.events_tx.send(PeerEvent::Connected(peer_id.clone()));
+
+        InMemoryClient {
+            peer_id,
+            server_tx: self.incoming_tx.clone(),
+            events_tx: self.events_tx.clone(),
+            received: Mutex::new(rx),
+        }
+    }
+}
+
+impl Transport for InMemoryServer {
+    async fn receive_message(&self) -> ZmqResult<(PeerId, Vec<Bytes>)> {
+        let mut incoming = self.incoming_rx.lock().await;
+        tokio::select! {
+            _ = self.cancel_token.cancelled() => Err(ZmqError::Other("cancelled")),
+            // the server owns a sender, so the channel can never be closed
+            msg = incoming.recv() => Ok(msg.unwrap()),
+        }
+    }
+
+    async fn send(&self, peer_id: PeerId, data: Vec<u8>) -> ZmqResult<()> {
+        let mut peers = self.peers.lock().unwrap();
+        let Some(peer) = peers.get(&peer_id) else {
+            return Err(ZmqError::Other("destination client not found"));
+        };
+        if peer.send(Bytes::from(data)).is_err() {
+            // the client has been dropped
+            peers.remove(&peer_id);
+            return Err(ZmqError::Other("destination client not found"));
+        }
+        Ok(())
+    }
+
+    async fn next_peer_event(&self) -> Option<PeerEvent> {
+        let mut events = self.events_rx.lock().await;
+        loop {
+            // the server owns a sender, so the channel can never be closed
+            let event = tokio::select! {
+                _ = self.cancel_token.cancelled() => return None,
+                event = events.recv() => event.unwrap(),
+            };
+            if let PeerEvent::Disconnected(peer_id) = &event {
+                let mut peers = self.peers.lock().unwrap();
+                match peers.get(peer_id) {
+                    // another client has taken over the identity
+                    Some(peer) if !peer.is_closed() => continue,
+                    _ => peers.remove(peer_id),
+                };
+            }
+            return Some(event);
+        }
+    }
+
+    fn cancel(&self) {
+        self.cancel_token.cancel();
+    }
+}
+
+pub struct InMemoryClient {
+    peer_id: PeerId,
+    server_tx: mpsc::UnboundedSender<Envelope>,
+    events_tx: mpsc::UnboundedSender<PeerEvent>,
+    received: Mutex<mpsc::UnboundedReceiver<Bytes>>,
+}
+
+impl Drop for InMemoryClient {
+    fn drop(&mut self) {
+        let _ = self
+            .events_tx
+            .send(PeerEvent::Disconnected(self.peer_id.clone()));
+    }
+}
+
+impl ClientTransport for InMemoryClient {
+    async fn send(&self, data: Vec<u8>) -> ZmqResult<()> {
+        self.server_tx
+            .send((self.peer_id.clone(), vec![Bytes::from(data)]))
+            .map_err(|_| ZmqError::Other("server is gone"))
+    }
+
+    async fn try_receive(&self) -> Option<Vec<u8>> {
+        let frame = self.received.lock().await.try_recv().ok()?;
+        Some(frame.to_vec())
+    }
+}
+
+impl InMemoryClient {
+    /// Waits until a message arrives. Returns `None` once the server has dropped the client.
+    pub async fn receive(&self) -> Option<Vec<u8>> {
+        let frame = self.received.lock().await.recv().await?;
+        Some(frame.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zeromq::{ZmqError, ZmqResult};
+
+    use crate::transport::{ClientTransport, InMemoryServer, PeerEvent, Transport};
+
+    #[tokio::test]
+    async fn roundtrip_2_clients() -> ZmqResult<()> {
+        let server = InMemoryServer::new();
+        let client1 = server.connect(b"ABC".to_vec());
+        let client2 = server.connect(b"ABD".to_vec());
+
+        client1.send(vec![1, 2, 3]).await?;
+        client2.send(vec![1, 2, 4]).await?;
+
+        let (peer_id, frames) = server.receive_message().await?;
+        assert_eq!(peer_id.as_bytes(), b"ABC");
+        assert_eq!(frames, vec![vec![1, 2, 3]]);
+
+        let (peer_id, frames) = server.receive_message().await?;
+        assert_eq!(peer_id.as_bytes(), b"ABD");
+        assert_eq!(frames, vec![vec![1, 2, 4]]);
+
+        server.send(b"ABC".to_vec().into(), vec![4, 5, 6]).await?;
+        server.send(b"ABD".to_vec().into(), vec![4, 5, 7]).await?;
+
+        assert_eq!(client1.try_receive().await, Some(vec![4, 5, 6]));
+        assert_eq!(client2.try_receive().await, Some(vec![4, 5, 7]));
+        assert_eq!(client1.try_receive().await, None);
+
+        // Sending to a client that is gone should fail
+        drop(client1);
+        assert!(server.send(b"ABC".to_vec().into(), vec![1]).await.is_err());
+
+        assert_eq!(
+            server.next_peer_event().await,
+            Some(PeerEvent::Connected(b"ABC".to_vec().into()))
+        );
+        assert_eq!(
+            server.next_peer_event().await,
+            Some(PeerEvent::Connected(b"ABD".to_vec().into()))
+        );
+        assert_eq!(
+            server.next_peer_event().await,
+            Some(PeerEvent::Disconnected(b"ABC".to_vec().into()))
+        );
+
+        // Shutdown server which should immediately cancel receive calls
+        let server_rx = server.receive_message();
+        server.cancel();
+
+        match server_rx.await {
+            Err(ZmqError::Other("cancelled")) => {}
+            res => panic!("got {res:?}"),
+        };
+
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+//! Ways of moving messages between clients and a server.
+//!
+//! The ZMQ sockets are used for real network connections, while the in-memory transport lets a
+//! server and its clients live in the same process without going through the network stack.
+
+use bytes::Bytes;
+use zeromq::ZmqResult;
+
+pub use memory::{InMemoryClient, InMemoryServer};
+
+mod memory;
+
+/// The identity of a client connected to a server
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PeerId(Bytes);
+
+impl PeerId {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for PeerId {
+    fn from(id: Vec<u8>) -> Self {
+        Self(Bytes::from(id))
+    }
+}
+
+impl From<Bytes> for PeerId {
+    fn from(id: Bytes) -> Self {
+        Self(id)
+    }
+}
+
+impl From<PeerId> for Bytes {
+    fn from(id: PeerId) -> Self {
+        id.0
+    }
+}
+
+/// A change in the set of clients connected to a server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerEvent {
+    Connected(PeerId),
+    Disconnected(PeerId),
+}
+
+/// The server side of a transport, which exchanges messages with many clients
+pub trait Transport: Send + Sync + 'static {
+    /// Receives the next message together with the identity of the client that sent it
+    fn receive_message(&self) -> impl Future<Output = ZmqResult<(PeerId, Vec<Bytes>)>> + Send;
+
+    fn send(&self, peer_id: PeerId, data: Vec<u8>) -> impl Future<Output = ZmqResult<()>> + Send;
+
+    /// Waits for the next client to connect or disconnect. Returns `None` once the transport has
+    /// been cancelled.
+    fn next_peer_event(&self) -> impl Future<Output = Option<PeerEvent>> + Send;
+
+    /// Makes current and future calls to `receive_message` fail with a "cancelled" error
+    fn cancel(&self);
+}
+
+/// The client side of a transport, which is connected to a single server
+pub trait ClientTransport: Send + Sync + 'static {
+    fn send(&self, data: Vec<u8>) -> impl Future<Output = ZmqResult<()>> + Send;
+
+    fn try_receive(&self) -> impl Future<Output = Option<Vec<u8>>> + Send;
+}
@@ -1,96 +1,584 @@
-use std::{collections::VecDeque, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
 
 use bytes::Bytes;
-use tokio::sync::Mutex;
+use futures::StreamExt;
+use snow::TransportState;
+use tokio::sync::{Mutex, Notify, OwnedMutexGuard, watch};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
 use zeromq::{
-    DealerRecvHalf, DealerSendHalf, DealerSocket, RouterSocket, SocketOptions, ZmqError,
-    ZmqMessage, ZmqResult, prelude::*, util::PeerIdentity,
+    DealerRecvHalf, DealerSendHalf, DealerSocket, RouterSocket, SocketEvent, SocketOptions,
+    ZmqError, ZmqMessage, ZmqResult, prelude::*, util::PeerIdentity,
 };
 
-enum ClientSocketState {
-    Init(PeerIdentity),
-    Connected(Arc<Mutex<DealerSendHalf>>, Arc<Mutex<DealerRecvHalf>>),
+use crate::transport::{ClientTransport, Transport};
+pub use crate::transport::{PeerEvent, PeerId};
+pub use compression::Compression;
+pub use noise::{Keypair, ServerEncryption, ServerTrust};
+use peers::PeerTracker;
+use queue::ReceiveQueue;
+pub use queue::{OverflowPolicy, ReceiveQueueConfig};
+pub use zeromq::Endpoint;
+
+mod compression;
+mod noise;
+mod peers;
+mod queue;
+
+/// How often a `ClientSocket` lets the server know that it is still there
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a `ServerSocket` waits for a client to send something before considering it gone. It
+/// allows for a few keepalives to go missing.
+pub const PEER_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// The port of a tcp endpoint, like the one returned by `ServerSocket::bind`
+pub fn endpoint_port(endpoint: &Endpoint) -> Option<u16> {
+    match endpoint {
+        Endpoint::Tcp(_, port) => Some(*port),
+        _ => None,
+    }
+}
+
+/// Io errors are wrapped in codec errors, but unlike other codec errors they mean that the
+/// connection is gone.
+fn is_io_error(err: &ZmqError) -> bool {
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        if err.is::<std::io::Error>() {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Keepalives are messages with a single empty frame. They are handled by the sockets and never
+/// passed on, since an empty frame is not a valid NBT message anyway.
+fn is_keepalive(frames: &[Bytes]) -> bool {
+    matches!(frames, [frame] if frame.is_empty())
+}
+
+/// The state of the connection between a `ClientSocket` and the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Reconnecting,
+    Closed,
+}
+
+impl ConnectionState {
+    /// The number used for this state in the JNI layer
+    pub fn id(self) -> i32 {
+        match self {
+            ConnectionState::Connecting => 0,
+            ConnectionState::Connected => 1,
+            ConnectionState::Reconnecting => 2,
+            ConnectionState::Closed => 3,
+        }
+    }
+}
+
+/// Decides how a `ClientSocket` tries to get back in touch with the server after losing the
+/// connection.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// The delay before the first attempt. It is doubled after every failed attempt.
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// How long a single attempt may take before it is given up
+    pub attempt_timeout: Duration,
+    /// The connection is closed after this many failed attempts. `None` means no limit.
+    pub max_attempts: Option<u32>,
+    /// The number of messages that can be sent while reconnecting. Further sends will fail.
+    pub max_pending_sends: usize,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            attempt_timeout: Duration::from_secs(5),
+            max_attempts: Some(10),
+            max_pending_sends: 256,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Connection {
+    tx: Arc<Mutex<DealerSendHalf>>,
+    rx: Arc<Mutex<DealerRecvHalf>>,
+    session: Option<Arc<std::sync::Mutex<TransportState>>>,
+    compression: Option<Compression>,
+    /// Set once the server has agreed to compress
+    compressing: Arc<AtomicBool>,
+}
+
+impl Connection {
+    async fn send(&self, data: &[u8]) -> ZmqResult<()> {
+        let compressed = match &self.compression {
+            Some(compression) if self.compressing.load(Ordering::Relaxed) => {
+                compression.compress(data)
+            }
+            _ => None,
+        };
+        let data = compressed.as_deref().unwrap_or(data);
+
+        // the frames have to be sent in the order they were encrypted, so the lock on the sender
+        // is taken first
+        let mut tx = self.tx.lock().await;
+        let data = match &self.session {
+            Some(session) => noise::encrypt(&mut session.lock().unwrap(), data)?,
+            None => data.to_vec(),
+        };
+        tx.send(ZmqMessage::from(data)).await
+    }
+
+    fn decode(&self, frame: Bytes) -> ZmqResult<Bytes> {
+        let frame = match &self.session {
+            Some(session) => noise::decrypt(&mut session.lock().unwrap(), &frame)?.into(),
+            None => frame,
+        };
+        compression::decompress(frame)
+    }
 }
 
 pub struct ClientSocket {
-    state: Mutex<ClientSocketState>,
-    received_frames: Mutex<VecDeque<Bytes>>,
+    peer_identity: PeerIdentity,
+    policy: ReconnectPolicy,
+    keepalive_interval: Duration,
+    encryption: Option<ServerTrust>,
+    compression: Option<Compression>,
+    endpoint: Mutex<Option<String>>,
+    connection: Mutex<Option<Connection>>,
+    state: watch::Sender<ConnectionState>,
+    pending_sends: Mutex<VecDeque<Vec<u8>>>,
+    reconnect_requested: Notify,
+    received_frames: ReceiveQueue,
+    frames_received: Notify,
+    cancel_token: tokio_util::sync::CancellationToken,
 }
 
 impl ClientSocket {
     pub fn new(client_id: Vec<u8>) -> Self {
+        Self::with_policy(client_id, ReconnectPolicy::default())
+    }
+
+    pub fn with_policy(client_id: Vec<u8>, policy: ReconnectPolicy) -> Self {
         Self {
-            state: Mutex::new(ClientSocketState::Init(
-                PeerIdentity::try_from(client_id).unwrap(),
-            )),
-            received_frames: Mutex::new(VecDeque::new()),
+            peer_identity: PeerIdentity::try_from(client_id).unwrap(),
+            policy,
+            keepalive_interval: KEEPALIVE_INTERVAL,
+            encryption: None,
+            compression: None,
+            endpoint: Mutex::new(None),
+            connection: Mutex::new(None),
+            state: watch::Sender::new(ConnectionState::Connecting),
+            pending_sends: Mutex::new(VecDeque::new()),
+            reconnect_requested: Notify::new(),
+            received_frames: ReceiveQueue::new(ReceiveQueueConfig::default()),
+            frames_received: Notify::new(),
+            cancel_token: tokio_util::sync::CancellationToken::new(),
         }
     }
 
+    /// Limits how many received frames can be waiting to be picked up, see `ReceiveQueueConfig`
+    pub fn with_receive_queue(mut self, config: ReceiveQueueConfig) -> Self {
+        self.received_frames = ReceiveQueue::new(config);
+        self
+    }
+
+    /// Sends a keepalive whenever `interval` has passed, so that the server does not think the
+    /// client is gone when it has nothing else to send
+    pub fn with_keepalive_interval(mut self, interval: Duration) -> Self {
+        self.keepalive_interval = interval;
+        self
+    }
+
+    /// Encrypts all traffic, and only talks to servers trusted according to `trust`
+    pub fn with_encryption(mut self, trust: ServerTrust) -> Self {
+        self.encryption = Some(trust);
+        self
+    }
+
+    /// Compresses large messages, if the server supports it
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    pub fn connection_state(&self) -> ConnectionState {
+        *self.state.borrow()
+    }
+
+    /// Returns a receiver that is notified every time the connection state changes
+    pub fn subscribe_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
     pub async fn connect(&self, host: &str, port: u16) -> ZmqResult<()> {
-        let mut state = self.state.lock().await;
-        *state = match &*state {
-            ClientSocketState::Init(peer_identity) => {
-                let mut options = SocketOptions::default();
-                options.peer_identity(peer_identity.clone());
+        if host.contains(':') {
+            // IPv6 addresses have to be enclosed in brackets
+            self.connect_to(&format!("tcp://[{host}]:{port}")).await
+        } else {
+            self.connect_to(&format!("tcp://{host}:{port}")).await
+        }
+    }
 
-                let mut conn = DealerSocket::with_options(options);
-                conn.connect(&format!("tcp://{host}:{port}")).await?;
+    /// Connects to an endpoint, like `tcp://localhost:1234` or `ipc:///tmp/hexacraft.sock`
+    pub async fn connect_to(&self, endpoint: &str) -> ZmqResult<()> {
+        let mut connection = self.connection.lock().await;
+        if connection.is_some() {
+            panic!("already connected");
+        }
 
-                let (tx, rx) = conn.split();
-                ClientSocketState::Connected(Arc::new(Mutex::new(tx)), Arc::new(Mutex::new(rx)))
+        match self.open_connection(endpoint).await {
+            Ok(conn) => {
+                *connection = Some(conn);
+                *self.endpoint.lock().await = Some(endpoint.to_string());
+                self.state.send_replace(ConnectionState::Connected);
+                Ok(())
             }
-            _ => {
-                panic!("already connected");
+            Err(err) => {
+                self.state.send_replace(ConnectionState::Closed);
+                Err(err)
+            }
+        }
+    }
+
+    async fn open_connection(&self, endpoint: &str) -> ZmqResult<Connection> {
+        let mut options = SocketOptions::default();
+        options.peer_identity(self.peer_identity.clone());
+
+        let mut conn = DealerSocket::with_options(options);
+        conn.connect(endpoint).await?;
+
+        let (mut tx, mut rx) = conn.split();
+
+        let session = match &self.encryption {
+            None => None,
+            Some(trust) => {
+                let (handshake, request) = noise::ClientHandshake::start()?;
+                tx.send(ZmqMessage::from(request)).await?;
+                let response = rx.recv().await?;
+                let response = response.get(0).ok_or(ZmqError::NoMessage)?;
+                let session = handshake.finish(response, trust, endpoint)?;
+                Some(Arc::new(std::sync::Mutex::new(session)))
             }
         };
-        Ok(())
+
+        let conn = Connection {
+            tx: Arc::new(Mutex::new(tx)),
+            rx: Arc::new(Mutex::new(rx)),
+            session,
+            compression: self.compression.clone(),
+            compressing: Arc::new(AtomicBool::new(false)),
+        };
+        if self.compression.is_some() {
+            // the answer is picked up by the receiver
+            conn.send(&compression::hello()).await?;
+        }
+        Ok(conn)
     }
 
+    /// Sends a message to the server.
+    ///
+    /// While the socket is reconnecting the message is buffered and sent once the connection is
+    /// back, unless the buffer is full in which case an error is returned.
     pub async fn send(&self, data: Vec<u8>) -> ZmqResult<()> {
-        let ClientSocketState::Connected(tx, _) = &*self.state.lock().await else {
-            panic!("not connected");
+        let mut pending_sends = self.pending_sends.lock().await;
+        match self.connection_state() {
+            ConnectionState::Connected => {}
+            ConnectionState::Connecting => return Err(ZmqError::Other("not connected")),
+            ConnectionState::Reconnecting => return self.buffer_send(&mut pending_sends, data),
+            ConnectionState::Closed => return Err(ZmqError::Other("connection closed")),
+        }
+
+        let Some(conn) = self.connection.lock().await.clone() else {
+            return Err(ZmqError::Other("not connected"));
         };
-        tx.lock().await.send(ZmqMessage::from(data)).await
+        drop(pending_sends);
+
+        match conn.send(&data).await {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                warn!(?err, "Client socket: Failed to send, will reconnect");
+                let mut pending_sends = self.pending_sends.lock().await;
+                self.start_reconnecting();
+                self.buffer_send(&mut pending_sends, data)
+            }
+        }
     }
 
-    pub async fn run_receiver(self: Arc<Self>) {
-        let rx = {
-            match &*self.state.lock().await {
-                ClientSocketState::Connected(_, rx) => rx.clone(),
-                _ => panic!("not connected"),
+    fn buffer_send(&self, pending_sends: &mut VecDeque<Vec<u8>>, data: Vec<u8>) -> ZmqResult<()> {
+        if pending_sends.len() >= self.policy.max_pending_sends {
+            return Err(ZmqError::BufferFull(
+                "too many messages sent while reconnecting",
+            ));
+        }
+        pending_sends.push_back(data);
+        Ok(())
+    }
+
+    fn start_reconnecting(&self) {
+        self.set_reconnecting();
+        self.reconnect_requested.notify_one();
+    }
+
+    fn set_reconnecting(&self) {
+        self.state.send_if_modified(|state| {
+            if *state == ConnectionState::Connected {
+                *state = ConnectionState::Reconnecting;
+                true
+            } else {
+                false
             }
-        };
+        });
+    }
+
+    /// Receives messages until the socket is closed, reconnecting whenever the connection is lost
+    pub async fn run_receiver(self: Arc<Self>) {
+        let mut keepalive = tokio::time::interval_at(
+            tokio::time::Instant::now() + self.keepalive_interval,
+            self.keepalive_interval,
+        );
+        keepalive.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
         loop {
-            match rx.lock().await.recv().await {
-                Ok(msg) => {
-                    let mut received_frames = self.received_frames.lock().await;
-                    for frame in msg.into_vec() {
-                        received_frames.push_back(frame);
+            let conn = match &*self.connection.lock().await {
+                Some(conn) => conn.clone(),
+                None => panic!("not connected"),
+            };
+
+            let res = tokio::select! {
+                _ = self.cancel_token.cancelled() => return,
+                _ = self.reconnect_requested.notified() => {
+                    if self.connection_state() == ConnectionState::Connected {
+                        // the request was made before the last reconnect finished
+                        continue;
                     }
-                    drop(received_frames);
+                    None
                 }
-                Err(ZmqError::Codec(err)) => {
+                _ = keepalive.tick() => {
+                    if self.connection_state() != ConnectionState::Connected {
+                        continue;
+                    }
+                    match conn.send(&[]).await {
+                        Ok(()) => continue,
+                        Err(err) => {
+                            warn!(?err, "Client socket: Failed to send keepalive, will reconnect");
+                            None
+                        }
+                    }
+                }
+                res = async { conn.rx.lock().await.recv().await } => Some(res),
+            };
+            let res = res.map(|res| {
+                res.and_then(|msg| {
+                    msg.into_vec()
+                        .into_iter()
+                        .map(|frame| conn.decode(frame))
+                        .collect::<ZmqResult<Vec<_>>>()
+                })
+            });
+            match res {
+                Some(Ok(frames)) if compression::is_hello(&frames) => {
+                    conn.compressing.store(true, Ordering::Relaxed);
+                    continue;
+                }
+                Some(Ok(frames)) => {
+                    for frame in frames {
+                        let push = self.received_frames.push(frame);
+                        tokio::pin!(push);
+                        let accepted = loop {
+                            tokio::select! {
+                                _ = self.cancel_token.cancelled() => return,
+                                accepted = &mut push => break accepted,
+                                // a full queue only slows the server down, so it still has to
+                                // hear from the client meanwhile
+                                _ = keepalive.tick() => {
+                                    if let Err(err) = conn.send(&[]).await {
+                                        warn!(?err, "Client socket: Failed to send keepalive");
+                                    }
+                                }
+                            }
+                        };
+                        self.frames_received.notify_waiters();
+                        if !accepted {
+                            warn!("Client socket: Receive queue is full, disconnecting");
+                            self.close();
+                            return;
+                        }
+                    }
+                    continue;
+                }
+                Some(Err(err @ ZmqError::Codec(_))) if is_io_error(&err) => {
+                    warn!(?err, "Client socket: Lost connection, will reconnect");
+                }
+                Some(Err(ZmqError::Codec(err))) => {
                     // the server probably sent an unknown command, so we ignore it
-                    eprintln!("Client socket: Got codec error: {:?}", err);
+                    warn!(?err, "Client socket: Got codec error");
+                    continue;
                 }
-                Err(err) => {
-                    eprintln!("Client socket: Got error: {:?}", err);
-                    return;
+                Some(Err(err)) => {
+                    warn!(?err, "Client socket: Got error, will reconnect");
                 }
+                None => {}
             };
+
+            if !self.reconnect().await {
+                self.close();
+                return;
+            }
+        }
+    }
+
+    /// Tries to connect again using exponential backoff. Returns false if it gave up.
+    async fn reconnect(&self) -> bool {
+        self.set_reconnecting();
+        let Some(endpoint) = self.endpoint.lock().await.clone() else {
+            return false;
+        };
+
+        let mut delay = self.policy.initial_delay;
+        let mut attempts = 0;
+        loop {
+            if self.policy.max_attempts.is_some_and(|max| attempts >= max) {
+                error!(attempts, "Client socket: Gave up reconnecting");
+                return false;
+            }
+            attempts += 1;
+
+            tokio::select! {
+                _ = self.cancel_token.cancelled() => return false,
+                _ = tokio::time::sleep(delay) => {}
+            }
+            delay = (delay * 2).min(self.policy.max_delay);
+
+            let conn = tokio::select! {
+                _ = self.cancel_token.cancelled() => return false,
+                res = tokio::time::timeout(self.policy.attempt_timeout, self.open_connection(&endpoint)) => res,
+            };
+            let conn = match conn {
+                Ok(Ok(conn)) => conn,
+                Ok(Err(err)) => {
+                    info!(
+                        attempt = attempts,
+                        ?err,
+                        "Client socket: Reconnect attempt failed"
+                    );
+                    continue;
+                }
+                Err(_) => {
+                    info!(
+                        attempt = attempts,
+                        "Client socket: Reconnect attempt timed out"
+                    );
+                    continue;
+                }
+            };
+
+            // new sends are buffered until everything that was buffered before has been sent
+            let mut pending_sends = self.pending_sends.lock().await;
+            let mut send_failed = false;
+            while let Some(data) = pending_sends.pop_front() {
+                if let Err(err) = conn.send(&data).await {
+                    warn!(?err, "Client socket: Failed to send after reconnecting");
+                    pending_sends.push_front(data);
+                    send_failed = true;
+                    break;
+                }
+            }
+            if send_failed {
+                continue;
+            }
+
+            *self.connection.lock().await = Some(conn);
+            self.state.send_replace(ConnectionState::Connected);
+            return true;
         }
     }
 
     pub async fn try_receive(&self) -> Option<Vec<u8>> {
-        let mut received_frames = self.received_frames.lock().await;
-        Some(received_frames.pop_front()?.to_vec())
+        Some(self.received_frames.pop()?.to_vec())
+    }
+
+    /// The number of received frames waiting to be picked up
+    pub fn queue_depth(&self) -> usize {
+        self.received_frames.len()
+    }
+
+    /// The number of received frames that were thrown away because the receive queue was full
+    pub fn dropped_frames(&self) -> u64 {
+        self.received_frames.dropped()
+    }
+
+    /// Waits until a frame has been received, or until the timeout has passed.
+    ///
+    /// Returns `None` on timeout, or if the socket is closed and no frames are left.
+    pub async fn receive_timeout(&self, timeout: Duration) -> Option<Vec<u8>> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            // the notification has to be enabled before checking the queue, or a frame arriving
+            // in between would not wake us up
+            let notified = self.frames_received.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if let Some(frame) = self.try_receive().await {
+                return Some(frame);
+            }
+            if self.connection_state() == ConnectionState::Closed {
+                return None;
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return None;
+            }
+        }
+    }
+
+    /// Closes the connection and stops the receiver. Messages that were not sent yet are dropped.
+    pub fn close(&self) {
+        self.state.send_replace(ConnectionState::Closed);
+        if !self.cancel_token.is_cancelled() {
+            self.cancel_token.cancel();
+        }
+        self.frames_received.notify_waiters();
+    }
+}
+
+impl ClientTransport for ClientSocket {
+    async fn send(&self, data: Vec<u8>) -> ZmqResult<()> {
+        ClientSocket::send(self, data).await
+    }
+
+    async fn try_receive(&self) -> Option<Vec<u8>> {
+        ClientSocket::try_receive(self).await
     }
 }
 
 pub struct ServerSocket {
-    socket: Mutex<RouterSocket>,
-    received_frames: Mutex<VecDeque<Bytes>>,
+    socket: Arc<Mutex<RouterSocket>>,
+    monitor: std::sync::Mutex<futures::channel::mpsc::Receiver<SocketEvent>>,
+    peers: PeerTracker,
+    encryption: Option<ServerEncryption>,
+    sessions: std::sync::Mutex<HashMap<PeerId, TransportState>>,
+    /// The clients that have connected again since their last handshake, and so may start a new
+    /// session
+    new_connections: std::sync::Mutex<HashSet<PeerId>>,
+    compression: Option<Compression>,
+    /// The clients that have asked for compression
+    compressing_peers: std::sync::Mutex<HashSet<PeerId>>,
     cancel_token: tokio_util::sync::CancellationToken,
 }
 
@@ -100,58 +588,272 @@ impl Drop for ServerSocket {
     }
 }
 
+impl Default for ServerSocket {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ServerSocket {
     pub fn new() -> Self {
+        Self::with_peer_timeout(PEER_TIMEOUT)
+    }
+
+    /// Creates a socket that considers a client disconnected if it has not sent anything for
+    /// `peer_timeout`, see `next_peer_event`. It should be longer than the keepalive interval of
+    /// the clients.
+    pub fn with_peer_timeout(peer_timeout: Duration) -> Self {
+        let mut socket = RouterSocket::new();
+        let monitor = socket.monitor();
         Self {
-            socket: Mutex::new(RouterSocket::new()),
-            received_frames: Mutex::new(VecDeque::new()),
+            socket: Arc::new(Mutex::new(socket)),
+            monitor: std::sync::Mutex::new(monitor),
+            peers: PeerTracker::new(peer_timeout),
+            encryption: None,
+            sessions: std::sync::Mutex::new(HashMap::new()),
+            new_connections: std::sync::Mutex::new(HashSet::new()),
+            compression: None,
+            compressing_peers: std::sync::Mutex::new(HashSet::new()),
             cancel_token: tokio_util::sync::CancellationToken::new(),
         }
     }
 
-    pub async fn bind(&self, port: u16) -> ZmqResult<()> {
-        self.socket
-            .lock()
-            .await
-            .bind(&format!("tcp://0.0.0.0:{port}"))
-            .await?;
-        Ok(())
+    /// Lets clients encrypt their traffic, see `ServerEncryption`
+    pub fn with_encryption(mut self, encryption: ServerEncryption) -> Self {
+        self.encryption = Some(encryption);
+        self
     }
 
-    pub async fn receive(&self) -> ZmqResult<Vec<u8>> {
-        let mut received_frames = self.received_frames.lock().await;
-        if received_frames.is_empty() {
-            let msg = loop {
-                let mut socket = self.socket.lock().await;
+    /// Compresses large messages to clients that support it
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
 
-                match tokio::select! {
-                    _ = self.cancel_token.cancelled() => {
-                        return Err(ZmqError::Other("cancelled"));
-                    }
-                    res = socket.recv() => res,
-                } {
-                    Ok(data) => break data,
-                    Err(ZmqError::Codec(err)) => {
-                        // the client probably sent an unknown command, so we ignore it
-                        eprintln!("Server socket: Got codec error: {:?}", err);
+    pub fn public_key(&self) -> Option<&[u8]> {
+        Some(self.encryption.as_ref()?.keypair.public_key())
+    }
+
+    /// Binds the socket to an endpoint, like `tcp://0.0.0.0:1234` (all IPv4 interfaces),
+    /// `tcp://127.0.0.1:1234` (only local connections), `tcp://[::]:1234` (IPv6) or
+    /// `ipc:///tmp/hexacraft.sock` (a unix socket).
+    ///
+    /// Returns the endpoint that was actually bound. If the port was 0 it contains the port that
+    /// was picked by the operating system.
+    pub async fn bind(&self, endpoint: &str) -> ZmqResult<Endpoint> {
+        self.socket.lock().await.bind(endpoint).await
+    }
+
+    /// Receives the next message together with the identity of the client that sent it.
+    ///
+    /// All frames of a multipart message are returned together, so concurrent callers can never
+    /// get frames from different messages mixed up.
+    pub async fn receive_message(&self) -> ZmqResult<(PeerId, Vec<Bytes>)> {
+        loop {
+            let mut socket = self.socket.clone().lock_owned().await;
+
+            let msg = match tokio::select! {
+                _ = self.cancel_token.cancelled() => {
+                    return Err(ZmqError::Other("cancelled"));
+                }
+                res = socket.recv() => res,
+            } {
+                Ok(data) => data,
+                Err(ZmqError::Codec(err)) => {
+                    // the client probably sent an unknown command, so we ignore it
+                    warn!(?err, "Server socket: Got codec error");
+                    continue;
+                }
+                Err(err) => {
+                    error!(?err, "Server socket: Got error");
+                    return Err(err);
+                }
+            };
+
+            let mut frames = msg.into_vec().into_iter();
+            // the router socket always puts the identity of the sender first
+            let peer_id = PeerId::from(frames.next().ok_or(ZmqError::NoMessage)?);
+            self.peers.seen(&peer_id);
+            let frames: Vec<Bytes> = frames.collect();
+
+            let frames = match &self.encryption {
+                None => frames,
+                Some(encryption) => match self.open_frames(encryption, &peer_id, frames) {
+                    Ok(Incoming::Message(frames)) => frames,
+                    Ok(Incoming::HandshakeResponse(response)) => {
+                        let failed = "Failed to finish handshake";
+                        let _ = send_reply(socket, peer_id, response, failed).await;
+                        continue;
                     }
                     Err(err) => {
-                        eprintln!("Server socket: Got error: {:?}", err);
-                        return Err(err);
+                        warn!(peer = ?peer_id, %err, "Server socket: Dropped message from client");
+                        continue;
                     }
-                };
+                },
             };
-            for frame in msg.into_vec() {
-                received_frames.push_back(frame);
+
+            if is_keepalive(&frames) {
+                continue;
+            }
+
+            if compression::is_hello(&frames) {
+                // clients not hearing back will simply not compress
+                if self.compression.is_some() {
+                    self.compressing_peers
+                        .lock()
+                        .unwrap()
+                        .insert(peer_id.clone());
+                    match self.seal(&peer_id, compression::hello()) {
+                        Ok(data) => {
+                            let failed = "Failed to answer compression hello";
+                            let _ = send_reply(socket, peer_id, data, failed).await;
+                        }
+                        Err(err) => warn!(
+                            peer = ?peer_id,
+                            ?err,
+                            "Server socket: Failed to answer compression hello"
+                        ),
+                    }
+                }
+                continue;
+            }
+
+            match frames
+                .into_iter()
+                .map(compression::decompress)
+                .collect::<ZmqResult<Vec<_>>>()
+            {
+                Ok(frames) => return Ok((peer_id, frames)),
+                Err(err) => {
+                    warn!(peer = ?peer_id, %err, "Server socket: Dropped message from client")
+                }
             }
         }
-        Ok(received_frames.pop_front().unwrap().to_vec())
     }
 
-    pub async fn send(&self, client_id: Vec<u8>, data: Vec<u8>) -> ZmqResult<()> {
-        let mut msg = ZmqMessage::from(client_id);
-        msg.push_back(data.into());
-        self.socket.lock().await.send(msg).await
+    fn open_frames(
+        &self,
+        encryption: &ServerEncryption,
+        peer_id: &PeerId,
+        frames: Vec<Bytes>,
+    ) -> ZmqResult<Incoming> {
+        if let [frame] = frames.as_slice()
+            && let Some((&noise::HANDSHAKE_FRAME, request)) = frame.split_first()
+        {
+            // the connection might have been accepted after the monitor was last looked at
+            self.handle_monitor_events();
+
+            // otherwise anyone knowing the identity of a client could replace its session
+            let mut sessions = self.sessions.lock().unwrap();
+            let is_new_connection = self.new_connections.lock().unwrap().remove(peer_id);
+            if sessions.contains_key(peer_id) && !is_new_connection {
+                return Err(ZmqError::Other(
+                    "handshake from a client that already has a session",
+                ));
+            }
+
+            let (session, response) = noise::server_handshake(&encryption.keypair, request)?;
+            sessions.insert(peer_id.clone(), session);
+            return Ok(Incoming::HandshakeResponse(response));
+        }
+
+        let mut sessions = self.sessions.lock().unwrap();
+
+        match sessions.get_mut(peer_id) {
+            Some(session) => {
+                let frames = frames
+                    .iter()
+                    .map(|frame| noise::decrypt(session, frame).map(Bytes::from))
+                    .collect::<ZmqResult<Vec<_>>>();
+                if frames.is_err() {
+                    // the client has to start over with a new handshake
+                    sessions.remove(peer_id);
+                }
+                Ok(Incoming::Message(frames?))
+            }
+            None if encryption.required => Err(ZmqError::Other("the message was not encrypted")),
+            None => Ok(Incoming::Message(frames)),
+        }
+    }
+
+    /// Compresses and encrypts a frame, depending on what was agreed on with the client
+    fn seal(&self, peer_id: &PeerId, data: Vec<u8>) -> ZmqResult<Vec<u8>> {
+        let data = match &self.compression {
+            Some(compression) if self.compressing_peers.lock().unwrap().contains(peer_id) => {
+                compression.compress(&data).unwrap_or(data)
+            }
+            _ => data,
+        };
+        match self.sessions.lock().unwrap().get_mut(peer_id) {
+            Some(session) => noise::encrypt(session, &data),
+            None => Ok(data),
+        }
+    }
+
+    pub async fn send(&self, peer_id: PeerId, data: Vec<u8>) -> ZmqResult<()> {
+        // the frames have to be sent in the order they were encrypted, so the socket is locked
+        // first
+        let mut socket = self.socket.lock().await;
+        let data = self.seal(&peer_id, data)?;
+
+        let res = socket.send(message(&peer_id, data)).await;
+        if res.is_err() {
+            self.peers.lost(&peer_id);
+        }
+        res
+    }
+
+    /// Waits for the next client to connect or disconnect. Returns `None` once the socket has been
+    /// cancelled.
+    ///
+    /// Connections are reported as soon as they are accepted, and closed connections as soon as
+    /// the socket monitor reports them. The router socket of zeromq 0.5 does not do that yet, so
+    /// a client is also considered disconnected when sending to it fails, or when it has not
+    /// sent anything for the peer timeout. Clients send keepalives to stay within it.
+    pub async fn next_peer_event(&self) -> Option<PeerEvent> {
+        // the monitor is only locked while polling it, since `receive_message` needs it too
+        let next_monitor_event =
+            || futures::future::poll_fn(|cx| self.monitor.lock().unwrap().poll_next_unpin(cx));
+        loop {
+            tokio::select! {
+                _ = self.cancel_token.cancelled() => return None,
+                event = self.peers.next_event() => {
+                    if let Some(PeerEvent::Disconnected(peer_id)) = &event {
+                        self.sessions.lock().unwrap().remove(peer_id);
+                        self.compressing_peers.lock().unwrap().remove(peer_id);
+                        self.new_connections.lock().unwrap().remove(peer_id);
+                    }
+                    if event.is_some() {
+                        return event;
+                    }
+                }
+                Some(event) = next_monitor_event() => self.handle_monitor_event(event),
+            }
+        }
+    }
+
+    /// Handles the monitor events that have already arrived
+    fn handle_monitor_events(&self) {
+        loop {
+            let Ok(event) = self.monitor.lock().unwrap().try_recv() else {
+                return;
+            };
+            self.handle_monitor_event(event);
+        }
+    }
+
+    fn handle_monitor_event(&self, event: SocketEvent) {
+        match event {
+            SocketEvent::Accepted(_, peer_id) => {
+                let peer_id = PeerId::from(Bytes::from(peer_id));
+                self.peers.seen(&peer_id);
+                self.new_connections.lock().unwrap().insert(peer_id);
+            }
+            SocketEvent::Disconnected(peer_id) => {
+                self.peers.lost(&PeerId::from(Bytes::from(peer_id)));
+            }
+            _ => {}
+        }
     }
 
     pub fn cancel(&self) {
@@ -161,11 +863,64 @@ impl ServerSocket {
     }
 }
 
+/// Sends a reply to a client while `ServerSocket::receive_message` has the socket locked.
+///
+/// The session of the client is already set up by then, so the reply has to arrive even if
+/// receiving is cancelled meanwhile, like `GameServer::run_receiver` does whenever it has a response
+/// to send. The reply is therefore sent from a task of its own, which keeps the socket locked until
+/// it is done so nothing sealed later can overtake it.
+fn send_reply(
+    mut socket: OwnedMutexGuard<RouterSocket>,
+    peer_id: PeerId,
+    data: Vec<u8>,
+    failed: &'static str,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        if let Err(err) = socket.send(message(&peer_id, data)).await {
+            warn!(peer = ?peer_id, ?err, "Server socket: {failed}");
+        }
+    })
+}
+
+/// A message to a client. The router socket uses the first frame to know where to send it.
+fn message(peer_id: &PeerId, data: Vec<u8>) -> ZmqMessage {
+    let mut msg = ZmqMessage::from(Bytes::from(peer_id.clone()));
+    msg.push_back(data.into());
+    msg
+}
+
+enum Incoming {
+    Message(Vec<Bytes>),
+    HandshakeResponse(Vec<u8>),
+}
+
+impl Transport for ServerSocket {
+    async fn receive_message(&self) -> ZmqResult<(PeerId, Vec<Bytes>)> {
+        ServerSocket::receive_message(self).await
+    }
+
+    async fn send(&self, peer_id: PeerId, data: Vec<u8>) -> ZmqResult<()> {
+        ServerSocket::send(self, peer_id, data).await
+    }
+
+    async fn next_peer_event(&self) -> Option<PeerEvent> {
+        ServerSocket::next_peer_event(self).await
+    }
+
+    fn cancel(&self) {
+        ServerSocket::cancel(self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::{sync::Arc, time::Duration};
+    use std::{num::NonZeroUsize, sync::Arc, time::Duration};
 
-    use zeromq::{ZmqError, ZmqResult};
+    use futures::FutureExt;
+    use zeromq::{
+        DealerSocket, SocketOptions, ZmqError, ZmqMessage, ZmqResult, prelude::*,
+        util::PeerIdentity,
+    };
 
     use crate::zmq;
 
@@ -174,24 +929,24 @@ mod tests {
         let client_id = vec![b'A', b'B', b'C'];
 
         let server = zmq::ServerSocket::new();
-        server.bind(1234).await?;
+        let endpoint = server.bind("tcp://127.0.0.1:0").await?;
+        let port = zmq::endpoint_port(&endpoint).unwrap();
 
         let client = Arc::new(zmq::ClientSocket::new(client_id.clone()));
-        client.connect("localhost", 1234).await?;
+        client.connect("localhost", port).await?;
 
         // Send message from client to server
         client.send(vec![1, 2, 3]).await?;
 
         tokio::spawn(client.clone().run_receiver());
 
-        let client_id_msg = server.receive().await?;
-        let data_msg = server.receive().await?;
+        let (peer_id, frames) = server.receive_message().await?;
 
-        assert_eq!(client_id_msg, vec![b'A', b'B', b'C']);
-        assert_eq!(data_msg, vec![1, 2, 3]);
+        assert_eq!(peer_id.as_bytes(), b"ABC");
+        assert_eq!(frames, vec![vec![1, 2, 3]]);
 
         // Send message from server to client
-        server.send(client_id.clone(), vec![4, 5, 6]).await?;
+        server.send(client_id.clone().into(), vec![4, 5, 6]).await?;
 
         tokio::time::sleep(Duration::from_millis(1)).await;
 
@@ -199,7 +954,7 @@ mod tests {
         assert_eq!(data_msg, vec![4, 5, 6]);
 
         // Shutdown server which should immediately cancel receive calls
-        let server_rx = server.receive();
+        let server_rx = server.receive_message();
         server.cancel();
 
         match server_rx.await {
@@ -216,39 +971,42 @@ mod tests {
         let client_id2 = vec![b'A', b'B', b'D'];
 
         let server = zmq::ServerSocket::new();
-        server.bind(1235).await?;
+        let endpoint = server.bind("tcp://127.0.0.1:0").await?;
+        let port = zmq::endpoint_port(&endpoint).unwrap();
 
         let client1 = Arc::new(zmq::ClientSocket::new(client_id1.clone()));
-        client1.connect("localhost", 1235).await?;
+        client1.connect("localhost", port).await?;
 
         // Send message from client to server
         client1.send(vec![1, 2, 3]).await?;
 
         tokio::spawn(client1.clone().run_receiver());
 
-        let client_id_msg = server.receive().await?;
-        let data_msg = server.receive().await?;
+        let (peer_id, frames) = server.receive_message().await?;
 
-        assert_eq!(client_id_msg, vec![b'A', b'B', b'C']);
-        assert_eq!(data_msg, vec![1, 2, 3]);
+        assert_eq!(peer_id.as_bytes(), b"ABC");
+        assert_eq!(frames, vec![vec![1, 2, 3]]);
 
         let client2 = Arc::new(zmq::ClientSocket::new(client_id2.clone()));
-        client2.connect("localhost", 1235).await?;
+        client2.connect("localhost", port).await?;
 
         tokio::spawn(client2.clone().run_receiver());
 
         // Send message from client to server
         client2.send(vec![1, 2, 4]).await?;
 
-        let client_id_msg = server.receive().await?;
-        let data_msg = server.receive().await?;
+        let (peer_id, frames) = server.receive_message().await?;
 
-        assert_eq!(client_id_msg, vec![b'A', b'B', b'D']);
-        assert_eq!(data_msg, vec![1, 2, 4]);
+        assert_eq!(peer_id.as_bytes(), b"ABD");
+        assert_eq!(frames, vec![vec![1, 2, 4]]);
 
         // Send message from server to client
-        server.send(client_id1.clone(), vec![4, 5, 6]).await?;
-        server.send(client_id2.clone(), vec![4, 5, 7]).await?;
+        server
+            .send(client_id1.clone().into(), vec![4, 5, 6])
+            .await?;
+        server
+            .send(client_id2.clone().into(), vec![4, 5, 7])
+            .await?;
 
         tokio::time::sleep(Duration::from_millis(1)).await;
 
@@ -259,7 +1017,7 @@ mod tests {
         assert_eq!(data_msg, vec![4, 5, 7]);
 
         // Shutdown server which should immediately cancel receive calls
-        let server_rx = server.receive();
+        let server_rx = server.receive_message();
         server.cancel();
 
         match server_rx.await {
@@ -269,4 +1027,470 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn client_reconnects_and_sends_buffered_messages() -> ZmqResult<()> {
+        let server = zmq::ServerSocket::new();
+        let endpoint = server.bind("tcp://127.0.0.1:0").await?;
+
+        let policy = zmq::ReconnectPolicy {
+            initial_delay: Duration::from_millis(10),
+            max_pending_sends: 4,
+            ..Default::default()
+        };
+        let client = Arc::new(zmq::ClientSocket::with_policy(b"ABC".to_vec(), policy));
+        let mut state = client.subscribe_state();
+        client.connect_to(&endpoint.to_string()).await?;
+        assert_eq!(client.connection_state(), zmq::ConnectionState::Connected);
+        tokio::spawn(client.clone().run_receiver());
+
+        client.send(vec![1]).await?;
+        assert_eq!(server.receive_message().await?.1, vec![vec![1]]);
+
+        drop(server);
+
+        // the first messages might disappear into the closed connection before it is noticed
+        while client.connection_state() == zmq::ConnectionState::Connected {
+            client.send(vec![0]).await?;
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            client.connection_state(),
+            zmq::ConnectionState::Reconnecting
+        );
+
+        client.send(vec![2]).await?;
+        client.send(vec![3]).await?;
+        let mut buffered = 0;
+        let err = loop {
+            match client.send(vec![4]).await {
+                Ok(()) => buffered += 1,
+                Err(err) => break err,
+            }
+        };
+        assert!(matches!(err, ZmqError::BufferFull(_)));
+        assert!(buffered < 4);
+
+        let server = zmq::ServerSocket::new();
+        server.bind(&endpoint.to_string()).await?;
+
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            state.wait_for(|s| *s == zmq::ConnectionState::Connected),
+        )
+        .await
+        .expect("client did not reconnect")
+        .unwrap();
+
+        // the message that failed to send when the connection was lost might come first
+        let mut frames = server.receive_message().await?.1;
+        if frames == vec![vec![0]] {
+            frames = server.receive_message().await?.1;
+        }
+        assert_eq!(frames, vec![vec![2]]);
+        assert_eq!(server.receive_message().await?.1, vec![vec![3]]);
+        for _ in 0..buffered {
+            assert_eq!(server.receive_message().await?.1, vec![vec![4]]);
+        }
+
+        server.send(b"ABC".to_vec().into(), vec![5]).await?;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(client.try_receive().await, Some(vec![5]));
+
+        client.close();
+        assert_eq!(client.connection_state(), zmq::ConnectionState::Closed);
+        assert!(client.send(vec![6]).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn receive_timeout_wakes_up_on_new_frames() -> ZmqResult<()> {
+        let server = zmq::ServerSocket::new();
+        let endpoint = server.bind("tcp://127.0.0.1:0").await?;
+
+        let client = Arc::new(zmq::ClientSocket::new(b"ABC".to_vec()));
+        client.connect_to(&endpoint.to_string()).await?;
+        tokio::spawn(client.clone().run_receiver());
+
+        assert_eq!(
+            client.receive_timeout(Duration::from_millis(10)).await,
+            None
+        );
+
+        let receiver = tokio::spawn({
+            let client = client.clone();
+            async move { client.receive_timeout(Duration::from_secs(10)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        server.send(b"ABC".to_vec().into(), vec![1, 2, 3]).await?;
+
+        let data = tokio::time::timeout(Duration::from_secs(1), receiver)
+            .await
+            .expect("receiver was not woken up")
+            .unwrap();
+        assert_eq!(data, Some(vec![1, 2, 3]));
+
+        // closing the socket wakes up waiting receivers
+        let receiver = tokio::spawn({
+            let client = client.clone();
+            async move { client.receive_timeout(Duration::from_secs(10)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        client.close();
+        let data = tokio::time::timeout(Duration::from_secs(1), receiver)
+            .await
+            .expect("receiver was not woken up")
+            .unwrap();
+        assert_eq!(data, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn peer_events() -> ZmqResult<()> {
+        let server = zmq::ServerSocket::with_peer_timeout(Duration::from_millis(300));
+        let endpoint = server.bind("tcp://127.0.0.1:0").await?;
+
+        let client = Arc::new(
+            zmq::ClientSocket::new(b"ABC".to_vec())
+                .with_keepalive_interval(Duration::from_millis(50)),
+        );
+        client.connect_to(&endpoint.to_string()).await?;
+        tokio::spawn(client.clone().run_receiver());
+
+        let next_event = || tokio::time::timeout(Duration::from_secs(1), server.next_peer_event());
+        assert_eq!(
+            next_event().await.unwrap(),
+            Some(zmq::PeerEvent::Connected(b"ABC".to_vec().into()))
+        );
+
+        // keepalives are not passed on, but they keep an idle client from timing out
+        let (received, event) = tokio::join!(
+            tokio::time::timeout(Duration::from_millis(600), server.receive_message()),
+            tokio::time::timeout(Duration::from_millis(600), server.next_peer_event())
+        );
+        assert!(received.is_err());
+        assert!(event.is_err());
+
+        // a client that goes quiet is eventually considered gone
+        client.close();
+        let _ = tokio::time::timeout(Duration::from_millis(600), server.receive_message()).await;
+        assert_eq!(
+            next_event().await.unwrap(),
+            Some(zmq::PeerEvent::Disconnected(b"ABC".to_vec().into()))
+        );
+
+        server.cancel();
+        assert_eq!(next_event().await.unwrap(), None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn clients_with_a_full_receive_queue_do_not_time_out() -> ZmqResult<()> {
+        let server = zmq::ServerSocket::with_peer_timeout(Duration::from_millis(300));
+        let endpoint = server.bind("tcp://127.0.0.1:0").await?;
+
+        let client = Arc::new(
+            zmq::ClientSocket::new(b"ABC".to_vec())
+                .with_keepalive_interval(Duration::from_millis(50))
+                .with_receive_queue(zmq::ReceiveQueueConfig {
+                    high_water_mark: NonZeroUsize::new(1).unwrap(),
+                    policy: zmq::OverflowPolicy::Block,
+                }),
+        );
+        client.connect_to(&endpoint.to_string()).await?;
+        tokio::spawn(client.clone().run_receiver());
+        let next_event = || tokio::time::timeout(Duration::from_secs(1), server.next_peer_event());
+        assert_eq!(
+            next_event().await.unwrap(),
+            Some(zmq::PeerEvent::Connected(b"ABC".to_vec().into()))
+        );
+
+        // the second message waits until the first has been picked up
+        server.send(b"ABC".to_vec().into(), vec![1]).await?;
+        server.send(b"ABC".to_vec().into(), vec![2]).await?;
+        let (received, event) = tokio::join!(
+            tokio::time::timeout(Duration::from_millis(600), server.receive_message()),
+            tokio::time::timeout(Duration::from_millis(600), server.next_peer_event())
+        );
+        assert!(received.is_err());
+        assert!(event.is_err());
+
+        assert_eq!(client.try_receive().await, Some(vec![1]));
+        assert_eq!(
+            client.receive_timeout(Duration::from_secs(1)).await,
+            Some(vec![2])
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn encrypted_roundtrip() -> ZmqResult<()> {
+        let keypair = zmq::Keypair::generate();
+        let server = zmq::ServerSocket::new().with_encryption(zmq::ServerEncryption {
+            keypair: keypair.clone(),
+            required: true,
+        });
+        let endpoint = server.bind("tcp://127.0.0.1:0").await?.to_string();
+
+        let client = Arc::new(
+            zmq::ClientSocket::new(b"ABC".to_vec())
+                .with_encryption(zmq::ServerTrust::Pinned(keypair.public_key().to_vec())),
+        );
+
+        // the server answers the handshake while it is receiving
+        let (sent, received) = tokio::join!(
+            async {
+                client.connect_to(&endpoint).await?;
+                client.send(vec![1, 2, 3]).await
+            },
+            server.receive_message()
+        );
+        sent?;
+        let (peer_id, frames) = received?;
+        assert_eq!(peer_id.as_bytes(), b"ABC");
+        assert_eq!(frames, vec![vec![1, 2, 3]]);
+
+        tokio::spawn(client.clone().run_receiver());
+        server.send(b"ABC".to_vec().into(), vec![4, 5, 6]).await?;
+        assert_eq!(
+            client.receive_timeout(Duration::from_secs(1)).await,
+            Some(vec![4, 5, 6])
+        );
+
+        // a client expecting another server refuses to connect
+        let other_key = zmq::Keypair::generate().public_key().to_vec();
+        let client = zmq::ClientSocket::new(b"ABD".to_vec())
+            .with_encryption(zmq::ServerTrust::Pinned(other_key));
+        let (connected, _) = tokio::join!(
+            client.connect_to(&endpoint),
+            tokio::time::timeout(Duration::from_millis(100), server.receive_message())
+        );
+        assert!(connected.is_err());
+
+        Ok(())
+    }
+
+    async fn connect_dealer(endpoint: &str, identity: &[u8]) -> ZmqResult<DealerSocket> {
+        let mut options = SocketOptions::default();
+        options.peer_identity(PeerIdentity::try_from(identity.to_vec()).unwrap());
+        let mut socket = DealerSocket::with_options(options);
+        socket.connect(endpoint).await?;
+        Ok(socket)
+    }
+
+    /// Starts a handshake and returns whether the server answered it
+    async fn handshake(socket: &mut DealerSocket) -> ZmqResult<bool> {
+        let (_, request) = zmq::noise::ClientHandshake::start()?;
+        socket.send(ZmqMessage::from(request)).await?;
+        let response = tokio::time::timeout(Duration::from_millis(200), socket.recv()).await;
+        Ok(response.is_ok())
+    }
+
+    #[tokio::test]
+    async fn sessions_are_only_replaced_by_new_connections() -> ZmqResult<()> {
+        let keypair = zmq::Keypair::generate();
+        let server = Arc::new(
+            zmq::ServerSocket::new().with_encryption(zmq::ServerEncryption {
+                keypair,
+                required: true,
+            }),
+        );
+        let endpoint = server.bind("tcp://127.0.0.1:0").await?.to_string();
+
+        // handshakes are answered while receiving
+        let receiver = tokio::spawn({
+            let server = server.clone();
+            async move { while server.receive_message().await.is_ok() {} }
+        });
+
+        let mut socket = connect_dealer(&endpoint, b"ABC").await?;
+        assert!(handshake(&mut socket).await?);
+        assert!(!handshake(&mut socket).await?);
+
+        // a client that connects again has to be able to start over
+        let mut socket = connect_dealer(&endpoint, b"ABC").await?;
+        assert!(handshake(&mut socket).await?);
+        assert!(!handshake(&mut socket).await?);
+
+        server.cancel();
+        receiver.await.unwrap();
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn handshakes_are_answered_when_receiving_is_cancelled() -> ZmqResult<()> {
+        let server = zmq::ServerSocket::new().with_encryption(zmq::ServerEncryption {
+            keypair: zmq::Keypair::generate(),
+            required: true,
+        });
+        let endpoint = server.bind("tcp://127.0.0.1:0").await?.to_string();
+
+        let mut socket = connect_dealer(&endpoint, b"ABC").await?;
+        let (_, request) = zmq::noise::ClientHandshake::start()?;
+        socket.send(ZmqMessage::from(request)).await?;
+
+        // receiving is given up on as soon as it has to wait, like when the server has a response
+        // to send, which is right when the reply to the handshake is being sent
+        let peer_id = zmq::PeerId::from(b"ABC".to_vec());
+        while !server.sessions.lock().unwrap().contains_key(&peer_id) {
+            let _ = server.receive_message().now_or_never();
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let response = tokio::time::timeout(Duration::from_millis(200), socket.recv()).await;
+        assert!(response.is_ok(), "the handshake was not answered");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn plaintext_clients_are_rejected_when_encryption_is_required() -> ZmqResult<()> {
+        let keypair = zmq::Keypair::generate();
+        let server = zmq::ServerSocket::new().with_encryption(zmq::ServerEncryption {
+            keypair: keypair.clone(),
+            required: true,
+        });
+        let endpoint = server.bind("tcp://127.0.0.1:0").await?.to_string();
+
+        let plaintext_client = zmq::ClientSocket::new(b"ABC".to_vec());
+        let encrypted_client = zmq::ClientSocket::new(b"ABD".to_vec())
+            .with_encryption(zmq::ServerTrust::Pinned(keypair.public_key().to_vec()));
+
+        let (sent, received) = tokio::join!(
+            async {
+                plaintext_client.connect_to(&endpoint).await?;
+                plaintext_client.send(vec![1, 2, 3]).await?;
+                encrypted_client.connect_to(&endpoint).await?;
+                encrypted_client.send(vec![4, 5, 6]).await
+            },
+            server.receive_message()
+        );
+        sent?;
+
+        // only the message from the encrypted client gets through
+        let (peer_id, frames) = received?;
+        assert_eq!(peer_id.as_bytes(), b"ABD");
+        assert_eq!(frames, vec![vec![4, 5, 6]]);
+
+        let next = tokio::time::timeout(Duration::from_millis(100), server.receive_message());
+        assert!(next.await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn compression_is_negotiated() -> ZmqResult<()> {
+        let server = zmq::ServerSocket::new().with_compression(zmq::Compression::default());
+        let endpoint = server.bind("tcp://127.0.0.1:0").await?.to_string();
+        let data: Vec<u8> = (0..10_000).map(|i| (i / 100) as u8).collect();
+
+        let client = Arc::new(
+            zmq::ClientSocket::new(b"ABC".to_vec()).with_compression(zmq::Compression::default()),
+        );
+        client.connect_to(&endpoint).await?;
+        tokio::spawn(client.clone().run_receiver());
+
+        // the server answers the hello while receiving the first message
+        client.send(data.clone()).await?;
+        assert_eq!(server.receive_message().await?.1, vec![data.clone()]);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        client.send(data.clone()).await?;
+        assert_eq!(server.receive_message().await?.1, vec![data.clone()]);
+
+        // a client that never said hello gets uncompressed frames
+        let mut options = SocketOptions::default();
+        options.peer_identity(PeerIdentity::try_from(b"ABD".to_vec())?);
+        let mut plain_client = DealerSocket::with_options(options);
+        plain_client.connect(&endpoint).await?;
+        plain_client.send(ZmqMessage::from(vec![1])).await?;
+        server.receive_message().await?;
+        server.send(b"ABD".to_vec().into(), data.clone()).await?;
+        assert_eq!(plain_client.recv().await?.into_vec(), vec![data.clone()]);
+
+        // after saying hello it gets them compressed
+        plain_client.send(ZmqMessage::from(vec![0xf3])).await?;
+        let (hello, _) = tokio::join!(
+            plain_client.recv(),
+            tokio::time::timeout(Duration::from_millis(100), server.receive_message())
+        );
+        assert_eq!(hello?.into_vec(), vec![vec![0xf3]]);
+        server.send(b"ABD".to_vec().into(), data.clone()).await?;
+        let frame = plain_client.recv().await?.into_vec().remove(0);
+        assert_eq!(frame[0], 0xf2);
+        assert!(frame.len() < data.len() / 10);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn multipart_messages_are_kept_together() -> ZmqResult<()> {
+        let server = zmq::ServerSocket::new();
+        let endpoint = server.bind("tcp://127.0.0.1:0").await?;
+
+        let mut options = SocketOptions::default();
+        options.peer_identity(PeerIdentity::try_from(b"ABC".to_vec())?);
+        let mut client = DealerSocket::with_options(options);
+        client.connect(&endpoint.to_string()).await?;
+
+        let mut msg = ZmqMessage::from(vec![1]);
+        msg.push_back(vec![2, 3].into());
+        client.send(msg).await?;
+        client.send(ZmqMessage::from(vec![4])).await?;
+
+        let (peer_id, frames) = server.receive_message().await?;
+        assert_eq!(peer_id.as_bytes(), b"ABC");
+        assert_eq!(frames, vec![vec![1], vec![2, 3]]);
+
+        let (peer_id, frames) = server.receive_message().await?;
+        assert_eq!(peer_id.as_bytes(), b"ABC");
+        assert_eq!(frames, vec![vec![4]]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ipv6_endpoint() -> ZmqResult<()> {
+        let server = zmq::ServerSocket::new();
+        let endpoint = server.bind("tcp://[::1]:0").await?;
+        let port = zmq::endpoint_port(&endpoint).unwrap();
+        assert_ne!(port, 0);
+
+        let client = zmq::ClientSocket::new(b"ABC".to_vec());
+        client.connect("::1", port).await?;
+        client.send(vec![1, 2, 3]).await?;
+
+        let (peer_id, frames) = server.receive_message().await?;
+        assert_eq!(peer_id.as_bytes(), b"ABC");
+        assert_eq!(frames, vec![vec![1, 2, 3]]);
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn ipc_endpoint() -> ZmqResult<()> {
+        let path = std::env::temp_dir().join(format!("hexacraft-test-{}.sock", std::process::id()));
+        let endpoint = format!("ipc://{}", path.display());
+
+        let server = zmq::ServerSocket::new();
+        server.bind(&endpoint).await?;
+
+        let client = zmq::ClientSocket::new(b"ABC".to_vec());
+        client.connect_to(&endpoint).await?;
+        client.send(vec![1, 2, 3]).await?;
+
+        let (peer_id, frames) = server.receive_message().await?;
+        assert_eq!(peer_id.as_bytes(), b"ABC");
+        assert_eq!(frames, vec![vec![1, 2, 3]]);
+
+        drop(server);
+        let _ = std::fs::remove_file(path);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,116 @@
+//! Compression of large ZMQ frames using zstd.
+//!
+//! Compression is negotiated per connection. A client that wants it sends a hello frame right
+//! after connecting, and the server answers with a hello of its own if it supports it. Only then
+//! do the two sides start compressing, so old clients and servers keep working.
+//!
+//! Compressed frames start with a marker byte, just like encrypted frames. Compression happens
+//! before encryption, since encrypted data does not compress.
+
+use std::io::Read;
+
+use bytes::Bytes;
+use zeromq::{ZmqError, ZmqResult};
+
+pub(crate) const COMPRESSED_FRAME: u8 = 0xf2;
+pub(crate) const HELLO_FRAME: u8 = 0xf3;
+
+/// Frames are never decompressed to more than this, so a small frame cannot use up all memory
+const MAX_DECOMPRESSED_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Compression settings for a `ClientSocket` or a `ServerSocket`
+#[derive(Debug, Clone)]
+pub struct Compression {
+    /// Frames smaller than this are sent as they are, since they would barely shrink
+    pub threshold: usize,
+    /// The zstd level, from 1 (fastest) to 22 (smallest)
+    pub level: i32,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            threshold: 1024,
+            level: 3,
+        }
+    }
+}
+
+impl Compression {
+    /// Compresses a frame if it is large enough and actually gets smaller. The result starts with
+    /// the marker byte.
+    pub fn compress(&self, data: &[u8]) -> Option<Vec<u8>> {
+        if data.len() < self.threshold {
+            return None;
+        }
+        let mut res = vec![COMPRESSED_FRAME];
+        zstd::stream::copy_encode(data, &mut res, self.level).ok()?;
+        (res.len() < data.len()).then_some(res)
+    }
+}
+
+pub(crate) fn hello() -> Vec<u8> {
+    vec![HELLO_FRAME]
+}
+
+pub(crate) fn is_hello(frames: &[Bytes]) -> bool {
+    matches!(frames, [frame] if frame.as_ref() == [HELLO_FRAME])
+}
+
+/// Decompresses the frame if it was compressed, otherwise it is returned as it is
+pub(crate) fn decompress(frame: Bytes) -> ZmqResult<Bytes> {
+    let Some((&COMPRESSED_FRAME, data)) = frame.split_first() else {
+        return Ok(frame);
+    };
+
+    let decoder = zstd::stream::Decoder::new(data)
+        .map_err(|_| ZmqError::Other("invalid compressed frame"))?;
+    let mut res = Vec::new();
+    decoder
+        .take(MAX_DECOMPRESSED_SIZE + 1)
+        .read_to_end(&mut res)
+        .map_err(|_| ZmqError::Other("invalid compressed frame"))?;
+    if res.len() as u64 > MAX_DECOMPRESSED_SIZE {
+        return Err(ZmqError::Other("compressed frame is too large"));
+    }
+    Ok(res.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::{Compression, decompress};
+
+    #[test]
+    fn only_large_frames_are_compressed() {
+        let compression = Compression {
+            threshold: 100,
+            level: 3,
+        };
+
+        assert_eq!(compression.compress(&[0; 99]), None);
+
+        let data = vec![7; 10_000];
+        let compressed = compression.compress(&data).unwrap();
+        assert!(compressed.len() < 100);
+        assert_eq!(decompress(compressed.into()).unwrap(), data);
+
+        // plain NBT frames pass through untouched
+        let plain = Bytes::from(vec![10, 0, 0, 0]);
+        assert_eq!(decompress(plain.clone()).unwrap(), plain);
+    }
+
+    #[test]
+    fn incompressible_frames_are_sent_as_they_are() {
+        let compression = Compression::default();
+        let mut state = 12345u32;
+        let random: Vec<u8> = (0..4096)
+            .map(|_| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        assert_eq!(compression.compress(&random), None);
+    }
+}
@@ -0,0 +1,338 @@
+//! Encryption of ZMQ frames using the Noise protocol.
+//!
+//! The client starts a `Noise_NX` handshake, in which the server proves that it owns its static
+//! key. The client then checks that key against a pinned key or a list of known servers, just
+//! like ssh does with `known_hosts`.
+//!
+//! Every frame starts with a marker byte so encrypted frames can be told apart from plaintext
+//! ones, which are NBT data and therefore start with a tag id.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use snow::{HandshakeState, TransportState};
+use zeromq::{ZmqError, ZmqResult};
+
+const NOISE_PARAMS: &str = "Noise_NX_25519_ChaChaPoly_BLAKE2s";
+
+pub(crate) const HANDSHAKE_FRAME: u8 = 0xf0;
+pub(crate) const ENCRYPTED_FRAME: u8 = 0xf1;
+
+/// The largest message Noise can handle, including the authentication tag
+const MAX_NOISE_MESSAGE: usize = 65535;
+const TAG_LEN: usize = 16;
+const MAX_CHUNK: usize = MAX_NOISE_MESSAGE - TAG_LEN;
+
+/// The static keypair of a server. The public key is what clients pin.
+#[derive(Clone)]
+pub struct Keypair {
+    private: Vec<u8>,
+    public: Vec<u8>,
+}
+
+impl Keypair {
+    pub fn generate() -> Self {
+        let keypair = builder()
+            .generate_keypair()
+            .expect("failed to generate keypair");
+        Self {
+            private: keypair.private,
+            public: keypair.public,
+        }
+    }
+
+    /// Loads the keypair stored in `server.key` in the world directory, or creates a new one if
+    /// there is none. The file contains the private key followed by the public key.
+    pub fn load_or_create(world_dir: &Path) -> io::Result<Self> {
+        let path = world_dir.join("server.key");
+        match fs::read(&path) {
+            Ok(bytes) if bytes.len() == 64 => Ok(Self {
+                private: bytes[..32].to_vec(),
+                public: bytes[32..].to_vec(),
+            }),
+            Ok(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a valid key file", path.display()),
+            )),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let keypair = Self::generate();
+                fs::create_dir_all(world_dir)?;
+
+                let mut options = fs::OpenOptions::new();
+                options.write(true).create_new(true);
+                // only the server itself should be able to read the private key
+                #[cfg(unix)]
+                std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+                options
+                    .open(&path)?
+                    .write_all(&[keypair.private.as_slice(), &keypair.public].concat())?;
+                Ok(keypair)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        &self.public
+    }
+}
+
+/// Encryption settings for a `ServerSocket`
+#[derive(Clone)]
+pub struct ServerEncryption {
+    pub keypair: Keypair,
+    /// If set, messages from clients that did not do the handshake are dropped
+    pub required: bool,
+}
+
+/// How a client decides whether to trust the key of a server
+#[derive(Debug, Clone)]
+pub enum ServerTrust {
+    /// Only a server with exactly this public key is accepted
+    Pinned(Vec<u8>),
+    /// The key of a new server is accepted and saved in the file. If the server later shows up
+    /// with a different key the connection is refused.
+    TrustOnFirstUse { known_servers: PathBuf },
+}
+
+impl ServerTrust {
+    fn check(&self, endpoint: &str, key: &[u8]) -> ZmqResult<()> {
+        match self {
+            ServerTrust::Pinned(pinned) => {
+                if pinned.as_slice() == key {
+                    Ok(())
+                } else {
+                    Err(ZmqError::Other("server key does not match the pinned key"))
+                }
+            }
+            ServerTrust::TrustOnFirstUse { known_servers } => {
+                match known_server_key(known_servers, endpoint) {
+                    Some(known) if known == key => Ok(()),
+                    Some(_) => Err(ZmqError::Other("server key has changed since last time")),
+                    None => add_known_server(known_servers, endpoint, key)
+                        .map_err(|_| ZmqError::Other("could not save server key")),
+                }
+            }
+        }
+    }
+}
+
+/// Known servers are stored one per line, as the endpoint followed by the key in hex
+fn known_server_key(path: &Path, endpoint: &str) -> Option<Vec<u8>> {
+    let content = fs::read_to_string(path).ok()?;
+    content.lines().find_map(|line| {
+        let (e, key) = line.split_once(' ')?;
+        if e == endpoint { from_hex(key) } else { None }
+    })
+}
+
+fn add_known_server(path: &Path, endpoint: &str, key: &[u8]) -> io::Result<()> {
+    use std::io::Write;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{endpoint} {}", to_hex(key))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn builder() -> snow::Builder<'static> {
+    snow::Builder::new(NOISE_PARAMS.parse().unwrap())
+}
+
+/// The client side of a handshake, which takes one message in each direction
+pub(crate) struct ClientHandshake {
+    state: HandshakeState,
+}
+
+impl ClientHandshake {
+    /// Starts the handshake and returns the first message to send
+    pub fn start() -> ZmqResult<(Self, Vec<u8>)> {
+        let mut state = builder().build_initiator().map_err(handshake_error)?;
+        let mut buf = vec![0; MAX_NOISE_MESSAGE];
+        let len = state
+            .write_message(&[], &mut buf)
+            .map_err(handshake_error)?;
+        Ok((Self { state }, frame(HANDSHAKE_FRAME, &buf[..len])))
+    }
+
+    /// Finishes the handshake using the response from the server
+    pub fn finish(
+        mut self,
+        response: &[u8],
+        trust: &ServerTrust,
+        endpoint: &str,
+    ) -> ZmqResult<TransportState> {
+        let Some((&HANDSHAKE_FRAME, response)) = response.split_first() else {
+            return Err(ZmqError::Other("server did not respond to the handshake"));
+        };
+        let mut buf = vec![0; MAX_NOISE_MESSAGE];
+        self.state
+            .read_message(response, &mut buf)
+            .map_err(handshake_error)?;
+
+        let server_key = self
+            .state
+            .get_remote_static()
+            .ok_or(ZmqError::Other("server did not send its key"))?;
+        trust.check(endpoint, server_key)?;
+
+        self.state.into_transport_mode().map_err(handshake_error)
+    }
+}
+
+/// Handles the first handshake message from a client and returns the response to send back
+pub(crate) fn server_handshake(
+    keypair: &Keypair,
+    message: &[u8],
+) -> ZmqResult<(TransportState, Vec<u8>)> {
+    let mut state = builder()
+        .local_private_key(&keypair.private)
+        .build_responder()
+        .map_err(handshake_error)?;
+
+    let mut buf = vec![0; MAX_NOISE_MESSAGE];
+    state
+        .read_message(message, &mut buf)
+        .map_err(handshake_error)?;
+    let len = state
+        .write_message(&[], &mut buf)
+        .map_err(handshake_error)?;
+
+    let transport = state.into_transport_mode().map_err(handshake_error)?;
+    Ok((transport, frame(HANDSHAKE_FRAME, &buf[..len])))
+}
+
+fn handshake_error(_: snow::Error) -> ZmqError {
+    ZmqError::Other("encryption handshake failed")
+}
+
+fn frame(marker: u8, data: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(data.len() + 1);
+    res.push(marker);
+    res.extend_from_slice(data);
+    res
+}
+
+/// Encrypts a frame. Noise messages are limited to 64 KiB, so larger frames are split into
+/// chunks, each prefixed by its length.
+pub(crate) fn encrypt(state: &mut TransportState, data: &[u8]) -> ZmqResult<Vec<u8>> {
+    let mut res = vec![ENCRYPTED_FRAME];
+    let mut buf = vec![0; MAX_NOISE_MESSAGE];
+    // an empty frame still needs one chunk
+    for chunk in data
+        .chunks(MAX_CHUNK)
+        .chain(data.is_empty().then_some(&[][..]))
+    {
+        let len = state
+            .write_message(chunk, &mut buf)
+            .map_err(|_| ZmqError::Other("encryption failed"))?;
+        res.extend_from_slice(&(len as u16).to_be_bytes());
+        res.extend_from_slice(&buf[..len]);
+    }
+    Ok(res)
+}
+
+pub(crate) fn decrypt(state: &mut TransportState, frame: &[u8]) -> ZmqResult<Vec<u8>> {
+    let Some((&ENCRYPTED_FRAME, mut rest)) = frame.split_first() else {
+        return Err(ZmqError::Other("frame was not encrypted"));
+    };
+
+    let mut res = Vec::with_capacity(rest.len());
+    let mut buf = vec![0; MAX_NOISE_MESSAGE];
+    while !rest.is_empty() {
+        let invalid = || ZmqError::Other("invalid encrypted frame");
+        let (len, tail) = rest.split_first_chunk::<2>().ok_or_else(invalid)?;
+        let len = u16::from_be_bytes(*len) as usize;
+        let chunk = tail.get(..len).ok_or_else(invalid)?;
+        let n = state
+            .read_message(chunk, &mut buf)
+            .map_err(|_| ZmqError::Other("decryption failed"))?;
+        res.extend_from_slice(&buf[..n]);
+        rest = &tail[len..];
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientHandshake, Keypair, ServerTrust, decrypt, encrypt, server_handshake};
+
+    #[test]
+    fn large_frames_are_split_into_chunks() {
+        let keypair = Keypair::generate();
+        let trust = ServerTrust::Pinned(keypair.public_key().to_vec());
+
+        let (client, request) = ClientHandshake::start().unwrap();
+        let (mut server, response) = server_handshake(&keypair, &request[1..]).unwrap();
+        let mut client = client.finish(&response, &trust, "test").unwrap();
+
+        let data: Vec<u8> = (0..200_000).map(|i| i as u8).collect();
+        let encrypted = encrypt(&mut client, &data).unwrap();
+        assert_eq!(decrypt(&mut server, &encrypted).unwrap(), data);
+
+        let encrypted = encrypt(&mut server, &[]).unwrap();
+        assert_eq!(decrypt(&mut client, &encrypted).unwrap(), Vec::<u8>::new());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_the_owner_can_read_the_key_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("hexacraft-server-key-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let keypair = Keypair::load_or_create(&dir).unwrap();
+        let mode = std::fs::metadata(dir.join("server.key"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(
+            Keypair::load_or_create(&dir).unwrap().public_key(),
+            keypair.public_key()
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn trust_on_first_use() {
+        let known_servers = std::env::temp_dir().join(format!(
+            "hexacraft-known-servers-{}.txt",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&known_servers);
+        let trust = ServerTrust::TrustOnFirstUse {
+            known_servers: known_servers.clone(),
+        };
+
+        assert!(trust.check("tcp://a:1", &[1, 2, 3]).is_ok());
+        assert!(trust.check("tcp://a:1", &[1, 2, 3]).is_ok());
+        assert!(trust.check("tcp://b:1", &[4, 5, 6]).is_ok());
+        assert!(trust.check("tcp://a:1", &[4, 5, 6]).is_err());
+
+        let _ = std::fs::remove_file(known_servers);
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::Duration,
+};
+
+use tokio::{sync::Notify, time::Instant};
+
+use crate::transport::{PeerEvent, PeerId};
+
+/// Keeps track of which clients are connected to a `ServerSocket`.
+///
+/// Clients are considered gone when the socket monitor reports it. Since the router socket does
+/// not always do that, they are also considered gone if a send to them fails, or if they have not
+/// sent anything for a while.
+pub(crate) struct PeerTracker {
+    timeout: Duration,
+    last_seen: Mutex<HashMap<PeerId, Instant>>,
+    events: Mutex<VecDeque<PeerEvent>>,
+    changed: Notify,
+}
+
+impl PeerTracker {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            last_seen: Mutex::new(HashMap::new()),
+            events: Mutex::new(VecDeque::new()),
+            changed: Notify::new(),
+        }
+    }
+
+    /// Should be called whenever there is a sign of life from a peer
+    pub fn seen(&self, peer_id: &PeerId) {
+        let is_new = self
+            .last_seen
+            .lock()
+            .unwrap()
+            .insert(peer_id.clone(), Instant::now())
+            .is_none();
+        if is_new {
+            self.push_event(PeerEvent::Connected(peer_id.clone()));
+        }
+    }
+
+    pub fn lost(&self, peer_id: &PeerId) {
+        if self.last_seen.lock().unwrap().remove(peer_id).is_some() {
+            self.push_event(PeerEvent::Disconnected(peer_id.clone()));
+        }
+    }
+
+    fn push_event(&self, event: PeerEvent) {
+        self.events.lock().unwrap().push_back(event);
+        self.changed.notify_one();
+    }
+
+    /// Returns the next event if there is one. Otherwise it waits until there might be one, and
+    /// returns `None` so the caller can try again.
+    pub async fn next_event(&self) -> Option<PeerEvent> {
+        let changed = self.changed.notified();
+
+        if let Some(event) = self.events.lock().unwrap().pop_front() {
+            return Some(event);
+        }
+
+        let next_deadline = self.expire_idle_peers();
+        if let Some(event) = self.events.lock().unwrap().pop_front() {
+            return Some(event);
+        }
+
+        tokio::select! {
+            _ = changed => {}
+            _ = tokio::time::sleep_until(next_deadline) => {}
+        }
+        None
+    }
+
+    /// Removes peers that have been quiet for too long and returns when the next one would expire
+    fn expire_idle_peers(&self) -> Instant {
+        let now = Instant::now();
+        let mut next_deadline = now + self.timeout;
+
+        let mut expired = Vec::new();
+        self.last_seen.lock().unwrap().retain(|peer_id, last_seen| {
+            let deadline = *last_seen + self.timeout;
+            if deadline <= now {
+                expired.push(peer_id.clone());
+                false
+            } else {
+                next_deadline = next_deadline.min(deadline);
+                true
+            }
+        });
+
+        for peer_id in expired {
+            self.push_event(PeerEvent::Disconnected(peer_id));
+        }
+        next_deadline
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    collections::VecDeque,
+    num::NonZeroUsize,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use bytes::Bytes;
+use tokio::sync::Notify;
+
+/// What to do with a received frame when the receive queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Stop receiving until there is room in the queue. The sender will eventually be slowed down.
+    Block,
+    /// Make room by dropping the oldest frame in the queue
+    DropOldest,
+    /// Give up on the connection
+    Disconnect,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReceiveQueueConfig {
+    /// The maximum number of frames in the queue. A queue without room could never take a frame.
+    pub high_water_mark: NonZeroUsize,
+    pub policy: OverflowPolicy,
+}
+
+impl Default for ReceiveQueueConfig {
+    fn default() -> Self {
+        Self {
+            high_water_mark: NonZeroUsize::new(10_000).unwrap(),
+            policy: OverflowPolicy::Block,
+        }
+    }
+}
+
+/// A queue of received frames with a bounded size
+pub(crate) struct ReceiveQueue {
+    config: ReceiveQueueConfig,
+    frames: Mutex<VecDeque<Bytes>>,
+    space_available: Notify,
+    dropped: AtomicU64,
+}
+
+impl ReceiveQueue {
+    pub fn new(config: ReceiveQueueConfig) -> Self {
+        Self {
+            config,
+            frames: Mutex::new(VecDeque::new()),
+            space_available: Notify::new(),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Adds a frame to the queue, applying the overflow policy if it is full.
+    ///
+    /// Returns false if the frame was refused and the connection should be closed.
+    pub async fn push(&self, frame: Bytes) -> bool {
+        loop {
+            let notified = self.space_available.notified();
+            {
+                let mut frames = self.frames.lock().unwrap();
+                if frames.len() < self.config.high_water_mark.get() {
+                    frames.push_back(frame);
+                    return true;
+                }
+                match self.config.policy {
+                    OverflowPolicy::Block => {}
+                    OverflowPolicy::DropOldest => {
+                        frames.pop_front();
+                        frames.push_back(frame);
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        return true;
+                    }
+                    OverflowPolicy::Disconnect => {
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        return false;
+                    }
+                }
+            }
+            notified.await;
+        }
+    }
+
+    pub fn pop(&self) -> Option<Bytes> {
+        let frame = self.frames.lock().unwrap().pop_front();
+        if frame.is_some() {
+            self.space_available.notify_one();
+        }
+        frame
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.lock().unwrap().len()
+    }
+
+    /// The number of frames that have been thrown away because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroUsize, sync::Arc, time::Duration};
+
+    use bytes::Bytes;
+
+    use super::{OverflowPolicy, ReceiveQueue, ReceiveQueueConfig};
+
+    fn queue(policy: OverflowPolicy) -> Arc<ReceiveQueue> {
+        Arc::new(ReceiveQueue::new(ReceiveQueueConfig {
+            high_water_mark: NonZeroUsize::new(2).unwrap(),
+            policy,
+        }))
+    }
+
+    #[tokio::test]
+    async fn drop_oldest() {
+        let queue = queue(OverflowPolicy::DropOldest);
+        for i in 0..4 {
+            assert!(queue.push(Bytes::from(vec![i])).await);
+        }
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.dropped(), 2);
+        assert_eq!(queue.pop(), Some(Bytes::from(vec![2])));
+        assert_eq!(queue.pop(), Some(Bytes::from(vec![3])));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[tokio::test]
+    async fn disconnect() {
+        let queue = queue(OverflowPolicy::Disconnect);
+        assert!(queue.push(Bytes::from(vec![0])).await);
+        assert!(queue.push(Bytes::from(vec![1])).await);
+        assert!(!queue.push(Bytes::from(vec![2])).await);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.dropped(), 1);
+    }
+
+    #[tokio::test]
+    async fn block_until_there_is_room() {
+        let queue = queue(OverflowPolicy::Block);
+        assert!(queue.push(Bytes::from(vec![0])).await);
+        assert!(queue.push(Bytes::from(vec![1])).await);
+
+        let pusher = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.push(Bytes::from(vec![2])).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!pusher.is_finished());
+
+        assert_eq!(queue.pop(), Some(Bytes::from(vec![0])));
+        assert!(pusher.await.unwrap());
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.dropped(), 0);
+    }
+}
@@ -1,25 +1,92 @@
+use std::num::NonZeroUsize;
 use std::sync::Arc;
 use std::time::Duration;
 
 use crate::handle::Handle;
-use crate::{run_with_timeout, throw_rte};
+use crate::{run_and_wait, run_with_timeout, throw_rte};
 
+use hexacraft::zmq::{Compression, OverflowPolicy, ReceiveQueueConfig, ServerTrust};
 use jni::JNIEnv;
 use jni::objects::{AsJArrayRaw, JByteArray, JClass, JObject, JString};
-use jni::sys::{jbyteArray, jint};
+use jni::sys::{jbyteArray, jint, jlong};
 use jni_fn::jni_fn;
 
+/// `overflow_policy` is 0 (block), 1 (drop oldest) or 2 (disconnect)
+fn receive_queue_config(high_water_mark: jint, overflow_policy: jint) -> ReceiveQueueConfig {
+    let high_water_mark = usize::try_from(high_water_mark)
+        .ok()
+        .and_then(NonZeroUsize::new)
+        .expect("high water mark must be positive");
+
+    let policy = match overflow_policy {
+        0 => OverflowPolicy::Block,
+        1 => OverflowPolicy::DropOldest,
+        2 => OverflowPolicy::Disconnect,
+        _ => panic!("unknown overflow policy: {overflow_policy}"),
+    };
+
+    ReceiveQueueConfig {
+        high_water_mark,
+        policy,
+    }
+}
+
 #[jni_fn("hexacraft.rs.RustLib$ClientSocket")]
 pub fn create<'local>(
     env: JNIEnv<'local>,
     _class: JClass<'local>,
     client_id: JByteArray<'local>,
+    high_water_mark: jint,
+    overflow_policy: jint,
+) -> Handle<Arc<hexacraft::zmq::ClientSocket>> {
+    let client_id = env
+        .convert_byte_array(client_id)
+        .expect("failed to convert byte array");
+
+    Handle::create(Arc::new(
+        hexacraft::zmq::ClientSocket::new(client_id)
+            .with_compression(Compression::default())
+            .with_receive_queue(receive_queue_config(high_water_mark, overflow_policy)),
+    ))
+}
+
+/// Creates a socket that encrypts its traffic. If `server_key` is null the key of the server is
+/// trusted on first use, and remembered in the `known_servers` file.
+#[jni_fn("hexacraft.rs.RustLib$ClientSocket")]
+pub fn createEncrypted<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    client_id: JByteArray<'local>,
+    server_key: JByteArray<'local>,
+    known_servers: JString<'local>,
+    high_water_mark: jint,
+    overflow_policy: jint,
 ) -> Handle<Arc<hexacraft::zmq::ClientSocket>> {
     let client_id = env
         .convert_byte_array(client_id)
         .expect("failed to convert byte array");
 
-    Handle::create(Arc::new(hexacraft::zmq::ClientSocket::new(client_id)))
+    let trust = if server_key.is_null() {
+        let known_servers = env
+            .get_string(&known_servers)
+            .expect("failed to read string");
+        let known_servers = known_servers.to_str().expect("invalid utf8").to_string();
+        ServerTrust::TrustOnFirstUse {
+            known_servers: known_servers.into(),
+        }
+    } else {
+        ServerTrust::Pinned(
+            env.convert_byte_array(server_key)
+                .expect("failed to convert byte array"),
+        )
+    };
+
+    Handle::create(Arc::new(
+        hexacraft::zmq::ClientSocket::new(client_id)
+            .with_encryption(trust)
+            .with_compression(Compression::default())
+            .with_receive_queue(receive_queue_config(high_water_mark, overflow_policy)),
+    ))
 }
 
 #[jni_fn("hexacraft.rs.RustLib$ClientSocket")]
@@ -39,7 +106,9 @@ pub fn connect<'local>(
         let socket = socket.clone();
         run_with_timeout(Duration::from_millis(3000), async move {
             let res = socket.connect(&host, port as u16).await;
-            tokio::spawn(socket.clone().run_receiver());
+            if res.is_ok() {
+                tokio::spawn(socket.clone().run_receiver());
+            }
             res
         })
     }) {
@@ -88,9 +157,7 @@ pub fn tryReceive<'local>(
             throw_rte(&mut env, "timed out receiving");
             *JObject::null()
         }
-        Some(None) => {
-            *JObject::null()
-        }
+        Some(None) => *JObject::null(),
         Some(Some(data)) => env
             .byte_array_from_slice(&data)
             .expect("failed to create byte array")
@@ -98,11 +165,64 @@ pub fn tryReceive<'local>(
     }
 }
 
+/// Waits at most `timeout_ms` milliseconds for data to arrive. Returns null if none arrived.
+#[jni_fn("hexacraft.rs.RustLib$ClientSocket")]
+pub fn receive<'local>(
+    env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<hexacraft::zmq::ClientSocket>>,
+    timeout_ms: jlong,
+) -> jbyteArray {
+    let timeout = Duration::from_millis(timeout_ms.max(0) as u64);
+    let data = handle.use_handle(|socket| {
+        let socket = socket.clone();
+        run_and_wait(async move { socket.receive_timeout(timeout).await })
+    });
+    match data {
+        None => *JObject::null(),
+        Some(data) => env
+            .byte_array_from_slice(&data)
+            .expect("failed to create byte array")
+            .as_jarray_raw(),
+    }
+}
+
+/// Returns 0 (connecting), 1 (connected), 2 (reconnecting) or 3 (closed)
+#[jni_fn("hexacraft.rs.RustLib$ClientSocket")]
+pub fn connectionState<'local>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<hexacraft::zmq::ClientSocket>>,
+) -> jint {
+    handle.use_handle(|socket| socket.connection_state().id())
+}
+
+/// The number of received messages waiting to be picked up
+#[jni_fn("hexacraft.rs.RustLib$ClientSocket")]
+pub fn queueDepth<'local>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<hexacraft::zmq::ClientSocket>>,
+) -> jlong {
+    handle.use_handle(|socket| socket.queue_depth() as jlong)
+}
+
+/// The number of received messages that were dropped because too many were waiting
+#[jni_fn("hexacraft.rs.RustLib$ClientSocket")]
+pub fn droppedMessages<'local>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<hexacraft::zmq::ClientSocket>>,
+) -> jlong {
+    handle.use_handle(|socket| socket.dropped_frames() as jlong)
+}
+
 #[jni_fn("hexacraft.rs.RustLib$ClientSocket")]
 pub fn close<'local>(
     _env: JNIEnv<'local>,
     _class: JClass<'local>,
     handle: Handle<Arc<hexacraft::zmq::ClientSocket>>,
 ) {
+    handle.use_handle(|socket| socket.close());
     handle.destroy();
 }
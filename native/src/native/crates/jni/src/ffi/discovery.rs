@@ -0,0 +1,106 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::handle::Handle;
+use crate::{run_with_timeout, throw_rte};
+
+use hexacraft::server::discovery::{self, DiscoveryListener};
+use hexacraft::server::nbt;
+use jni::JNIEnv;
+use jni::objects::{AsJArrayRaw, JClass, JString};
+use jni::sys::jbyteArray;
+use jni_fn::jni_fn;
+
+/// Reads an address like "239.255.72.67:34254", or returns the default one if it is null
+pub(crate) fn read_address(env: &mut JNIEnv, address: &JString) -> Result<SocketAddr, String> {
+    if address.is_null() {
+        return Ok(discovery::default_address());
+    }
+    let address = env.get_string(address).expect("failed to read string");
+    let address = address.to_str().expect("invalid utf8").to_string();
+    address
+        .parse()
+        .map_err(|err| format!("invalid address '{address}': {err}"))
+}
+
+#[jni_fn("hexacraft.rs.RustLib$Discovery")]
+pub fn listen<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    address: JString<'local>,
+) -> Handle<Arc<DiscoveryListener>> {
+    let address = match read_address(&mut env, &address) {
+        Ok(address) => address,
+        Err(err) => {
+            throw_rte(&mut env, err);
+            return Handle::null();
+        }
+    };
+
+    // the socket has to be created inside the runtime
+    let listener = run_with_timeout(Duration::from_millis(1000), async move {
+        let listener = Arc::new(DiscoveryListener::bind(address)?);
+        tokio::spawn({
+            let listener = listener.clone();
+            async move { listener.run().await }
+        });
+        Ok::<_, std::io::Error>(listener)
+    });
+
+    match listener {
+        None => {
+            throw_rte(&mut env, "timed out starting discovery");
+            Handle::null()
+        }
+        Some(Err(err)) => {
+            throw_rte(&mut env, format!("failed to listen for servers: {err}"));
+            Handle::null()
+        }
+        Some(Ok(listener)) => Handle::create(listener),
+    }
+}
+
+/// Returns the servers that are currently visible, see `RustLib.Discovery.servers`
+#[jni_fn("hexacraft.rs.RustLib$Discovery")]
+pub fn servers<'local>(
+    env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<DiscoveryListener>>,
+) -> jbyteArray {
+    let servers = handle.use_handle(|listener| listener.servers());
+
+    let servers = servers
+        .into_iter()
+        .map(|server| {
+            let a = server.announcement;
+            nbt::MapTag::new()
+                .set("host", nbt::Tag::String(server.host.to_string()))
+                .set("port", nbt::Tag::Short(a.port as i16))
+                .set("name", nbt::Tag::String(a.name))
+                .set(
+                    "protocol_version",
+                    nbt::Tag::Short(a.protocol_version as i16),
+                )
+                .set("players", nbt::Tag::Int(a.players as i32))
+                .build()
+        })
+        .collect();
+    let tag = nbt::MapTag::new()
+        .set("servers", nbt::Tag::List(servers))
+        .build();
+
+    env.byte_array_from_slice(&tag.to_binary())
+        .expect("failed to create byte array")
+        .as_jarray_raw()
+}
+
+#[jni_fn("hexacraft.rs.RustLib$Discovery")]
+pub fn stop<'local>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<DiscoveryListener>>,
+) {
+    handle.use_handle(|listener| listener.close());
+    handle.destroy();
+}
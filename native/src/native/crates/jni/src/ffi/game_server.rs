@@ -1,34 +1,124 @@
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 
+use crate::ffi::discovery::read_address;
 use crate::handle::Handle;
-use crate::run_with_timeout;
+use crate::{run_with_timeout, throw_rte};
 
-use hexacraft::server::{GameServer, GameState};
+use hexacraft::ZmqError;
+use hexacraft::server::{
+    GameServer, GameState,
+    auth::verifier_from_config,
+    discovery::Announcer,
+    world_config::{WorldConfig, open_world},
+};
+use hexacraft::transport::{InMemoryClient, InMemoryServer};
+use hexacraft::zmq::{Compression, Keypair, ServerEncryption, ServerSocket};
 use jni::JNIEnv;
-use jni::objects::{JClass, JString};
-use jni::sys::{jboolean, jint};
+use jni::objects::{AsJArrayRaw, JByteArray, JClass, JObject, JString};
+use jni::sys::{jboolean, jbyteArray, jint};
 use jni_fn::jni_fn;
+use tracing::warn;
 
 #[jni_fn("hexacraft.rs.RustLib$GameServer")]
 pub fn start<'local>(
     mut env: JNIEnv<'local>,
     _class: JClass<'local>,
     is_online: jboolean,
-    port: jint,
+    endpoint: JString<'local>,
     path: JString<'local>,
+    require_encryption: jboolean,
+    auth_service: JString<'local>,
 ) -> Handle<Arc<GameServer<GameState>>> {
     let is_online = is_online == 1;
+    let require_encryption = require_encryption == 1;
 
-    assert!((0..1 << 16).contains(&port), "port is out of range");
-    let port = port as u16;
+    let endpoint = env.get_string(&endpoint).expect("failed to read string");
+    let endpoint = endpoint.to_str().expect("invalid utf8").to_string();
 
     let path = env.get_string(&path).expect("failed to read string");
     let path = path.to_str().expect("invalid utf8").to_string();
 
+    let state = match create_state(&mut env, is_online, &path, &auth_service) {
+        Ok(state) => state,
+        Err(err) => {
+            throw_rte(&mut env, err);
+            return Handle::null();
+        }
+    };
+    let keypair = match Keypair::load_or_create(Path::new(&path)) {
+        Ok(keypair) => keypair,
+        Err(err) => {
+            throw_rte(&mut env, format!("failed to load server key: {err}"));
+            return Handle::null();
+        }
+    };
+
+    let socket = ServerSocket::new()
+        .with_encryption(ServerEncryption {
+            keypair,
+            required: require_encryption,
+        })
+        .with_compression(Compression::default());
+
+    let server = run_with_timeout(Duration::from_millis(1000), async move {
+        let state = Arc::new(state);
+        let server =
+            Arc::new(GameServer::start_with_socket(socket, &endpoint, state.clone()).await?);
+        tokio::spawn({
+            let state = state.clone();
+            async move { state.run_ticks().await }
+        });
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.run_receiver().await }
+        });
+        Ok::<_, ZmqError>(server)
+    });
+
+    match server {
+        None => {
+            throw_rte(&mut env, "timed out starting server");
+            Handle::null()
+        }
+        Some(Err(err)) => {
+            throw_rte(&mut env, format!("failed to start server: {err}"));
+            Handle::null()
+        }
+        Some(Ok(server)) => Handle::create(server),
+    }
+}
+
+/// Starts a server that only clients in the same process can connect to, using `connectInProcess`.
+/// It is meant for single-player games, which have no reason to go through the network.
+#[jni_fn("hexacraft.rs.RustLib$GameServer")]
+pub fn startInProcess<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    is_online: jboolean,
+    path: JString<'local>,
+    auth_service: JString<'local>,
+) -> Handle<Arc<GameServer<GameState, InMemoryServer>>> {
+    let is_online = is_online == 1;
+
+    let path = env.get_string(&path).expect("failed to read string");
+    let path = path.to_str().expect("invalid utf8").to_string();
+
+    let state = match create_state(&mut env, is_online, &path, &auth_service) {
+        Ok(state) => state,
+        Err(err) => {
+            throw_rte(&mut env, err);
+            return Handle::null();
+        }
+    };
+
     let server = run_with_timeout(Duration::from_millis(1000), async move {
-        let state = Arc::new(GameState::create(is_online, path));
-        let server = Arc::new(GameServer::start(port, state.clone()).await);
+        let state = Arc::new(state);
+        let server = Arc::new(GameServer::new(
+            Arc::new(InMemoryServer::new()),
+            state.clone(),
+        ));
         tokio::spawn({
             let state = state.clone();
             async move { state.run_ticks().await }
@@ -38,10 +128,135 @@ pub fn start<'local>(
             async move { server.run_receiver().await }
         });
         server
+    });
+
+    match server {
+        None => {
+            throw_rte(&mut env, "timed out starting server");
+            Handle::null()
+        }
+        Some(server) => Handle::create(server),
+    }
+}
+
+/// Connects a client to a server started with `startInProcess`. The returned handle is used with
+/// `InProcessClient`.
+#[jni_fn("hexacraft.rs.RustLib$GameServer")]
+pub fn connectInProcess<'local>(
+    env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<GameServer<GameState, InMemoryServer>>>,
+    client_id: JByteArray<'local>,
+) -> Handle<Arc<InMemoryClient>> {
+    let client_id = env
+        .convert_byte_array(client_id)
+        .expect("failed to convert byte array");
+
+    let client = handle.use_handle(|server| server.connect(client_id));
+    Handle::create(Arc::new(client))
+}
+
+#[jni_fn("hexacraft.rs.RustLib$GameServer")]
+pub fn stopInProcess<'local>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<GameServer<GameState, InMemoryServer>>>,
+) {
+    handle.use_handle(|server| {
+        let shutdown_task = {
+            let server = server.clone();
+            async move { server.shutdown().await }
+        };
+        if run_with_timeout(Duration::from_millis(1000), shutdown_task).is_none() {
+            warn!("Server shutdown timed out. The server will now be forced to shut down.");
+        }
+        server.cancel();
+    });
+    handle.destroy();
+}
+
+/// Opens the world at `path`, creating it if needed, and sets up authentication if an auth
+/// service is given
+fn create_state(
+    env: &mut JNIEnv,
+    is_online: bool,
+    path: &str,
+    auth_service: &JString,
+) -> Result<GameState, String> {
+    // the world is created here if needed, and is otherwise opened as it is
+    let world = open_world(Path::new(path), &WorldConfig::default(), false)
+        .map_err(|err| format!("failed to open world: {err}"))?;
+    let mut state = GameState::create(is_online, world);
+    if !auth_service.is_null() {
+        let auth_service = env.get_string(auth_service).expect("failed to read string");
+        let auth_service = auth_service.to_str().expect("invalid utf8").to_string();
+        let verifier = verifier_from_config(&auth_service)
+            .map_err(|err| format!("failed to set up authentication: {err}"))?;
+        state = state.with_token_verifier(verifier);
+    }
+    Ok(state)
+}
+
+/// Returns the port the server is listening on, or -1 if it is not listening on a port
+#[jni_fn("hexacraft.rs.RustLib$GameServer")]
+pub fn localPort<'local>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<GameServer<GameState>>>,
+) -> jint {
+    handle.use_handle(|server| {
+        server
+            .local_endpoint()
+            .and_then(hexacraft::zmq::endpoint_port)
+            .map_or(-1, jint::from)
     })
-    .unwrap();
+}
 
-    Handle::create(server)
+/// Returns the public key of the server, which clients can pin
+#[jni_fn("hexacraft.rs.RustLib$GameServer")]
+pub fn publicKey<'local>(
+    env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<GameServer<GameState>>>,
+) -> jbyteArray {
+    let key = handle.use_handle(|server| server.public_key().map(<[u8]>::to_vec));
+    match key {
+        None => *JObject::null(),
+        Some(key) => env
+            .byte_array_from_slice(&key)
+            .expect("failed to create byte array")
+            .as_jarray_raw(),
+    }
+}
+
+/// Announces the server on the local network until it stops. If `address` is null the default
+/// multicast group is used.
+#[jni_fn("hexacraft.rs.RustLib$GameServer")]
+pub fn announce<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<GameServer<GameState>>>,
+    name: JString<'local>,
+    address: JString<'local>,
+) {
+    let name = env.get_string(&name).expect("failed to read string");
+    let name = name.to_str().expect("invalid utf8").to_string();
+    let address = match read_address(&mut env, &address) {
+        Ok(address) => address,
+        Err(err) => return throw_rte(&mut env, err),
+    };
+
+    let server = handle.use_handle(|server| server.clone());
+    let started = run_with_timeout(Duration::from_millis(1000), async move {
+        let announcer = Announcer::bind(&name, address)?;
+        tokio::spawn(async move { server.run_announcer(announcer).await });
+        Ok::<_, std::io::Error>(())
+    });
+    match started {
+        None => throw_rte(&mut env, "timed out starting announcements"),
+        Some(Err(err)) => throw_rte(&mut env, format!("failed to announce the server: {err}")),
+        Some(Ok(())) => {}
+    }
 }
 
 #[jni_fn("hexacraft.rs.RustLib$GameServer")]
@@ -56,7 +271,7 @@ pub fn stop<'local>(
             async move { server.shutdown().await }
         };
         if run_with_timeout(Duration::from_millis(1000), shutdown_task).is_none() {
-            eprintln!("Server shutdown timed out. The server will now be forced to shut down.");
+            warn!("Server shutdown timed out. The server will now be forced to shut down.");
         }
     });
     handle.destroy(); // this stops the server
@@ -0,0 +1,155 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::ffi::server_socket::message_to_array;
+use crate::handle::Handle;
+use crate::{run_and_wait, run_with_timeout, throw_ie, throw_rte};
+
+use hexacraft::ZmqError;
+use hexacraft::transport::{ClientTransport, InMemoryClient, InMemoryServer, Transport};
+use jni::JNIEnv;
+use jni::objects::{AsJArrayRaw, JByteArray, JClass, JObject};
+use jni::sys::{jbyteArray, jobjectArray};
+use jni_fn::jni_fn;
+
+/// Creates a server that clients in the same process can connect to without going through the
+/// network. It is used just like a `ServerSocket`.
+#[jni_fn("hexacraft.rs.RustLib$InProcessServer")]
+pub fn create<'local>(_env: JNIEnv<'local>, _class: JClass<'local>) -> Handle<Arc<InMemoryServer>> {
+    Handle::create(Arc::new(InMemoryServer::new()))
+}
+
+/// Connects a client to the server. The returned handle is used with `InProcessClient`.
+#[jni_fn("hexacraft.rs.RustLib$InProcessServer")]
+pub fn connect<'local>(
+    env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<InMemoryServer>>,
+    client_id: JByteArray<'local>,
+) -> Handle<Arc<InMemoryClient>> {
+    let client_id = env
+        .convert_byte_array(client_id)
+        .expect("failed to convert byte array");
+
+    let client = handle.use_handle(|server| server.connect(client_id));
+    Handle::create(Arc::new(client))
+}
+
+#[jni_fn("hexacraft.rs.RustLib$InProcessServer")]
+pub fn send<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<InMemoryServer>>,
+    client_id: JByteArray<'local>,
+    data: JByteArray<'local>,
+) {
+    let client_id = env
+        .convert_byte_array(client_id)
+        .expect("failed to convert byte array");
+
+    let data = env
+        .convert_byte_array(data)
+        .expect("failed to convert byte array");
+
+    match handle.use_handle(|server| {
+        let server = server.clone();
+        run_with_timeout(Duration::from_millis(3000), async move {
+            server.send(client_id.into(), data).await
+        })
+    }) {
+        None => throw_rte(&mut env, "timed out sending data"),
+        Some(Err(err)) => throw_rte(&mut env, format!("failed to send data: {err}")),
+        _ => {}
+    };
+}
+
+/// Returns the next message as an array where the first element is the client id and the rest are
+/// the frames of the message
+#[jni_fn("hexacraft.rs.RustLib$InProcessServer")]
+pub fn receive<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<InMemoryServer>>,
+) -> jobjectArray {
+    match handle.use_handle(|server| {
+        let server = server.clone();
+        run_and_wait(async move { server.receive_message().await })
+    }) {
+        Err(ZmqError::Other("cancelled")) => {
+            throw_ie(&mut env, "cancelled");
+            *JObject::null()
+        }
+        Err(err) => {
+            throw_rte(&mut env, format!("failed to receive data: {err}"));
+            *JObject::null()
+        }
+        Ok((peer_id, frames)) => message_to_array(&mut env, peer_id.as_bytes(), &frames),
+    }
+}
+
+#[jni_fn("hexacraft.rs.RustLib$InProcessServer")]
+pub fn close<'local>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<InMemoryServer>>,
+) {
+    handle.use_handle(|server| server.cancel());
+    handle.destroy();
+}
+
+#[jni_fn("hexacraft.rs.RustLib$InProcessClient")]
+pub fn send<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<InMemoryClient>>,
+    data: JByteArray<'local>,
+) {
+    let data = env
+        .convert_byte_array(data)
+        .expect("failed to convert byte array");
+
+    match handle.use_handle(|client| {
+        let client = client.clone();
+        run_with_timeout(Duration::from_millis(3000), async move {
+            client.send(data).await
+        })
+    }) {
+        None => throw_rte(&mut env, "timed out sending data"),
+        Some(Err(err)) => throw_rte(&mut env, format!("failed to send data: {err}")),
+        _ => {}
+    };
+}
+
+#[jni_fn("hexacraft.rs.RustLib$InProcessClient")]
+pub fn tryReceive<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<InMemoryClient>>,
+) -> jbyteArray {
+    match handle.use_handle(|client| {
+        let client = client.clone();
+        run_with_timeout(Duration::from_millis(3000), async move {
+            client.try_receive().await
+        })
+    }) {
+        None => {
+            throw_rte(&mut env, "timed out receiving");
+            *JObject::null()
+        }
+        Some(None) => *JObject::null(),
+        Some(Some(data)) => env
+            .byte_array_from_slice(&data)
+            .expect("failed to create byte array")
+            .as_jarray_raw(),
+    }
+}
+
+/// Disconnects the client from the server
+#[jni_fn("hexacraft.rs.RustLib$InProcessClient")]
+pub fn close<'local>(
+    _env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    handle: Handle<Arc<InMemoryClient>>,
+) {
+    handle.destroy();
+}
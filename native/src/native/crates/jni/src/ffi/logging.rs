@@ -0,0 +1,92 @@
+use std::sync::OnceLock;
+
+use crate::throw_rte;
+
+use hexacraft::logging::{ForwardLayer, LogConfig, LogFile, LogGuard};
+use jni::objects::{JClass, JString, JValue};
+use jni::{JNIEnv, JavaVM};
+use jni_fn::jni_fn;
+use tracing::Level;
+
+/// Keeps the log file open for as long as the library is loaded
+static LOG_GUARD: OnceLock<LogGuard> = OnceLock::new();
+
+#[jni_fn("hexacraft.rs.RustLib$Logging")]
+pub fn init<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    filter: JString<'local>,
+    log_dir: JString<'local>,
+) {
+    let filter = env.get_string(&filter).expect("failed to read string");
+    let filter = filter.to_str().expect("invalid utf8").to_string();
+
+    let mut config = LogConfig::new(&filter).without_stderr();
+    if !log_dir.is_null() {
+        let log_dir = env.get_string(&log_dir).expect("failed to read string");
+        let log_dir = log_dir.to_str().expect("invalid utf8").to_string();
+        config = config.with_file(LogFile::new(log_dir, "native"));
+    }
+
+    let vm = env.get_java_vm().expect("failed to get JavaVM");
+    let layer = ForwardLayer::new(move |level, target: &str, message| {
+        log_to_jvm(&vm, level, target, message)
+    });
+    match config.init_with(Some(Box::new(layer))) {
+        Ok(guard) => {
+            let _ = LOG_GUARD.set(guard);
+        }
+        Err(err) => throw_rte(&mut env, err),
+    }
+}
+
+/// Logs using `java.util.logging`, with a logger named like the target, e.g.
+/// `hexacraft_core.server.state`
+fn log_to_jvm(vm: &JavaVM, level: Level, target: &str, message: String) {
+    // logs can come from any thread, also ones the JVM does not know about
+    let Ok(mut env) = vm.attach_current_thread_as_daemon() else {
+        return;
+    };
+    if env.exception_check().unwrap_or(true) {
+        // calling Java now would be undefined behavior, so the log is dropped
+        return;
+    }
+
+    let level = match level {
+        Level::ERROR => "SEVERE",
+        Level::WARN => "WARNING",
+        Level::INFO => "INFO",
+        Level::DEBUG => "FINE",
+        Level::TRACE => "FINER",
+    };
+    let logged = env.with_local_frame(8, |env| -> jni::errors::Result<()> {
+        let name = env.new_string(target.replace("::", "."))?;
+        let logger = env
+            .call_static_method(
+                "java/util/logging/Logger",
+                "getLogger",
+                "(Ljava/lang/String;)Ljava/util/logging/Logger;",
+                &[JValue::Object(&name)],
+            )?
+            .l()?;
+        let level = env
+            .get_static_field(
+                "java/util/logging/Level",
+                level,
+                "Ljava/util/logging/Level;",
+            )?
+            .l()?;
+        let message = env.new_string(message)?;
+        env.call_method(
+            &logger,
+            "log",
+            "(Ljava/util/logging/Level;Ljava/lang/String;)V",
+            &[JValue::Object(&level), JValue::Object(&message)],
+        )?;
+        Ok(())
+    });
+    if logged.is_err() {
+        // a broken logger should not break the code that logged
+        let _ = env.exception_clear();
+    }
+}
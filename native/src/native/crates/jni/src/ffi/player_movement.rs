@@ -0,0 +1,55 @@
+use glam::{DVec3, Vec2};
+use hexacraft::server::input::{self, AnalogInput, PlayerInput};
+use jni::JNIEnv;
+use jni::objects::{JClass, JDoubleArray, JObjectArray, JString};
+use jni::sys::jfloat;
+use jni_fn::jni_fn;
+
+use crate::throw_rte;
+
+/// Updates `rotation` (x, y and z) in place, using the same code as the server
+#[jni_fn("hexacraft.rs.RustLib$PlayerMovement")]
+pub fn updateRotation<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    rotation: JDoubleArray<'local>,
+    mouse_dx: jfloat,
+    mouse_dy: jfloat,
+    look_x: jfloat,
+    look_y: jfloat,
+    pressed_keys: JObjectArray<'local>,
+) {
+    let mut r = [0.0; 3];
+    env.get_double_array_region(&rotation, 0, &mut r)
+        .expect("failed to read rotation");
+
+    let key_count = env
+        .get_array_length(&pressed_keys)
+        .expect("failed to read array length");
+    let keys = (0..key_count)
+        .map(|i| {
+            let key: JString = env
+                .get_object_array_element(&pressed_keys, i)
+                .expect("failed to read array element")
+                .into();
+            let key = env.get_string(&key).expect("failed to read string");
+            key.to_str().expect("invalid utf8").to_string()
+        })
+        .collect::<Vec<_>>();
+    let keys = match PlayerInput::from_key_names(keys.iter().map(|k| k.as_str())) {
+        Ok(keys) => keys,
+        Err(err) => {
+            throw_rte(&mut env, err);
+            return;
+        }
+    };
+    let analog = AnalogInput {
+        look: Vec2::new(look_x, look_y),
+    };
+
+    let mut r = DVec3::from_array(r);
+    input::update_rotation(&mut r, Vec2::new(mouse_dx, mouse_dy), keys, analog);
+
+    env.set_double_array_region(&rotation, 0, &r.to_array())
+        .expect("failed to write rotation");
+}
@@ -6,8 +6,8 @@ use crate::{run_and_wait, run_with_timeout, throw_ie, throw_rte};
 
 use hexacraft::ZmqError;
 use jni::JNIEnv;
-use jni::objects::{AsJArrayRaw, JByteArray, JClass, JObject};
-use jni::sys::{jbyteArray, jint};
+use jni::objects::{JByteArray, JClass, JObject, JString};
+use jni::sys::{jint, jobjectArray};
 use jni_fn::jni_fn;
 
 #[jni_fn("hexacraft.rs.RustLib$ServerSocket")]
@@ -18,25 +18,34 @@ pub fn create<'local>(
     Handle::create(Arc::new(hexacraft::zmq::ServerSocket::new()))
 }
 
+/// Binds the socket to an endpoint like `tcp://0.0.0.0:1234` and returns the bound port, which is
+/// useful when binding to port 0. For endpoints without a port (like `ipc://`) -1 is returned.
 #[jni_fn("hexacraft.rs.RustLib$ServerSocket")]
 pub fn bind<'local>(
     mut env: JNIEnv<'local>,
     _class: JClass<'local>,
     handle: Handle<Arc<hexacraft::zmq::ServerSocket>>,
-    port: jint,
-) {
-    assert!((0..1 << 16).contains(&port), "port is out of range");
+    endpoint: JString<'local>,
+) -> jint {
+    let endpoint = env.get_string(&endpoint).expect("failed to read string");
+    let endpoint = endpoint.to_str().expect("invalid utf8").to_string();
 
     match handle.use_handle(|socket| {
         let socket = socket.clone();
         run_with_timeout(Duration::from_millis(3000), async move {
-            socket.bind(port as u16).await
+            socket.bind(&endpoint).await
         })
     }) {
-        None => throw_rte(&mut env, "timed out connecting to server"),
-        Some(Err(err)) => throw_rte(&mut env, format!("failed to connect to server: {err}")),
-        _ => {}
-    };
+        None => {
+            throw_rte(&mut env, "timed out binding server socket");
+            -1
+        }
+        Some(Err(err)) => {
+            throw_rte(&mut env, format!("failed to bind server socket: {err}"));
+            -1
+        }
+        Some(Ok(endpoint)) => hexacraft::zmq::endpoint_port(&endpoint).map_or(-1, jint::from),
+    }
 }
 
 #[jni_fn("hexacraft.rs.RustLib$ServerSocket")]
@@ -58,7 +67,7 @@ pub fn send<'local>(
     match handle.use_handle(|socket| {
         let socket = socket.clone();
         run_with_timeout(Duration::from_millis(3000), async move {
-            socket.send(client_id, data).await
+            socket.send(client_id.into(), data).await
         })
     }) {
         None => {
@@ -74,15 +83,17 @@ pub fn send<'local>(
     };
 }
 
+/// Returns the next message as an array where the first element is the client id and the rest are
+/// the frames of the message
 #[jni_fn("hexacraft.rs.RustLib$ServerSocket")]
 pub fn receive<'local>(
     mut env: JNIEnv<'local>,
     _class: JClass<'local>,
     handle: Handle<Arc<hexacraft::zmq::ServerSocket>>,
-) -> jbyteArray {
+) -> jobjectArray {
     match handle.use_handle(|socket| {
         let socket = socket.clone();
-        run_and_wait(async move { socket.receive().await })
+        run_and_wait(async move { socket.receive_message().await })
     }) {
         Err(ZmqError::Other("cancelled")) => {
             throw_ie(&mut env, "cancelled");
@@ -92,11 +103,30 @@ pub fn receive<'local>(
             throw_rte(&mut env, format!("failed to receive data: {err}"));
             *JObject::null()
         }
-        Ok(data) => env
-            .byte_array_from_slice(&data)
-            .expect("failed to create byte array")
-            .as_jarray_raw(),
+        Ok((peer_id, frames)) => message_to_array(&mut env, peer_id.as_bytes(), &frames),
+    }
+}
+
+/// Puts the client id and the frames of a message in an array, which is how `receive` returns
+/// messages
+pub(crate) fn message_to_array(
+    env: &mut JNIEnv,
+    peer_id: &[u8],
+    frames: &[impl AsRef<[u8]>],
+) -> jobjectArray {
+    let arr = env
+        .new_object_array(frames.len() as i32 + 1, "[B", JObject::null())
+        .expect("failed to create array");
+
+    let parts = std::iter::once(peer_id).chain(frames.iter().map(|f| f.as_ref()));
+    for (i, part) in parts.enumerate() {
+        let bytes = env
+            .byte_array_from_slice(part)
+            .expect("failed to create byte array");
+        env.set_object_array_element(&arr, i as i32, bytes)
+            .expect("failed to write to array");
     }
+    arr.into_raw()
 }
 
 #[jni_fn("hexacraft.rs.RustLib$ServerSocket")]
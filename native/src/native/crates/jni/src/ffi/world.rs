@@ -0,0 +1,45 @@
+use std::path::Path;
+
+use crate::throw_rte;
+
+use hexacraft::server::nbt;
+use hexacraft::server::world_config::{WorldConfig, open_world};
+use jni::JNIEnv;
+use jni::objects::{AsJArrayRaw, JByteArray, JClass, JObject, JString};
+use jni::sys::{jboolean, jbyteArray};
+use jni_fn::jni_fn;
+
+/// Opens or creates a world, see `RustLib.World.open`
+#[jni_fn("hexacraft.rs.RustLib$World")]
+pub fn open<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    path: JString<'local>,
+    settings: JByteArray<'local>,
+    change_existing: jboolean,
+) -> jbyteArray {
+    let path = env.get_string(&path).expect("failed to read string");
+    let path = path.to_str().expect("invalid utf8").to_string();
+
+    let config = if settings.is_null() {
+        Ok(WorldConfig::default())
+    } else {
+        let settings = env
+            .convert_byte_array(&settings)
+            .expect("failed to read byte array");
+        nbt::Tag::from_binary(&settings).and_then(|(_, tag)| WorldConfig::from_nbt(&tag))
+    };
+    let world =
+        config.and_then(|config| open_world(Path::new(&path), &config, change_existing == 1));
+
+    match world {
+        Ok(world) => env
+            .byte_array_from_slice(&world.to_nbt().to_binary())
+            .expect("failed to create byte array")
+            .as_jarray_raw(),
+        Err(err) => {
+            throw_rte(&mut env, format!("failed to open world: {err}"));
+            *JObject::null()
+        }
+    }
+}
@@ -30,6 +30,14 @@ impl<S: 'static> Handle<S> {
         }
     }
 
+    /// A handle that does not refer to anything, to be returned together with an exception
+    pub fn null() -> Self {
+        Self {
+            raw: 0,
+            _phantom: PhantomData {},
+        }
+    }
+
     pub unsafe fn wrap(raw: i64) -> Self {
         if DEBUG {
             println!("Wrapped handle: {raw}");
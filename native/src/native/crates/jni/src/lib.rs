@@ -7,10 +7,15 @@ use jni::{
 
 mod ffi {
     mod client_socket;
+    mod discovery;
     mod game_server;
+    mod in_process;
+    mod logging;
     mod noise;
+    mod player_movement;
     mod server_socket;
     mod vorbis;
+    mod world;
 }
 mod handle;
 mod util;
@@ -0,0 +1,97 @@
+use hexacraft::server::nbt::Tag;
+
+use crate::{
+    path::{self, Segment},
+    snbt,
+};
+
+/// Lists the differences between two tags, one line per changed, added or removed value
+pub fn diff(a: &Tag, b: &Tag) -> Vec<String> {
+    let mut lines = Vec::new();
+    diff_at(&mut Vec::new(), a, b, &mut lines);
+    lines
+}
+
+fn diff_at(at: &mut Vec<Segment>, a: &Tag, b: &Tag, lines: &mut Vec<String>) {
+    if a == b {
+        return;
+    }
+
+    match (a, b) {
+        (Tag::Map(a_items), Tag::Map(b_items)) => {
+            for (name, a_item) in a_items {
+                at.push(Segment::Key(name.clone()));
+                match b_items.iter().find(|(n, _)| n == name) {
+                    Some((_, b_item)) => diff_at(at, a_item, b_item, lines),
+                    None => lines.push(line('-', at, a_item)),
+                }
+                at.pop();
+            }
+            for (name, b_item) in b_items {
+                if !a_items.iter().any(|(n, _)| n == name) {
+                    at.push(Segment::Key(name.clone()));
+                    lines.push(line('+', at, b_item));
+                    at.pop();
+                }
+            }
+        }
+        (Tag::List(a_items), Tag::List(b_items)) => {
+            for idx in 0..a_items.len().max(b_items.len()) {
+                at.push(Segment::Index(idx));
+                match (a_items.get(idx), b_items.get(idx)) {
+                    (Some(a_item), Some(b_item)) => diff_at(at, a_item, b_item, lines),
+                    (Some(a_item), None) => lines.push(line('-', at, a_item)),
+                    (None, Some(b_item)) => lines.push(line('+', at, b_item)),
+                    (None, None) => unreachable!(),
+                }
+                at.pop();
+            }
+        }
+        _ => lines.push(format!(
+            "~ {}: {} -> {}",
+            path::format(at),
+            snbt::format(a, false),
+            snbt::format(b, false)
+        )),
+    }
+}
+
+fn line(kind: char, at: &[Segment], tag: &Tag) -> String {
+    format!("{kind} {}: {}", path::format(at), snbt::format(tag, false))
+}
+
+#[cfg(test)]
+mod tests {
+    use hexacraft::server::nbt::{MapTag, Tag};
+
+    use super::diff;
+
+    #[test]
+    fn reports_changes_by_path() {
+        let a = MapTag::new()
+            .set("name", Tag::String("a".to_string()))
+            .set("removed", Tag::Byte(1))
+            .set("list", Tag::List(vec![Tag::Int(1), Tag::Int(2)]))
+            .build();
+        let b = MapTag::new()
+            .set("name", Tag::String("b".to_string()))
+            .set(
+                "list",
+                Tag::List(vec![Tag::Int(1), Tag::Int(3), Tag::Int(4)]),
+            )
+            .set("added", Tag::Long(5))
+            .build();
+
+        assert_eq!(
+            diff(&a, &b),
+            vec![
+                "~ name: \"a\" -> \"b\"",
+                "- removed: 1b",
+                "~ list[1]: 2 -> 3",
+                "+ list[2]: 4",
+                "+ added: 5L",
+            ]
+        );
+        assert!(diff(&a, &a).is_empty());
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{Compression as GzLevel, read::GzDecoder, write::GzEncoder};
+use hexacraft::server::nbt::Tag;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+}
+
+impl Compression {
+    pub fn toggled(self) -> Self {
+        match self {
+            Compression::None => Compression::Gzip,
+            Compression::Gzip => Compression::None,
+        }
+    }
+}
+
+pub struct NbtFile {
+    pub compression: Compression,
+    pub name: String,
+    pub tag: Tag,
+}
+
+/// A world directory refers to its `world.dat` file, anything else is used as is.
+pub fn resolve(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        path.join("world.dat")
+    } else {
+        path.to_path_buf()
+    }
+}
+
+pub fn read(path: &Path) -> Result<NbtFile, String> {
+    let path = resolve(path);
+    let bytes =
+        fs::read(&path).map_err(|err| format!("could not read {}: {err}", path.display()))?;
+
+    let (compression, data) = if bytes.starts_with(&GZIP_MAGIC) {
+        let mut data = Vec::new();
+        GzDecoder::new(bytes.as_slice())
+            .read_to_end(&mut data)
+            .map_err(|err| format!("could not decompress {}: {err}", path.display()))?;
+        (Compression::Gzip, data)
+    } else {
+        (Compression::None, bytes)
+    };
+
+    let (name, tag) = Tag::from_binary(&data)
+        .map_err(|err| format!("invalid nbt in {}: {err}", path.display()))?;
+
+    Ok(NbtFile {
+        compression,
+        name,
+        tag,
+    })
+}
+
+pub fn write(path: &Path, file: &NbtFile) -> Result<(), String> {
+    let path = resolve(path);
+    let data = file.tag.to_named_binary(&file.name);
+
+    let bytes = match file.compression {
+        Compression::None => data,
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), GzLevel::default());
+            encoder
+                .write_all(&data)
+                .and_then(|_| encoder.finish())
+                .map_err(|err| format!("could not compress {}: {err}", path.display()))?
+        }
+    };
+
+    fs::write(&path, bytes).map_err(|err| format!("could not write {}: {err}", path.display()))
+}
@@ -0,0 +1,103 @@
+use std::fmt::Write;
+
+use hexacraft::server::nbt::Tag;
+
+/// Formats the tag as JSON. Type information (like the size of integers) is lost.
+pub fn format(tag: &Tag) -> String {
+    let mut out = String::new();
+    write_tag(&mut out, tag, 0);
+    out
+}
+
+fn write_tag(out: &mut String, tag: &Tag, indent: usize) {
+    match tag {
+        Tag::End => out.push_str("null"),
+        Tag::Byte(v) => write!(out, "{v}").unwrap(),
+        Tag::Short(v) => write!(out, "{v}").unwrap(),
+        Tag::Int(v) => write!(out, "{v}").unwrap(),
+        Tag::Long(v) => write!(out, "{v}").unwrap(),
+        Tag::Float(v) => write_float(out, *v as f64),
+        Tag::Double(v) => write_float(out, *v),
+        Tag::String(v) => write_string(out, v),
+        Tag::ByteArray(vs) => write_numbers(out, vs.iter()),
+        Tag::ShortArray(vs) => write_numbers(out, vs.iter()),
+        Tag::IntArray(vs) => write_numbers(out, vs.iter()),
+        Tag::LongArray(vs) => write_numbers(out, vs.iter()),
+        Tag::List(vs) => {
+            if vs.is_empty() {
+                out.push_str("[]");
+                return;
+            }
+            out.push('[');
+            for (i, v) in vs.iter().enumerate() {
+                if i != 0 {
+                    out.push(',');
+                }
+                new_line(out, indent + 1);
+                write_tag(out, v, indent + 1);
+            }
+            new_line(out, indent);
+            out.push(']');
+        }
+        Tag::Map(vs) => {
+            if vs.is_empty() {
+                out.push_str("{}");
+                return;
+            }
+            out.push('{');
+            for (i, (name, v)) in vs.iter().enumerate() {
+                if i != 0 {
+                    out.push(',');
+                }
+                new_line(out, indent + 1);
+                write_string(out, name);
+                out.push_str(": ");
+                write_tag(out, v, indent + 1);
+            }
+            new_line(out, indent);
+            out.push('}');
+        }
+    }
+}
+
+fn new_line(out: &mut String, indent: usize) {
+    out.push('\n');
+    for _ in 0..indent {
+        out.push_str("  ");
+    }
+}
+
+fn write_float(out: &mut String, v: f64) {
+    if v.is_finite() {
+        write!(out, "{v:?}").unwrap();
+    } else {
+        out.push_str("null");
+    }
+}
+
+fn write_numbers<T: std::fmt::Display>(out: &mut String, vs: impl Iterator<Item = T>) {
+    out.push('[');
+    for (i, v) in vs.enumerate() {
+        if i != 0 {
+            out.push_str(", ");
+        }
+        write!(out, "{v}").unwrap();
+    }
+    out.push(']');
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
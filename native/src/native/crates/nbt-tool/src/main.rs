@@ -0,0 +1,127 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::file::{Compression, NbtFile};
+
+mod diff;
+mod file;
+mod json;
+mod path;
+mod snbt;
+
+/// Inspects and edits NBT files, like the ones in Hexacraft world folders.
+///
+/// Wherever a file is expected a world folder can be given instead, in which case its world.dat
+/// file is used.
+#[derive(Parser)]
+#[command(name = "nbt-tool")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Prints the content of a file
+    Dump {
+        file: PathBuf,
+        #[arg(long, value_enum, default_value_t = Format::Snbt)]
+        format: Format,
+    },
+    /// Prints the value at a path, like `general.name` or `slots[0].id`
+    Get {
+        file: PathBuf,
+        path: String,
+        #[arg(long, value_enum, default_value_t = Format::Snbt)]
+        format: Format,
+    },
+    /// Replaces the value at a path with a value written in SNBT, like `8b` or `"name"`
+    Set {
+        file: PathBuf,
+        path: String,
+        value: String,
+    },
+    /// Lists the differences between two files. Exits with 1 if there were differences.
+    Diff { a: PathBuf, b: PathBuf },
+    /// Writes a file with a different compression
+    Convert {
+        input: PathBuf,
+        output: PathBuf,
+        /// Defaults to the opposite of the input compression
+        #[arg(long, value_enum)]
+        compression: Option<CompressionArg>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Snbt,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CompressionArg {
+    None,
+    Gzip,
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse().command) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run(command: Command) -> Result<ExitCode, String> {
+    match command {
+        Command::Dump { file, format } => {
+            let file = file::read(&file)?;
+            println!("{}", format_tag(&file.tag, format));
+        }
+        Command::Get { file, path, format } => {
+            let file = file::read(&file)?;
+            let tag = path::get(&file.tag, &path::parse(&path)?)?;
+            println!("{}", format_tag(&tag, format));
+        }
+        Command::Set { file, path, value } => {
+            let mut nbt = file::read(&file)?;
+            let value = snbt::parse(&value).map_err(|err| format!("invalid value: {err}"))?;
+            path::set(&mut nbt.tag, &path::parse(&path)?, value)?;
+            file::write(&file, &nbt)?;
+        }
+        Command::Diff { a, b } => {
+            let lines = diff::diff(&file::read(&a)?.tag, &file::read(&b)?.tag);
+            for line in &lines {
+                println!("{line}");
+            }
+            if !lines.is_empty() {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Command::Convert {
+            input,
+            output,
+            compression,
+        } => {
+            let nbt = file::read(&input)?;
+            let compression = match compression {
+                Some(CompressionArg::None) => Compression::None,
+                Some(CompressionArg::Gzip) => Compression::Gzip,
+                None => nbt.compression.toggled(),
+            };
+            file::write(&output, &NbtFile { compression, ..nbt })?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn format_tag(tag: &hexacraft::server::nbt::Tag, format: Format) -> String {
+    match format {
+        Format::Snbt => snbt::format(tag, true),
+        Format::Json => json::format(tag),
+    }
+}
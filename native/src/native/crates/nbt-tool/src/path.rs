@@ -0,0 +1,289 @@
+//! Paths into a tag, like `general.name` or `players[0].position.x`.
+//!
+//! Keys containing special characters can be quoted: `"with.dot".value`.
+
+use std::{iter::Peekable, str::Chars};
+
+use hexacraft::server::nbt::Tag;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+pub fn parse(path: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut chars = path.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '[' => {
+                chars.next();
+                let mut index = String::new();
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some(c) => index.push(c),
+                        None => return Err(format!("unterminated index in path '{path}'")),
+                    }
+                }
+                let index = index
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("invalid index '{index}' in path '{path}'"))?;
+                segments.push(Segment::Index(index));
+            }
+            '.' if !segments.is_empty() => {
+                chars.next();
+                segments.push(Segment::Key(parse_key(&mut chars, path)?));
+            }
+            _ if segments.is_empty() => {
+                segments.push(Segment::Key(parse_key(&mut chars, path)?));
+            }
+            c => return Err(format!("unexpected '{c}' in path '{path}'")),
+        }
+    }
+
+    Ok(segments)
+}
+
+fn parse_key(chars: &mut Peekable<Chars>, path: &str) -> Result<String, String> {
+    let mut key = String::new();
+    if chars.peek() == Some(&'"') {
+        chars.next();
+        loop {
+            match chars.next() {
+                Some('"') => break,
+                Some(c) => key.push(c),
+                None => return Err(format!("unterminated quote in path '{path}'")),
+            }
+        }
+    } else {
+        while let Some(&c) = chars.peek() {
+            if c == '.' || c == '[' {
+                break;
+            }
+            key.push(c);
+            chars.next();
+        }
+        if key.is_empty() {
+            return Err(format!("empty key in path '{path}'"));
+        }
+    }
+    Ok(key)
+}
+
+pub fn format(path: &[Segment]) -> String {
+    let mut res = String::new();
+    for segment in path {
+        match segment {
+            Segment::Key(key) => {
+                if !res.is_empty() {
+                    res.push('.');
+                }
+                if key.is_empty() || key.contains(['.', '[', ']', '"']) {
+                    res.push('"');
+                    res.push_str(key);
+                    res.push('"');
+                } else {
+                    res.push_str(key);
+                }
+            }
+            Segment::Index(idx) => res.push_str(&format!("[{idx}]")),
+        }
+    }
+    res
+}
+
+pub fn get(tag: &Tag, path: &[Segment]) -> Result<Tag, String> {
+    let Some((segment, rest)) = path.split_first() else {
+        return Ok(tag.clone());
+    };
+
+    match (tag, segment) {
+        (Tag::Map(items), Segment::Key(key)) => {
+            let (_, child) = items
+                .iter()
+                .find(|(name, _)| name == key)
+                .ok_or_else(|| format!("no field named '{key}'"))?;
+            get(child, rest)
+        }
+        (Tag::List(items), Segment::Index(idx)) => {
+            let child = items
+                .get(*idx)
+                .ok_or_else(|| out_of_bounds(*idx, items.len()))?;
+            get(child, rest)
+        }
+        (array, Segment::Index(idx)) => {
+            let item = array_item(array, *idx)?;
+            if rest.is_empty() {
+                Ok(item)
+            } else {
+                Err("array items have no fields".to_string())
+            }
+        }
+        (_, Segment::Key(key)) => Err(format!("cannot look up '{key}' in a non-map tag")),
+    }
+}
+
+/// Replaces the tag at `path` with `value`. Missing map fields at the end of the path are added.
+pub fn set(tag: &mut Tag, path: &[Segment], value: Tag) -> Result<(), String> {
+    let Some((segment, rest)) = path.split_first() else {
+        *tag = value;
+        return Ok(());
+    };
+
+    match (tag, segment) {
+        (Tag::Map(items), Segment::Key(key)) => {
+            match items.iter_mut().find(|(name, _)| name == key) {
+                Some((_, child)) => set(child, rest, value),
+                None if rest.is_empty() => {
+                    items.push((key.clone(), value));
+                    Ok(())
+                }
+                None => Err(format!("no field named '{key}'")),
+            }
+        }
+        (Tag::List(items), Segment::Index(idx)) => {
+            let len = items.len();
+            if rest.is_empty()
+                && let Some(first) = items.first()
+                && std::mem::discriminant(first) != std::mem::discriminant(&value)
+            {
+                return Err("all items in a list must have the same type".to_string());
+            }
+            let child = items
+                .get_mut(*idx)
+                .ok_or_else(|| out_of_bounds(*idx, len))?;
+            set(child, rest, value)
+        }
+        (array, Segment::Index(idx)) => {
+            if !rest.is_empty() {
+                return Err("array items have no fields".to_string());
+            }
+            set_array_item(array, *idx, value)
+        }
+        (_, Segment::Key(key)) => Err(format!("cannot look up '{key}' in a non-map tag")),
+    }
+}
+
+fn out_of_bounds(idx: usize, len: usize) -> String {
+    format!("index {idx} is out of bounds (length is {len})")
+}
+
+fn array_item(array: &Tag, idx: usize) -> Result<Tag, String> {
+    let item = match array {
+        Tag::ByteArray(vs) => vs.get(idx).map(|&v| Tag::Byte(v)),
+        Tag::ShortArray(vs) => vs.get(idx).map(|&v| Tag::Short(v)),
+        Tag::IntArray(vs) => vs.get(idx).map(|&v| Tag::Int(v)),
+        Tag::LongArray(vs) => vs.get(idx).map(|&v| Tag::Long(v)),
+        _ => return Err("only lists and arrays can be indexed".to_string()),
+    };
+    item.ok_or_else(|| out_of_bounds(idx, array_len(array)))
+}
+
+fn set_array_item(array: &mut Tag, idx: usize, value: Tag) -> Result<(), String> {
+    let len = array_len(array);
+    let wrong_type = || "wrong type for array item".to_string();
+    match array {
+        Tag::ByteArray(vs) => {
+            let Tag::Byte(v) = value else {
+                return Err(wrong_type());
+            };
+            *vs.get_mut(idx).ok_or_else(|| out_of_bounds(idx, len))? = v;
+        }
+        Tag::ShortArray(vs) => {
+            let Tag::Short(v) = value else {
+                return Err(wrong_type());
+            };
+            *vs.get_mut(idx).ok_or_else(|| out_of_bounds(idx, len))? = v;
+        }
+        Tag::IntArray(vs) => {
+            let Tag::Int(v) = value else {
+                return Err(wrong_type());
+            };
+            *vs.get_mut(idx).ok_or_else(|| out_of_bounds(idx, len))? = v;
+        }
+        Tag::LongArray(vs) => {
+            let Tag::Long(v) = value else {
+                return Err(wrong_type());
+            };
+            *vs.get_mut(idx).ok_or_else(|| out_of_bounds(idx, len))? = v;
+        }
+        _ => return Err("only lists and arrays can be indexed".to_string()),
+    }
+    Ok(())
+}
+
+fn array_len(array: &Tag) -> usize {
+    match array {
+        Tag::ByteArray(vs) => vs.len(),
+        Tag::ShortArray(vs) => vs.len(),
+        Tag::IntArray(vs) => vs.len(),
+        Tag::LongArray(vs) => vs.len(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hexacraft::server::nbt::{MapTag, Tag};
+
+    use super::{Segment, get, parse, set};
+
+    #[test]
+    fn parse_path() {
+        assert_eq!(
+            parse("a.b[2].\"c.d\"").unwrap(),
+            vec![
+                Segment::Key("a".to_string()),
+                Segment::Key("b".to_string()),
+                Segment::Index(2),
+                Segment::Key("c.d".to_string()),
+            ]
+        );
+        assert_eq!(parse("").unwrap(), vec![]);
+        assert!(parse("a..b").is_err());
+        assert!(parse("a[x]").is_err());
+    }
+
+    #[test]
+    fn get_and_set() {
+        let mut tag = MapTag::new()
+            .set(
+                "general",
+                MapTag::new()
+                    .set("name", Tag::String("old".to_string()))
+                    .build(),
+            )
+            .set("heights", Tag::IntArray(vec![1, 2, 3]))
+            .build();
+
+        set(
+            &mut tag,
+            &parse("general.name").unwrap(),
+            Tag::String("new".to_string()),
+        )
+        .unwrap();
+        set(&mut tag, &parse("general.size").unwrap(), Tag::Byte(8)).unwrap();
+        set(&mut tag, &parse("heights[1]").unwrap(), Tag::Int(5)).unwrap();
+
+        assert_eq!(
+            get(&tag, &parse("general.name").unwrap()).unwrap(),
+            Tag::String("new".to_string())
+        );
+        assert_eq!(
+            get(&tag, &parse("general.size").unwrap()).unwrap(),
+            Tag::Byte(8)
+        );
+        assert_eq!(
+            get(&tag, &parse("heights[1]").unwrap()).unwrap(),
+            Tag::Int(5)
+        );
+
+        assert!(set(&mut tag, &parse("heights[1]").unwrap(), Tag::Long(5)).is_err());
+        assert!(set(&mut tag, &parse("heights[3]").unwrap(), Tag::Int(5)).is_err());
+        assert!(get(&tag, &parse("missing.field").unwrap()).is_err());
+    }
+}
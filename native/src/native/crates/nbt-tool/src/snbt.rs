@@ -0,0 +1,427 @@
+//! Stringified NBT, roughly following the syntax used by Minecraft.
+//!
+//! Numbers carry a suffix for their type (`1b`, `2s`, `3`, `4L`, `5.0f`, `6.0d`), arrays are
+//! written as `[B; ..]`, `[I; ..]`, `[L; ..]` and (non-standard) `[S; ..]` for short arrays.
+
+use std::fmt::Write;
+
+use hexacraft::server::nbt::Tag;
+
+pub fn format(tag: &Tag, pretty: bool) -> String {
+    let mut out = String::new();
+    write_tag(&mut out, tag, pretty.then_some(0));
+    out
+}
+
+fn write_tag(out: &mut String, tag: &Tag, indent: Option<usize>) {
+    match tag {
+        Tag::End => out.push_str("END"),
+        Tag::Byte(v) => write!(out, "{v}b").unwrap(),
+        Tag::Short(v) => write!(out, "{v}s").unwrap(),
+        Tag::Int(v) => write!(out, "{v}").unwrap(),
+        Tag::Long(v) => write!(out, "{v}L").unwrap(),
+        Tag::Float(v) => write!(out, "{v:?}f").unwrap(),
+        Tag::Double(v) => write!(out, "{v:?}d").unwrap(),
+        Tag::ByteArray(vs) => write_array(out, 'B', vs.iter().map(|v| format!("{v}b"))),
+        Tag::String(v) => write_string(out, v),
+        Tag::List(vs) => {
+            let nested = vs.iter().any(|v| matches!(v, Tag::List(_) | Tag::Map(_)));
+            let inner = if nested { indent.map(|i| i + 1) } else { None };
+
+            out.push('[');
+            for (i, v) in vs.iter().enumerate() {
+                if i != 0 {
+                    out.push(',');
+                    if inner.is_none() {
+                        out.push(' ');
+                    }
+                }
+                new_line(out, inner);
+                write_tag(out, v, inner);
+            }
+            if !vs.is_empty() {
+                new_line(out, indent.filter(|_| nested));
+            }
+            out.push(']');
+        }
+        Tag::Map(vs) => {
+            let inner = indent.map(|i| i + 1);
+
+            out.push('{');
+            for (i, (name, v)) in vs.iter().enumerate() {
+                if i != 0 {
+                    out.push(',');
+                    if inner.is_none() {
+                        out.push(' ');
+                    }
+                }
+                new_line(out, inner);
+                write_key(out, name);
+                out.push_str(": ");
+                write_tag(out, v, inner);
+            }
+            if !vs.is_empty() {
+                new_line(out, indent);
+            }
+            out.push('}');
+        }
+        Tag::IntArray(vs) => write_array(out, 'I', vs.iter().map(|v| v.to_string())),
+        Tag::LongArray(vs) => write_array(out, 'L', vs.iter().map(|v| format!("{v}L"))),
+        Tag::ShortArray(vs) => write_array(out, 'S', vs.iter().map(|v| format!("{v}s"))),
+    }
+}
+
+fn new_line(out: &mut String, indent: Option<usize>) {
+    if let Some(indent) = indent {
+        out.push('\n');
+        for _ in 0..indent {
+            out.push_str("  ");
+        }
+    }
+}
+
+fn write_array(out: &mut String, kind: char, items: impl Iterator<Item = String>) {
+    write!(out, "[{kind};").unwrap();
+    for (i, item) in items.enumerate() {
+        out.push_str(if i == 0 { " " } else { ", " });
+        out.push_str(&item);
+    }
+    out.push(']');
+}
+
+fn write_key(out: &mut String, key: &str) {
+    if !key.is_empty() && key.chars().all(is_unquoted_char) {
+        out.push_str(key);
+    } else {
+        write_string(out, key);
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn is_unquoted_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+' | '.')
+}
+
+pub fn parse(s: &str) -> Result<Tag, String> {
+    let mut parser = Parser { s, pos: 0 };
+    let tag = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.pos != s.len() {
+        return Err(parser.error("unexpected trailing characters"));
+    }
+    Ok(tag)
+}
+
+struct Parser<'s> {
+    s: &'s str,
+    pos: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn error(&self, message: &str) -> String {
+        format!("{message} at position {}", self.pos)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.s[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("expected '{expected}'")));
+        }
+        self.pos += expected.len_utf8();
+        Ok(())
+    }
+
+    /// Consumes `c` if it is the next non-whitespace character
+    fn accept(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Tag, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.parse_map(),
+            Some('[') => self.parse_list_or_array(),
+            Some('"' | '\'') => Ok(Tag::String(self.parse_quoted()?)),
+            Some(_) => Ok(parse_primitive(self.parse_unquoted()?)),
+            None => Err(self.error("expected a value")),
+        }
+    }
+
+    fn parse_map(&mut self) -> Result<Tag, String> {
+        self.expect('{')?;
+        let mut items = Vec::new();
+        if self.accept('}') {
+            return Ok(Tag::Map(items));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = match self.peek() {
+                Some('"' | '\'') => self.parse_quoted()?,
+                _ => self.parse_unquoted()?.to_string(),
+            };
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            if items.iter().any(|(name, _)| *name == key) {
+                return Err(self.error(&format!("duplicate key '{key}'")));
+            }
+            items.push((key, value));
+
+            if !self.accept(',') {
+                self.expect('}')?;
+                return Ok(Tag::Map(items));
+            }
+        }
+    }
+
+    fn parse_list_or_array(&mut self) -> Result<Tag, String> {
+        self.expect('[')?;
+
+        let rest = &self.s[self.pos..];
+        let mut chars = rest.chars();
+        if let (Some(kind @ ('B' | 'I' | 'L' | 'S')), Some(';')) = (chars.next(), chars.next()) {
+            self.pos += 2;
+            return self.parse_array(kind);
+        }
+
+        let mut items: Vec<Tag> = Vec::new();
+        if self.accept(']') {
+            return Ok(Tag::List(items));
+        }
+        loop {
+            let item = self.parse_value()?;
+            if let Some(first) = items.first()
+                && std::mem::discriminant(first) != std::mem::discriminant(&item)
+            {
+                return Err(self.error("all items in a list must have the same type"));
+            }
+            items.push(item);
+
+            if !self.accept(',') {
+                self.expect(']')?;
+                return Ok(Tag::List(items));
+            }
+        }
+    }
+
+    fn parse_array(&mut self, kind: char) -> Result<Tag, String> {
+        let mut items = Vec::new();
+        if !self.accept(']') {
+            loop {
+                let item = self.parse_value()?;
+                let v = match item {
+                    Tag::Byte(v) => v as i64,
+                    Tag::Short(v) => v as i64,
+                    Tag::Int(v) => v as i64,
+                    Tag::Long(v) => v,
+                    _ => return Err(self.error("arrays can only contain integers")),
+                };
+                items.push(v);
+
+                if !self.accept(',') {
+                    self.expect(']')?;
+                    break;
+                }
+            }
+        }
+
+        let out_of_range = |_| self.error("array item is out of range");
+        Ok(match kind {
+            'B' => Tag::ByteArray(
+                items
+                    .into_iter()
+                    .map(i8::try_from)
+                    .collect::<Result<_, _>>()
+                    .map_err(out_of_range)?,
+            ),
+            'S' => Tag::ShortArray(
+                items
+                    .into_iter()
+                    .map(i16::try_from)
+                    .collect::<Result<_, _>>()
+                    .map_err(out_of_range)?,
+            ),
+            'I' => Tag::IntArray(
+                items
+                    .into_iter()
+                    .map(i32::try_from)
+                    .collect::<Result<_, _>>()
+                    .map_err(out_of_range)?,
+            ),
+            _ => Tag::LongArray(items),
+        })
+    }
+
+    fn parse_quoted(&mut self) -> Result<String, String> {
+        let quote = self.peek().ok_or_else(|| self.error("expected a string"))?;
+        self.pos += 1;
+
+        let mut res = String::new();
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += c.len_utf8();
+            match c {
+                '\\' => {
+                    let escaped = self
+                        .peek()
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += escaped.len_utf8();
+                    res.push(match escaped {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        c => c,
+                    });
+                }
+                c if c == quote => return Ok(res),
+                c => res.push(c),
+            }
+        }
+    }
+
+    fn parse_unquoted(&mut self) -> Result<&'s str, String> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !is_unquoted_char(c) {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+        if start == self.pos {
+            return Err(self.error("unexpected character"));
+        }
+        Ok(&self.s[start..self.pos])
+    }
+}
+
+/// Unquoted tokens that are not numbers are strings
+fn parse_primitive(token: &str) -> Tag {
+    match token {
+        "true" => return Tag::Byte(1),
+        "false" => return Tag::Byte(0),
+        _ => {}
+    }
+
+    let (number, suffix) = token.split_at(token.len() - 1);
+    let suffixed = match suffix {
+        "b" | "B" => number.parse().ok().map(Tag::Byte),
+        "s" | "S" => number.parse().ok().map(Tag::Short),
+        "l" | "L" => number.parse().ok().map(Tag::Long),
+        "f" | "F" => number.parse().ok().map(Tag::Float),
+        "d" | "D" => number.parse().ok().map(Tag::Double),
+        _ => None,
+    };
+    if let Some(tag) = suffixed {
+        return tag;
+    }
+
+    if let Ok(v) = token.parse() {
+        return Tag::Int(v);
+    }
+
+    let looks_numeric = token.starts_with(|c: char| c.is_ascii_digit() || "+-.".contains(c));
+    if looks_numeric && let Ok(v) = token.parse() {
+        return Tag::Double(v);
+    }
+
+    Tag::String(token.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use hexacraft::server::nbt::{MapTag, Tag};
+
+    use super::{format, parse};
+
+    fn example() -> Tag {
+        MapTag::new()
+            .set("version", Tag::Short(2))
+            .set(
+                "general",
+                MapTag::new()
+                    .set("name", Tag::String("My \"world\"".to_string()))
+                    .set("worldSize", Tag::Byte(8))
+                    .build(),
+            )
+            .set("seed", Tag::Long(-42))
+            .set("scale", Tag::Double(0.1))
+            .set("speed", Tag::Float(1.5))
+            .set("ids", Tag::ByteArray(vec![1, -2]))
+            .set("heights", Tag::ShortArray(vec![300, -7]))
+            .set("counts", Tag::IntArray(vec![]))
+            .set("coords", Tag::LongArray(vec![1 << 40]))
+            .set(
+                "items",
+                Tag::List(vec![
+                    MapTag::new().set("slot", Tag::Byte(0)).build(),
+                    MapTag::new().set("slot", Tag::Byte(1)).build(),
+                ]),
+            )
+            .set("empty", Tag::List(vec![]))
+            .set("with space", Tag::Int(3))
+            .build()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let tag = example();
+        assert_eq!(parse(&format(&tag, false)).unwrap(), tag);
+        assert_eq!(parse(&format(&tag, true)).unwrap(), tag);
+    }
+
+    #[test]
+    fn number_suffixes() {
+        assert_eq!(parse("1b").unwrap(), Tag::Byte(1));
+        assert_eq!(parse("-1s").unwrap(), Tag::Short(-1));
+        assert_eq!(parse("7").unwrap(), Tag::Int(7));
+        assert_eq!(parse("7L").unwrap(), Tag::Long(7));
+        assert_eq!(parse("0.5f").unwrap(), Tag::Float(0.5));
+        assert_eq!(parse("0.5").unwrap(), Tag::Double(0.5));
+        assert_eq!(parse("true").unwrap(), Tag::Byte(1));
+        assert_eq!(parse("abc").unwrap(), Tag::String("abc".to_string()));
+    }
+
+    #[test]
+    fn invalid_input_is_rejected() {
+        assert!(parse("[1, 2b]").is_err());
+        assert!(parse("[B; 300]").is_err());
+        assert!(parse("{a: 1, a: 2}").is_err());
+        assert!(parse("{a: 1").is_err());
+        assert!(parse("\"abc").is_err());
+        assert!(parse("1 2").is_err());
+    }
+}
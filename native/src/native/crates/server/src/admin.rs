@@ -0,0 +1,238 @@
+//! A password protected socket for running commands on the server, like from scripts.
+//!
+//! The protocol is line based. The first line a client sends is the password, which the server
+//! answers with `OK`, or with `ERROR` before closing the connection. After that every line is a
+//! command, which the server answers with the lines the command printed followed by a line with a
+//! single `.`. Messages sent to all players are streamed in between as lines starting with `* `,
+//! and the server log as lines starting with `# `.
+//!
+//! For example: `printf 'secret\nlist\n' | nc -q 1 127.0.0.1 25575`
+
+use std::{io, sync::Arc, time::Duration};
+
+use hexacraft::server::GameState;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{
+    Notify,
+    broadcast::{self, error::RecvError},
+    mpsc,
+};
+use tracing::warn;
+
+use crate::console;
+
+const MAX_LINE_LENGTH: u64 = 4096;
+
+/// Slows down guessing the password
+const WRONG_PASSWORD_DELAY: Duration = Duration::from_secs(1);
+
+pub struct AdminSocket {
+    listener: TcpListener,
+    password: String,
+    /// Lines of the server log, see `with_logs`
+    logs: broadcast::Sender<String>,
+}
+
+impl AdminSocket {
+    pub async fn bind(address: &str, port: u16, password: String) -> io::Result<Self> {
+        let listener = TcpListener::bind((address, port)).await?;
+        Ok(Self {
+            listener,
+            password,
+            logs: broadcast::channel(1).0,
+        })
+    }
+
+    /// Streams the log lines sent to this channel, like by `logging::broadcast_layer`
+    pub fn with_logs(mut self, logs: broadcast::Sender<String>) -> Self {
+        self.logs = logs;
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<std::net::SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub async fn run(self, state: Arc<GameState>, stop: Arc<Notify>) {
+        let password = Arc::new(self.password);
+        loop {
+            let Ok((stream, _)) = self.listener.accept().await else {
+                continue;
+            };
+            let state = state.clone();
+            let stop = stop.clone();
+            let password = password.clone();
+            let logs = self.logs.clone();
+            tokio::spawn(async move {
+                if let Err(err) = handle_connection(stream, &state, &stop, &password, &logs).await {
+                    warn!(%err, "Admin connection failed");
+                }
+            });
+        }
+    }
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    state: &GameState,
+    stop: &Notify,
+    password: &str,
+    logs: &broadcast::Sender<String>,
+) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let given = read_line(&mut reader).await?.unwrap_or_default();
+    if !constant_time_eq(given.as_bytes(), password.as_bytes()) {
+        tokio::time::sleep(WRONG_PASSWORD_DELAY).await;
+        writer.write_all(b"ERROR wrong password\n").await?;
+        return Ok(());
+    }
+    writer.write_all(b"OK\n").await?;
+
+    // lines are read in a separate task, since reading a line cannot be interrupted without
+    // losing what has been read so far
+    let (line_tx, mut lines) = mpsc::channel(16);
+    tokio::spawn(async move {
+        while let Ok(Some(line)) = read_line(&mut reader).await {
+            if line_tx.send(line).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    let mut output = state.follow_output();
+    let mut logs = logs.subscribe();
+    loop {
+        let text = tokio::select! {
+            line = lines.recv() => {
+                let Some(line) = line else {
+                    return Ok(());
+                };
+                let mut text = String::new();
+                for output in console::execute(state, &line, stop).await {
+                    text.push_str(&output);
+                    text.push('\n');
+                }
+                text.push_str(".\n");
+                text
+            }
+            line = output.recv() => match line {
+                Ok(line) => format!("* {line}\n"),
+                Err(RecvError::Lagged(missed)) => format!("* ({missed} lines were skipped)\n"),
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            line = logs.recv() => match line {
+                Ok(line) => format!("# {line}\n"),
+                Err(RecvError::Lagged(missed)) => format!("# ({missed} lines were skipped)\n"),
+                Err(RecvError::Closed) => return Ok(()),
+            }
+        };
+        writer.write_all(text.as_bytes()).await?;
+    }
+}
+
+/// Reads a line without the line ending, or None at the end of the stream
+async fn read_line(reader: &mut (impl AsyncBufRead + Unpin)) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    let len = reader.take(MAX_LINE_LENGTH).read_line(&mut line).await?;
+    if len == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with('\n') && len as u64 == MAX_LINE_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "line is too long",
+        ));
+    }
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// Compares without revealing how much of the password was right through the time it takes
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use hexacraft::server::{GameState, WorldInfo};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpStream;
+    use tokio::sync::{Notify, broadcast};
+
+    use super::AdminSocket;
+
+    #[tokio::test]
+    async fn commands_need_the_password() {
+        let state = Arc::new(GameState::create(false, WorldInfo::default()));
+        tokio::spawn({
+            let state = state.clone();
+            async move { state.run_ticks().await }
+        });
+        let stop = Arc::new(Notify::new());
+
+        let admin = AdminSocket::bind("127.0.0.1", 0, "secret".to_string())
+            .await
+            .unwrap();
+        let addr = admin.local_addr().unwrap();
+        tokio::spawn(admin.run(state, stop.clone()));
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"secret\nlist\nstop\n").await.unwrap();
+
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "OK");
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            "0 players online: "
+        );
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), ".");
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            "Stopping the server"
+        );
+        stop.notified().await;
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"guess\nstop\n").await.unwrap();
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            "ERROR wrong password"
+        );
+        assert_eq!(lines.next_line().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn logs_are_streamed_after_logging_in() {
+        let state = Arc::new(GameState::create(false, WorldInfo::default()));
+        let (logs, _) = broadcast::channel(16);
+
+        let admin = AdminSocket::bind("127.0.0.1", 0, "secret".to_string())
+            .await
+            .unwrap()
+            .with_logs(logs.clone());
+        let addr = admin.local_addr().unwrap();
+        tokio::spawn(admin.run(state, Arc::new(Notify::new())));
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"secret\nhelp\n").await.unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "OK");
+        lines.next_line().await.unwrap().unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), ".");
+
+        logs.send("INFO hexacraft_server: Server started".to_string())
+            .unwrap();
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            "# INFO hexacraft_server: Server started"
+        );
+    }
+}
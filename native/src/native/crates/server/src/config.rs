@@ -0,0 +1,347 @@
+use std::{fs, io, net::SocketAddr, num::NonZeroU32, path::Path, path::PathBuf};
+
+use hexacraft::logging::{LogConfig, LogFile, LogRotation};
+use hexacraft::server::{discovery, tick::DEFAULT_TICK_RATE, world_config::WorldConfig};
+
+use clap::Args;
+use serde::Deserialize;
+
+/// Settings that can be given both as flags and in the config file. Flags take precedence.
+#[derive(Args, Deserialize, Default)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Settings {
+    /// The world directory, which is created if it does not exist [default: world]
+    #[arg(long)]
+    pub world: Option<PathBuf>,
+    /// The name of new worlds [default: the name of the world directory]
+    #[arg(long)]
+    pub world_name: Option<String>,
+    /// The size exponent of new worlds, from 0 to 20. The world is 2^size chunks around.
+    /// [default: 7]
+    #[arg(long)]
+    pub world_size: Option<u8>,
+    /// The seed of new worlds [default: random]
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// [default: 0.1]
+    #[arg(long)]
+    pub block_gen_scale: Option<f64>,
+    /// [default: 0.02]
+    #[arg(long)]
+    pub height_map_gen_scale: Option<f64>,
+    /// [default: 0.01]
+    #[arg(long)]
+    pub block_density_gen_scale: Option<f64>,
+    /// [default: 0.002]
+    #[arg(long)]
+    pub biome_height_map_gen_scale: Option<f64>,
+    /// [default: 0.002]
+    #[arg(long)]
+    pub biome_height_variation_gen_scale: Option<f64>,
+    /// Whether the world settings above may replace the ones of an existing world. Otherwise
+    /// the server refuses to start if they differ. [default: false]
+    #[arg(long)]
+    pub change_world_settings: Option<bool>,
+    /// The address to listen on [default: 0.0.0.0]
+    #[arg(long)]
+    pub bind: Option<String>,
+    /// The port to listen on, 0 picks a free port [default: 1234]
+    #[arg(long)]
+    pub port: Option<u16>,
+    /// [default: 20]
+    #[arg(long)]
+    pub max_players: Option<usize>,
+    /// The message of the day, shown in server lists
+    #[arg(long)]
+    pub motd: Option<String>,
+    /// Ticks per second [default: 60]
+    #[arg(long)]
+    pub tick_rate: Option<u32>,
+    /// Whether players have to prove who they are when logging in [default: false]
+    #[arg(long)]
+    pub online: Option<bool>,
+    /// Where login tokens are verified, see `auth::verifier_from_config`. Needed in online mode.
+    #[arg(long)]
+    pub auth_service: Option<String>,
+    /// Whether clients that do not encrypt the connection are turned away [default: false]
+    #[arg(long)]
+    pub require_encryption: Option<bool>,
+    /// Whether the server announces itself to players on the local network, using the motd as its
+    /// name [default: false]
+    #[arg(long)]
+    pub announce: Option<bool>,
+    /// Where announcements are sent, like a broadcast address [default: 239.255.72.67:34254]
+    #[arg(long)]
+    pub announce_address: Option<String>,
+    /// The port of the admin socket, which is off unless a port is given
+    #[arg(long)]
+    pub admin_port: Option<u16>,
+    /// The address the admin socket listens on [default: 127.0.0.1]
+    #[arg(long)]
+    pub admin_bind: Option<String>,
+    /// The password of the admin socket. Prefer the config file, since flags are visible to
+    /// other users.
+    #[arg(long)]
+    pub admin_password: Option<String>,
+    /// The port to serve Prometheus metrics on at `/metrics`, which is off unless a port is given
+    #[arg(long)]
+    pub metrics_port: Option<u16>,
+    /// The address the metrics are served on [default: 127.0.0.1]
+    #[arg(long)]
+    pub metrics_bind: Option<String>,
+    /// Which logs to show, like `debug` or `warn,hexacraft_core::zmq=debug` [default: info]
+    #[arg(long)]
+    pub log: Option<String>,
+    /// A directory to also write logs to, in files that are rotated
+    #[arg(long)]
+    pub log_dir: Option<PathBuf>,
+    /// When to start a new log file: hourly, daily or never [default: daily]
+    #[arg(long)]
+    pub log_rotation: Option<String>,
+}
+
+impl Settings {
+    /// Reads settings from a toml file. A missing file is the same as an empty one.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(format!("failed to read {}: {err}", path.display())),
+        };
+        toml::from_str(&text).map_err(|err| format!("invalid {}: {err}", path.display()))
+    }
+
+    /// Uses the settings of `self` where they are given, and `other` otherwise
+    pub fn or(self, other: Settings) -> Settings {
+        Settings {
+            world: self.world.or(other.world),
+            world_name: self.world_name.or(other.world_name),
+            world_size: self.world_size.or(other.world_size),
+            seed: self.seed.or(other.seed),
+            block_gen_scale: self.block_gen_scale.or(other.block_gen_scale),
+            height_map_gen_scale: self.height_map_gen_scale.or(other.height_map_gen_scale),
+            block_density_gen_scale: self
+                .block_density_gen_scale
+                .or(other.block_density_gen_scale),
+            biome_height_map_gen_scale: self
+                .biome_height_map_gen_scale
+                .or(other.biome_height_map_gen_scale),
+            biome_height_variation_gen_scale: self
+                .biome_height_variation_gen_scale
+                .or(other.biome_height_variation_gen_scale),
+            change_world_settings: self.change_world_settings.or(other.change_world_settings),
+            bind: self.bind.or(other.bind),
+            port: self.port.or(other.port),
+            max_players: self.max_players.or(other.max_players),
+            motd: self.motd.or(other.motd),
+            tick_rate: self.tick_rate.or(other.tick_rate),
+            online: self.online.or(other.online),
+            auth_service: self.auth_service.or(other.auth_service),
+            require_encryption: self.require_encryption.or(other.require_encryption),
+            announce: self.announce.or(other.announce),
+            announce_address: self.announce_address.or(other.announce_address),
+            admin_port: self.admin_port.or(other.admin_port),
+            admin_bind: self.admin_bind.or(other.admin_bind),
+            admin_password: self.admin_password.or(other.admin_password),
+            metrics_port: self.metrics_port.or(other.metrics_port),
+            metrics_bind: self.metrics_bind.or(other.metrics_bind),
+            log: self.log.or(other.log),
+            log_dir: self.log_dir.or(other.log_dir),
+            log_rotation: self.log_rotation.or(other.log_rotation),
+        }
+    }
+}
+
+/// The settings of a server, with defaults filled in
+#[derive(Debug, PartialEq)]
+pub struct ServerConfig {
+    pub world: PathBuf,
+    /// The settings of the world if it is new
+    pub world_config: WorldConfig,
+    /// Whether `world_config` may change the settings of an existing world
+    pub change_world_settings: bool,
+    pub bind: String,
+    pub port: u16,
+    pub max_players: usize,
+    pub motd: Option<String>,
+    pub tick_rate: NonZeroU32,
+    pub online: bool,
+    pub auth_service: Option<String>,
+    pub require_encryption: bool,
+    /// Where the server is announced on the local network
+    pub announce: Option<SocketAddr>,
+    pub admin: Option<AdminConfig>,
+    /// The address and port to serve metrics on
+    pub metrics: Option<(String, u16)>,
+    pub log: String,
+    pub log_file: Option<LogFile>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AdminConfig {
+    pub bind: String,
+    pub port: u16,
+    pub password: String,
+}
+
+impl ServerConfig {
+    pub fn from_settings(settings: Settings) -> Result<Self, String> {
+        let admin = match (settings.admin_port, settings.admin_password) {
+            (None, _) => None,
+            (Some(_), None) => return Err("the admin socket needs an admin-password".to_string()),
+            (Some(_), Some(password)) if password.is_empty() => {
+                return Err("admin-password cannot be empty".to_string());
+            }
+            (Some(port), Some(password)) => Some(AdminConfig {
+                bind: settings
+                    .admin_bind
+                    .unwrap_or_else(|| "127.0.0.1".to_string()),
+                port,
+                password,
+            }),
+        };
+        let log_rotation = match settings.log_rotation {
+            Some(rotation) => rotation.parse::<LogRotation>()?,
+            None => LogRotation::Daily,
+        };
+        let announce_address = match settings.announce_address {
+            Some(address) => address
+                .parse::<SocketAddr>()
+                .map_err(|err| format!("invalid announce-address '{address}': {err}"))?,
+            None => discovery::default_address(),
+        };
+        let tick_rate = match settings.tick_rate {
+            Some(rate) => NonZeroU32::new(rate)
+                .filter(|rate| rate.get() <= 1000)
+                .ok_or("tick-rate has to be between 1 and 1000")?,
+            None => DEFAULT_TICK_RATE,
+        };
+        let config = ServerConfig {
+            world: settings.world.unwrap_or_else(|| PathBuf::from("world")),
+            world_config: WorldConfig {
+                name: settings.world_name,
+                size: settings.world_size,
+                seed: settings.seed,
+                block_gen_scale: settings.block_gen_scale,
+                height_map_gen_scale: settings.height_map_gen_scale,
+                block_density_gen_scale: settings.block_density_gen_scale,
+                biome_height_map_gen_scale: settings.biome_height_map_gen_scale,
+                biome_height_variation_gen_scale: settings.biome_height_variation_gen_scale,
+            },
+            change_world_settings: settings.change_world_settings.unwrap_or(false),
+            bind: settings.bind.unwrap_or_else(|| "0.0.0.0".to_string()),
+            port: settings.port.unwrap_or(1234),
+            max_players: settings.max_players.unwrap_or(20),
+            motd: settings.motd,
+            tick_rate,
+            online: settings.online.unwrap_or(false),
+            auth_service: settings.auth_service,
+            require_encryption: settings.require_encryption.unwrap_or(false),
+            announce: settings
+                .announce
+                .unwrap_or(false)
+                .then_some(announce_address),
+            admin,
+            metrics: settings.metrics_port.map(|port| {
+                let bind = settings
+                    .metrics_bind
+                    .unwrap_or_else(|| "127.0.0.1".to_string());
+                (bind, port)
+            }),
+            log: settings.log.unwrap_or_else(|| "info".to_string()),
+            log_file: settings
+                .log_dir
+                .map(|dir| LogFile::new(dir, "server").with_rotation(log_rotation)),
+        };
+
+        if config.max_players == 0 {
+            return Err("max-players has to be at least 1".to_string());
+        }
+        if config.online && config.auth_service.is_none() {
+            return Err("online mode needs an auth-service".to_string());
+        }
+        config.world_config.validate()?;
+        Ok(config)
+    }
+
+    pub fn logging(&self) -> LogConfig {
+        let config = LogConfig::new(&self.log);
+        match &self.log_file {
+            Some(file) => config.with_file(file.clone()),
+            None => config,
+        }
+    }
+
+    /// The ZMQ endpoint to bind to, like `tcp://0.0.0.0:1234`
+    pub fn endpoint(&self) -> String {
+        if self.bind.contains(':') {
+            format!("tcp://[{}]:{}", self.bind, self.port)
+        } else {
+            format!("tcp://{}:{}", self.bind, self.port)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{ServerConfig, Settings};
+
+    #[test]
+    fn flags_override_the_config_file() {
+        let file: Settings = toml::from_str(
+            r#"
+            world = "/srv/hexacraft/world"
+            port = 4000
+            tick-rate = 20
+            world-size = 10
+            block-gen-scale = 0.05
+            "#,
+        )
+        .unwrap();
+        let flags = Settings {
+            port: Some(5000),
+            ..Settings::default()
+        };
+
+        let config = ServerConfig::from_settings(flags.or(file)).unwrap();
+        assert_eq!(config.world, PathBuf::from("/srv/hexacraft/world"));
+        assert_eq!(config.port, 5000);
+        assert_eq!(config.tick_rate.get(), 20);
+        assert_eq!(config.max_players, 20);
+        assert_eq!(config.endpoint(), "tcp://0.0.0.0:5000");
+        assert_eq!(config.world_config.size, Some(10));
+        assert_eq!(config.world_config.block_gen_scale, Some(0.05));
+        assert_eq!(config.world_config.seed, None);
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        assert!(toml::from_str::<Settings>("prot = 4000").is_err());
+
+        let settings = Settings {
+            tick_rate: Some(0),
+            ..Settings::default()
+        };
+        assert!(ServerConfig::from_settings(settings).is_err());
+
+        let settings = Settings {
+            online: Some(true),
+            ..Settings::default()
+        };
+        assert!(ServerConfig::from_settings(settings).is_err());
+
+        let settings = Settings {
+            world_size: Some(21),
+            ..Settings::default()
+        };
+        assert!(ServerConfig::from_settings(settings).is_err());
+
+        let settings = Settings {
+            admin_port: Some(25575),
+            ..Settings::default()
+        };
+        assert!(ServerConfig::from_settings(settings).is_err());
+    }
+}
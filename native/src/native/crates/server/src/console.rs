@@ -0,0 +1,45 @@
+//! Running commands on the server as the server itself, from stdin or the admin socket.
+
+use std::sync::Arc;
+
+use hexacraft::server::GameState;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::{Notify, broadcast::error::RecvError};
+
+const HELP: &str = "Commands: chat <message>, list, kick <player>, status, tps, stop, help";
+
+/// Runs a line typed by an operator, and returns what it printed. `stop` is handled here, since
+/// only the server binary knows how to shut down.
+pub async fn execute(state: &GameState, line: &str, stop: &Notify) -> Vec<String> {
+    match line.trim() {
+        "" => Vec::new(),
+        "help" => vec![HELP.to_string()],
+        "stop" => {
+            stop.notify_one();
+            vec!["Stopping the server".to_string()]
+        }
+        line => state.run_command(line).await,
+    }
+}
+
+/// Reads commands from stdin and prints their output, together with everything sent to all
+/// players. Stops reading when stdin is closed, like when the server runs as a service.
+pub async fn run_stdin(state: Arc<GameState>, stop: Arc<Notify>) {
+    let mut output = state.follow_output();
+    tokio::spawn(async move {
+        loop {
+            match output.recv().await {
+                Ok(line) => println!("{line}"),
+                Err(RecvError::Lagged(missed)) => println!("({missed} lines were skipped)"),
+                Err(RecvError::Closed) => return,
+            }
+        }
+    });
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        for output in execute(&state, &line, &stop).await {
+            println!("{output}");
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::{path::PathBuf, process::ExitCode, sync::Arc, time::Duration};
+
+use clap::Parser;
+use hexacraft::logging::broadcast_layer;
+use hexacraft::server::{
+    DEFAULT_MOTD, GameServer, GameState, auth::verifier_from_config, discovery::Announcer, metrics,
+    world_config::open_world,
+};
+use hexacraft::zmq::{Compression, Keypair, ServerEncryption, ServerSocket};
+use tokio::net::TcpListener;
+use tokio::sync::{Notify, broadcast};
+use tracing::{info, warn};
+
+use crate::admin::AdminSocket;
+use crate::config::{ServerConfig, Settings};
+
+mod admin;
+mod config;
+mod console;
+
+/// How long players get to log out before the server stops anyway
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Followers of the server log that fall further behind than this miss some of it
+const LOG_BUFFER_SIZE: usize = 256;
+
+/// Runs a Hexacraft server without a game client.
+///
+/// Settings are read from the config file, and can be overridden by flags. Commands like `list` or
+/// `kick <player>` can be typed on stdin, or sent to the admin socket if it is enabled. The server
+/// shuts down gracefully on `stop`, SIGINT or SIGTERM, and right away on a second signal.
+#[derive(Parser)]
+#[command(name = "hexacraft-server")]
+struct Cli {
+    /// A toml file with the same settings as the flags, like `port = 1234`
+    #[arg(long, default_value = "server.toml")]
+    config: PathBuf,
+    #[command(flatten)]
+    settings: Settings,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), String> {
+    let settings = cli.settings.or(Settings::load(&cli.config)?);
+    let config = ServerConfig::from_settings(settings)?;
+    let (logs, _) = broadcast::channel(LOG_BUFFER_SIZE);
+    let _log_guard = config
+        .logging()
+        .init_with(Some(Box::new(broadcast_layer(logs.clone()))))?;
+
+    let world = open_world(
+        &config.world,
+        &config.world_config,
+        config.change_world_settings,
+    )
+    .map_err(|err| format!("{err}. Use --change-world-settings true to change them anyway."))?;
+    info!(
+        name = world.world_name,
+        size = world.world_size.0,
+        seed = world.gen_settings.seed,
+        "Opened the world in {}",
+        config.world.display()
+    );
+    let keypair = Keypair::load_or_create(&config.world)
+        .map_err(|err| format!("failed to load server key: {err}"))?;
+
+    let mut state = GameState::create(config.online, world)
+        .with_tick_rate(config.tick_rate)
+        .with_max_players(config.max_players);
+    if let Some(auth_service) = &config.auth_service {
+        let verifier = verifier_from_config(auth_service)
+            .map_err(|err| format!("failed to set up authentication: {err}"))?;
+        state = state.with_token_verifier(verifier);
+    }
+    if let Some(motd) = &config.motd {
+        state = state.with_motd(motd.clone());
+    }
+    let state = Arc::new(state);
+
+    let socket = ServerSocket::new()
+        .with_encryption(ServerEncryption {
+            keypair,
+            required: config.require_encryption,
+        })
+        .with_compression(Compression::default());
+    let server = GameServer::start_with_socket(socket, &config.endpoint(), state.clone())
+        .await
+        .map_err(|err| format!("failed to start server: {err}"))?;
+    let server = Arc::new(server);
+
+    tokio::spawn({
+        let state = state.clone();
+        async move { state.run_ticks().await }
+    });
+    tokio::spawn({
+        let server = server.clone();
+        async move { server.run_receiver().await }
+    });
+
+    if let Some(address) = config.announce {
+        let name = config.motd.as_deref().unwrap_or(DEFAULT_MOTD);
+        let announcer = Announcer::bind(name, address)
+            .map_err(|err| format!("failed to announce the server: {err}"))?;
+        info!("Announcing the server to {address}");
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.run_announcer(announcer).await }
+        });
+    }
+
+    let stop = Arc::new(Notify::new());
+    if let Some(admin) = config.admin {
+        let socket = AdminSocket::bind(&admin.bind, admin.port, admin.password)
+            .await
+            .map_err(|err| format!("failed to start admin socket: {err}"))?;
+        if let Ok(addr) = socket.local_addr() {
+            info!("Admin socket listening on {addr}");
+        }
+        tokio::spawn(socket.with_logs(logs).run(state.clone(), stop.clone()));
+    }
+    if let Some((bind, port)) = &config.metrics {
+        let listener = TcpListener::bind((bind.as_str(), *port))
+            .await
+            .map_err(|err| format!("failed to serve metrics: {err}"))?;
+        if let Ok(addr) = listener.local_addr() {
+            info!("Serving metrics on http://{addr}/metrics");
+        }
+        tokio::spawn(metrics::serve(listener, state.metrics()));
+    }
+    tokio::spawn(console::run_stdin(state.clone(), stop.clone()));
+
+    match server.local_endpoint() {
+        Some(endpoint) => info!("Server listening on {endpoint}"),
+        None => info!("Server started"),
+    }
+
+    tokio::select! {
+        _ = shutdown_signal() => {}
+        _ = stop.notified() => {}
+    }
+    info!("Shutting down, waiting for players to log out...");
+    tokio::select! {
+        res = tokio::time::timeout(SHUTDOWN_TIMEOUT, server.shutdown()) => {
+            if res.is_err() {
+                warn!("Shutdown timed out. The server will now be forced to shut down.");
+            }
+        }
+        _ = shutdown_signal() => {
+            warn!("Got a second signal. The server will now be forced to shut down.");
+        }
+    }
+    Ok(())
+}
+
+/// Waits for SIGINT (like Ctrl-C) or SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut terminate = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen for Ctrl-C");
+    }
+}
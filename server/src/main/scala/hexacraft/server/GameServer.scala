@@ -25,12 +25,34 @@ object GameServer {
       renderDistance: Double,
       maxChunksToLoadPerTick: Int = 4
   ): GameServer = {
-    val world = new ServerWorld(worldProvider, worldInfo, renderDistance, maxChunksToLoadPerTick)
-
     val tcpServer = TcpServer
       .start(port)
       .unwrapWith(m => new IllegalStateException(s"Could not start server: $m"))
 
+    createWith(isOnline, tcpServer, worldInfo, worldProvider, renderDistance, maxChunksToLoadPerTick)
+  }
+
+  /** Creates a server that only clients in this process can join, see `connectInProcess` */
+  def createInProcess(
+      isOnline: Boolean,
+      worldInfo: WorldInfo,
+      worldProvider: WorldProvider,
+      renderDistance: Double,
+      maxChunksToLoadPerTick: Int = 4
+  ): GameServer = {
+    val tcpServer = TcpServer.startInProcess()
+    createWith(isOnline, tcpServer, worldInfo, worldProvider, renderDistance, maxChunksToLoadPerTick)
+  }
+
+  private def createWith(
+      isOnline: Boolean,
+      tcpServer: TcpServer,
+      worldInfo: WorldInfo,
+      worldProvider: WorldProvider,
+      renderDistance: Double,
+      maxChunksToLoadPerTick: Int
+  ): GameServer = {
+    val world = new ServerWorld(worldProvider, worldInfo, renderDistance, maxChunksToLoadPerTick)
     new GameServer(isOnline, tcpServer, worldInfo, worldProvider, world)(using world.size)
   }
 }
@@ -65,6 +87,11 @@ class GameServer(
     worldProvider.saveWorldData(Nbt.encode(world.worldInfo))
   }
 
+  /** Connects a client in this process, and returns a handle to be used with `RustLib.InProcessClient`.
+    * Only servers created with `createInProcess` can be connected to like this.
+    */
+  def connectInProcess(clientId: Array[Byte]): Long = server.connectInProcess(clientId)
+
   def tick(): Unit = {
     try {
       val players = this.players.synchronized {
@@ -350,7 +377,7 @@ class GameServer(
     // TODO: call this function from tick to reduce race conditions
 
     packet match {
-      case Login(id, name) =>
+      case Login(id, name, _) =>
         if isShuttingDown then {
           return Some(
             Nbt.makeMap(
@@ -449,7 +476,7 @@ class GameServer(
     val PlayerData(player, _, playerCamera) = playerData
 
     packet match {
-      case Login(_, _) => None // already handled above
+      case Login(_, _, _) => None // already handled above
       case Logout =>
         logoutPlayer(playerData)
         players.remove(clientId)
@@ -500,6 +527,8 @@ class GameServer(
           .withField("messages", Nbt.ListTag(messages.map(Nbt.encode)))
 
         Some(response)
+      case Subscribe(_) | Unsubscribe =>
+        None // this server does not push updates, so the client keeps polling
       case GetWorldLoadingEvents(maxChunksToLoad) =>
         val prio = chunksLoadedPerPlayer.synchronized {
           chunksLoadedPerPlayer.getOrElseUpdate(player.id, ChunkLoadingPrioritizer(world.renderDistance))
@@ -566,10 +595,10 @@ class GameServer(
       case PlayerUpdatedInventory(inv) =>
         player.inventory = inv
         Some(Nbt.encode(inv))
-      case PlayerMovedMouse(dist) =>
+      case PlayerMovedMouse(dist, _) =>
         playerData.mouseMovement.add(dist)
         None
-      case PlayerPressedKeys(keys) =>
+      case PlayerPressedKeys(keys, _) =>
         playerData.pressedKeys = keys
         None
       case RunCommand(command, args) =>
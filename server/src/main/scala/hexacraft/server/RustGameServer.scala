@@ -6,11 +6,47 @@ import java.nio.file.Path
 
 object RustGameServer {
   def start(isOnline: Boolean, port: Int, path: Path): RustGameServer = {
-    val handle = RustLib.GameServer.start(isOnline, port, path.toAbsolutePath.toString)
+    start(isOnline, s"tcp://0.0.0.0:$port", path)
+  }
+
+  def start(
+      isOnline: Boolean,
+      endpoint: String,
+      path: Path,
+      requireEncryption: Boolean = false,
+      authService: Option[String] = None
+  ): RustGameServer = {
+    val handle = RustLib.GameServer.start(
+      isOnline,
+      endpoint,
+      path.toAbsolutePath.toString,
+      requireEncryption,
+      authService.orNull
+    )
     new RustGameServer(handle)
   }
+
+  /** Starts a server that only clients in this process can join, see `InProcessRustGameServer.connect` */
+  def startInProcess(isOnline: Boolean, path: Path, authService: Option[String] = None): InProcessRustGameServer = {
+    val handle = RustLib.GameServer.startInProcess(isOnline, path.toAbsolutePath.toString, authService.orNull)
+    new InProcessRustGameServer(handle)
+  }
 }
 
 class RustGameServer(handle: Long) {
+  /** The port the server is listening on, or -1 if it's not a tcp server */
+  def localPort: Int = RustLib.GameServer.localPort(handle)
+
+  /** The key clients can pin to make sure they are talking to this server */
+  def publicKey: Array[Byte] = RustLib.GameServer.publicKey(handle)
+
   def stop(): Unit = RustLib.GameServer.stop(handle)
 }
+
+class InProcessRustGameServer(handle: Long) {
+
+  /** Connects a client in this process, and returns a handle to be used with `RustLib.InProcessClient` */
+  def connect(clientId: Array[Byte]): Long = RustLib.GameServer.connectInProcess(handle, clientId)
+
+  def stop(): Unit = RustLib.GameServer.stopInProcess(handle)
+}
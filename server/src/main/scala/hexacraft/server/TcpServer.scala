@@ -13,22 +13,37 @@ object TcpServer {
     case InvalidPacket(message: String)
   }
 
-  def start(port: Int): Result[TcpServer, String] = {
+  /** @param port the port to listen on, or 0 to let the OS pick a free port */
+  def start(port: Int): Result[TcpServer, String] = start(s"tcp://0.0.0.0:$port")
+
+  /** @param endpoint for example "tcp://127.0.0.1:1234", "tcp://[::]:1234" or "ipc:///tmp/hexacraft.sock" */
+  def start(endpoint: String): Result[TcpServer, String] = {
     val socketHandle = RustLib.ServerSocket.create()
 
-    try {
-      RustLib.ServerSocket.bind(socketHandle, port)
-    } catch {
-      case e: RuntimeException =>
-        RustLib.ServerSocket.close(socketHandle)
-        return Err(s"Server could not be bound: ${e.getMessage}")
-    }
+    val boundPort =
+      try {
+        RustLib.ServerSocket.bind(socketHandle, endpoint)
+      } catch {
+        case e: RuntimeException =>
+          RustLib.ServerSocket.close(socketHandle)
+          return Err(s"Server could not be bound: ${e.getMessage}")
+      }
+
+    Ok(new TcpServer(Socket.Zmq(socketHandle), boundPort))
+  }
 
-    Ok(new TcpServer(socketHandle, port))
+  /** Starts a server that only clients in this process can connect to, using `connectInProcess` */
+  def startInProcess(): TcpServer = {
+    new TcpServer(Socket.InProcess(RustLib.InProcessServer.create()), -1)
+  }
+
+  private enum Socket {
+    case Zmq(handle: Long)
+    case InProcess(handle: Long)
   }
 }
 
-class TcpServer private (socketHandle: Long, val localPort: Int) {
+class TcpServer private (socket: TcpServer.Socket, val localPort: Int) {
   private var _running = true
 
   def running: Boolean = _running
@@ -45,13 +60,25 @@ class TcpServer private (socketHandle: Long, val localPort: Int) {
     }
   }
 
+  /** Connects a client in this process to the server, and returns a handle to be used with `RustLib.InProcessClient`.
+    * Only servers started with `startInProcess` can be connected to like this.
+    */
+  def connectInProcess(clientId: Array[Byte]): Long = socket match {
+    case TcpServer.Socket.InProcess(handle) => RustLib.InProcessServer.connect(handle, clientId)
+    case TcpServer.Socket.Zmq(_)            => throw new IllegalStateException("The server is not in-process")
+  }
+
   def receive(): Result[(Long, NetworkPacket), TcpServer.Error] = {
     if !_running then {
       throw new IllegalStateException("The server is not running")
     }
 
-    val identity = doReceive()
-    val bytes = doReceive()
+    val frames = doReceive()
+    if frames.length != 2 then {
+      return Err(TcpServer.Error.InvalidPacket(s"Expected 2 frames but got ${frames.length}"))
+    }
+    val identity = frames(0)
+    val bytes = frames(1)
 
     if identity.isEmpty then {
       return Err(TcpServer.Error.InvalidPacket("Received an empty identity frame"))
@@ -66,9 +93,12 @@ class TcpServer private (socketHandle: Long, val localPort: Int) {
     } yield (clientId, packet)
   }
 
-  private def doReceive(): Array[Byte] = {
+  private def doReceive(): Array[Array[Byte]] = {
     try {
-      RustLib.ServerSocket.receive(socketHandle)
+      socket match {
+        case TcpServer.Socket.Zmq(handle)       => RustLib.ServerSocket.receive(handle)
+        case TcpServer.Socket.InProcess(handle) => RustLib.InProcessServer.receive(handle)
+      }
     } catch {
       case e: RuntimeException =>
         throw new NetworkException(e.getMessage)
@@ -86,7 +116,12 @@ class TcpServer private (socketHandle: Long, val localPort: Int) {
 
   private def doSend(clientId: Long, data: Nbt): Unit = {
     try {
-      RustLib.ServerSocket.send(socketHandle, clientId.toString.getBytes(), data.toBinary())
+      socket match {
+        case TcpServer.Socket.Zmq(handle) =>
+          RustLib.ServerSocket.send(handle, clientId.toString.getBytes(), data.toBinary())
+        case TcpServer.Socket.InProcess(handle) =>
+          RustLib.InProcessServer.send(handle, clientId.toString.getBytes(), data.toBinary())
+      }
     } catch {
       case e: RuntimeException =>
         throw new NetworkException(e.getMessage)
@@ -99,7 +134,10 @@ class TcpServer private (socketHandle: Long, val localPort: Int) {
       throw new IllegalStateException("The server is not running")
     }
     _running = false
-    RustLib.ServerSocket.close(socketHandle)
+    socket match {
+      case TcpServer.Socket.Zmq(handle)       => RustLib.ServerSocket.close(handle)
+      case TcpServer.Socket.InProcess(handle) => RustLib.InProcessServer.close(handle)
+    }
   }
 
   def unload(): Unit = {
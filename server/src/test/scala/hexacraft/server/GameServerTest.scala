@@ -51,7 +51,7 @@ class GameServerTest extends FunSuite {
   class Session(val server: GameServer, val port: Int) {
     def connect(): SimpleSocket = {
       val clientId = (new Random().nextInt(1000000) + 1000000).toString
-      val socketHandle = RustLib.ClientSocket.create(clientId.getBytes())
+      val socketHandle = RustLib.ClientSocket.create(clientId.getBytes(), 10000, 0)
       RustLib.ClientSocket.connect(socketHandle, "localhost", port)
       SimpleSocket(socketHandle)
     }
@@ -131,6 +131,31 @@ class GameServerTest extends FunSuite {
     }
   }
 
+  test("client in the same process can login without going through the network") {
+    val worldProvider = FakeWorldProvider(9876)
+    val server = GameServer.createInProcess(false, worldProvider.worldInfo, worldProvider, 10)
+
+    try {
+      server.tick()
+
+      val clientHandle = server.connectInProcess("1234567".getBytes())
+      RustLib.InProcessClient.send(clientHandle, NetworkPacket.Login(UUID.randomUUID(), "The Dude").serialize())
+
+      var response = RustLib.InProcessClient.tryReceive(clientHandle)
+      while response == null do {
+        Thread.sleep(1)
+        response = RustLib.InProcessClient.tryReceive(clientHandle)
+      }
+      assertEquals(Nbt.fromBinary(response)._2.asMap.get, Nbt.makeMap("success" -> Nbt.ByteTag(true)))
+
+      RustLib.InProcessClient.send(clientHandle, NetworkPacket.Logout.serialize())
+      Thread.sleep(1) // this gives the GameServer enough time to remove the player, which prevents the sleep in unload
+      RustLib.InProcessClient.close(clientHandle)
+    } finally {
+      server.unload()
+    }
+  }
+
   test("first client gets server start message after login") {
     val seed = 9876
 